@@ -0,0 +1,265 @@
+use super::{bounds, Cache, CancellationToken, Error, Result};
+use std::ops::{Range, RangeBounds};
+
+fn check_block_size(block_size: u64) -> Result<()> {
+    if block_size == 0 {
+        Err(Error::InvalidArgument("block size must not be zero"))
+    } else {
+        Ok(())
+    }
+}
+
+/// The number of occurrences of each byte value in a run of bytes.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; 256],
+}
+
+impl Histogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        Histogram { counts: [0; 256] }
+    }
+
+    /// Computes the histogram of the passed range of the cache.
+    pub fn compute<C: Cache + ?Sized, R: RangeBounds<u64>>(cache: &C, range: R) -> Result<Self> {
+        let mut histogram = Histogram::new();
+        cache.traverse_chunks(range, |chunk| {
+            histogram.update(chunk);
+            Ok(())
+        })?;
+        Ok(histogram)
+    }
+
+    /// Splits the passed range of the cache into consecutive blocks of
+    /// `block_size` bytes, and calls `f` with the range and histogram of
+    /// each block in ascending order. The last block is shorter if the range
+    /// is not a multiple of the block size. Only the histogram of the current
+    /// block is kept in memory, however large the range. The traversal uses
+    /// `Cache::traverse_chunks_with`, so it can be cancelled with `token` and
+    /// reports its progress to `progress`, such as when it runs on a
+    /// background thread over a cache that is `Sync`. Returns
+    /// `Error::InvalidArgument` if `block_size` is zero.
+    pub fn for_blocks<C, R, P, F>(
+        cache: &C,
+        range: R,
+        block_size: u64,
+        token: &CancellationToken,
+        progress: P,
+        f: F,
+    ) -> Result<()>
+    where
+        C: Cache + ?Sized,
+        R: RangeBounds<u64>,
+        P: FnMut(u64, u64),
+        F: FnMut(Range<u64>, &Histogram) -> Result<()>,
+    {
+        check_block_size(block_size)?;
+        let mut f = f;
+        let range = bounds(&range, cache.len());
+        let mut histogram = Histogram::new();
+        let mut block_start = range.start;
+        let mut filled = 0;
+        cache.traverse_chunks_with(range, token, progress, |chunk| {
+            let mut chunk = chunk;
+            while !chunk.is_empty() {
+                let len = (block_size - filled).min(chunk.len() as u64) as usize;
+                histogram.update(&chunk[..len]);
+                chunk = &chunk[len..];
+                filled += len as u64;
+                if filled == block_size {
+                    f(block_start..block_start + filled, &histogram)?;
+                    histogram.clear();
+                    block_start += filled;
+                    filled = 0;
+                }
+            }
+            Ok(())
+        })?;
+        if filled > 0 {
+            f(block_start..block_start + filled, &histogram)?;
+        }
+        Ok(())
+    }
+
+    /// Counts the passed bytes.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.counts[*byte as usize] += 1;
+        }
+    }
+
+    /// Adds the counts of another histogram to this one.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+    }
+
+    /// Resets all counts to zero.
+    pub fn clear(&mut self) {
+        self.counts = [0; 256];
+    }
+
+    /// Returns the number of occurrences of the passed byte value.
+    pub fn count(&self, byte: u8) -> u64 {
+        self.counts[byte as usize]
+    }
+
+    /// Returns the number of occurrences of every byte value, indexed by
+    /// value.
+    pub fn counts(&self) -> &[u64; 256] {
+        &self.counts
+    }
+
+    /// Returns the total number of bytes counted.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the Shannon entropy of the counted bytes in bits per byte,
+    /// from 0 for a run of a single value to 8 for uniformly distributed
+    /// values. Compressed and encrypted data is typically close to 8. The
+    /// entropy of an empty histogram is 0.
+    pub fn entropy(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        let total = total as f64;
+        let sum: f64 = self
+            .counts
+            .iter()
+            .filter(|count| **count != 0)
+            .map(|count| {
+                let count = *count as f64;
+                count * count.log2()
+            })
+            .sum();
+        (total.log2() - sum / total).max(0.0)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl std::fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_map()
+            .entries(
+                self.counts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, count)| **count != 0),
+            )
+            .finish()
+    }
+}
+
+/// The Shannon entropy of each fixed-size block of a range of a cache.
+///
+/// Entropy is stored as one `f32` per block, in bits per byte, so the series
+/// for a large file stays small enough to be kept for display, such as an
+/// overview of which regions are compressed or encrypted. See
+/// `Histogram::entropy`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntropySeries {
+    range: Range<u64>,
+    block_size: u64,
+    values: Vec<f32>,
+}
+
+impl EntropySeries {
+    /// Computes the entropy of each block of `block_size` bytes in the
+    /// passed range of the cache. The last block is shorter if the range is
+    /// not a multiple of the block size. See `Histogram::for_blocks` for how
+    /// the range is traversed, cancelled and reports its progress. Returns
+    /// `Error::InvalidArgument` if `block_size` is zero.
+    pub fn compute<C, R, P>(
+        cache: &C,
+        range: R,
+        block_size: u64,
+        token: &CancellationToken,
+        progress: P,
+    ) -> Result<Self>
+    where
+        C: Cache + ?Sized,
+        R: RangeBounds<u64>,
+        P: FnMut(u64, u64),
+    {
+        check_block_size(block_size)?;
+        let range = bounds(&range, cache.len());
+        let blocks = (range.end - range.start).div_ceil(block_size);
+        let mut values = Vec::with_capacity(blocks as usize);
+        Histogram::for_blocks(
+            cache,
+            range.clone(),
+            block_size,
+            token,
+            progress,
+            |_, histogram| {
+                values.push(histogram.entropy() as f32);
+                Ok(())
+            },
+        )?;
+        Ok(EntropySeries {
+            range,
+            block_size,
+            values,
+        })
+    }
+
+    /// Returns the range of the cache covered by the series.
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Returns the size of each block in bytes.
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Returns the number of blocks in the series.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if the series has no blocks, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the entropy of each block in bits per byte, in ascending
+    /// order of offset.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Returns the entropy of the block at the passed index, or `None` if
+    /// the index is out of bounds.
+    pub fn get(&self, idx: usize) -> Option<f32> {
+        self.values.get(idx).cloned()
+    }
+
+    /// Returns the range of the cache covered by the block at the passed
+    /// index, or `None` if the index is out of bounds.
+    pub fn block_range(&self, idx: usize) -> Option<Range<u64>> {
+        if idx >= self.values.len() {
+            return None;
+        }
+        let start = self.range.start + idx as u64 * self.block_size;
+        Some(start..(start + self.block_size).min(self.range.end))
+    }
+
+    /// Returns the index of the block containing the passed offset, or
+    /// `None` if the offset is outside the series.
+    pub fn block_at(&self, offset: u64) -> Option<usize> {
+        if offset < self.range.start || offset >= self.range.end {
+            return None;
+        }
+        Some(((offset - self.range.start) / self.block_size) as usize)
+    }
+}
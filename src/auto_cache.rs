@@ -1,8 +1,11 @@
-use super::{Cache, FullCache, SwapCache};
+use super::fingerprint::FingerprintFn;
+use super::{Cache, Fingerprint, Fingerprinted, FullCache, Operation, ResultExt, SwapCache};
 
 use std::io::{Read, Seek, SeekFrom};
 use std::ops::RangeBounds;
 
+const NAME: &str = "AutoCache";
+
 /// A cache that internally uses `FullCache` or `SwapCache` depending on source size.
 ///
 /// `AutoCache` attempts to use the most appropriate cache type based on
@@ -55,24 +58,48 @@ impl<T: Read + Seek> AutoCache<T> {
     /// Creates a new `AutoCache` containing the passed source and with the passed maximum
     /// memory usage.
     pub fn new(source: T, mem_max: usize) -> Result<Self> {
+        Self::with_fingerprinter(source, mem_max, None)
+    }
+
+    fn with_fingerprinter(
+        source: T,
+        mem_max: usize,
+        fingerprinter: Option<FingerprintFn<T>>,
+    ) -> Result<Self> {
         if mem_max == 0 {
             return Err(Error::new_zero_cache("AutoCache configured with no memory"));
         }
         let mut source = source;
-        let len = source.seek(SeekFrom::End(0))?;
+        let len = source
+            .seek(SeekFrom::End(0))
+            .context(NAME, Operation::Seek, None)?;
         if len > mem_max as u64 {
             let page_sz = sqrt(mem_max);
             let mut frame_count = page_sz + 1;
             if page_sz * frame_count > mem_max {
                 frame_count = page_sz;
             }
-            Ok(Swap(SwapCache::new(source, page_sz, frame_count)?))
+            Ok(Swap(SwapCache::with_fingerprinter(
+                source,
+                page_sz,
+                frame_count,
+                fingerprinter,
+            )?))
         } else {
-            Ok(Full(FullCache::new(source)?))
+            Ok(Full(FullCache::with_fingerprinter(source, fingerprinter)?))
         }
     }
 }
 
+impl<T: Fingerprinted> AutoCache<T> {
+    /// Creates a new `AutoCache` like `AutoCache::new`, and records the full
+    /// fingerprint of the source, as `FullCache::new_fingerprinted` and
+    /// `SwapCache::new_fingerprinted` do.
+    pub fn new_fingerprinted(source: T, mem_max: usize) -> Result<Self> {
+        Self::with_fingerprinter(source, mem_max, Some(T::fingerprint))
+    }
+}
+
 impl<T: Read + Seek> Cache for AutoCache<T> {
     type Source = T;
 
@@ -83,6 +110,13 @@ impl<T: Read + Seek> Cache for AutoCache<T> {
         }
     }
 
+    fn replace_source(&mut self, source: T) -> Result<T> {
+        match self {
+            Full(ref mut full) => full.replace_source(source),
+            Swap(ref mut swap) => swap.replace_source(source),
+        }
+    }
+
     fn len(&self) -> u64 {
         match self {
             Full(ref full) => full.len(),
@@ -97,6 +131,23 @@ impl<T: Read + Seek> Cache for AutoCache<T> {
         }
     }
 
+    fn fingerprint(&self) -> Fingerprint {
+        match self {
+            Full(ref full) => full.fingerprint(),
+            Swap(ref swap) => swap.fingerprint(),
+        }
+    }
+
+    fn validate(&mut self) -> Result<bool>
+    where
+        T: Fingerprinted,
+    {
+        match self {
+            Full(ref mut full) => full.validate(),
+            Swap(ref mut swap) => swap.validate(),
+        }
+    }
+
     fn traverse_chunks<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
         &self,
         range: R,
@@ -107,4 +158,15 @@ impl<T: Read + Seek> Cache for AutoCache<T> {
             Swap(ref swap) => swap.traverse_chunks(range, f),
         }
     }
+
+    fn traverse_chunks_rev<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        match self {
+            Full(ref full) => full.traverse_chunks_rev(range, f),
+            Swap(ref swap) => swap.traverse_chunks_rev(range, f),
+        }
+    }
 }
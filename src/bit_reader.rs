@@ -0,0 +1,186 @@
+use super::{Cache, Error, Result};
+
+/// The number of bytes buffered from the cache at once.
+const BUF_LEN: u64 = 4096;
+
+/// The order in which the bits of each byte are read by a `BitReader`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BitOrder {
+    /// Bits are read from the most significant bit of each byte down, and
+    /// the first bit read is the most significant bit of the value, as in
+    /// most bitfield protocols, JPEG and MPEG.
+    MsbFirst,
+
+    /// Bits are read from the least significant bit of each byte up, and the
+    /// first bit read is the least significant bit of the value, as in
+    /// DEFLATE and other LZ77 based formats.
+    LsbFirst,
+}
+
+/// A reader of values at bit granularity over a cache.
+///
+/// `BitReader` reads values of up to 64 bits starting at any bit of the
+/// cache, in either `BitOrder`. Its position is a bit offset from the start
+/// of the cache, where bit `n` is in byte `n / 8`. Bytes are copied from the
+/// chunks of `Cache::traverse_chunks` into a buffer 4 KiB at a time, so
+/// sequential reads rarely reach the cache. If a read fails, the
+/// position of the reader is left unchanged.
+pub struct BitReader<'a, C: Cache + ?Sized> {
+    cache: &'a C,
+    order: BitOrder,
+    pos: u64,
+    buf: Vec<u8>,
+    buf_start: u64,
+}
+
+impl<'a, C: Cache + ?Sized> BitReader<'a, C> {
+    /// Creates a new `BitReader` over the passed cache that reads bits in
+    /// the passed order, with its position initialized to bit zero.
+    pub fn new(cache: &'a C, order: BitOrder) -> Self {
+        BitReader {
+            cache,
+            order,
+            pos: 0,
+            buf: Vec::new(),
+            buf_start: 0,
+        }
+    }
+
+    /// Returns the cache being read.
+    pub fn cache(&self) -> &'a C {
+        self.cache
+    }
+
+    /// Returns the bit order of the reader.
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Sets the bit order of the reader for subsequent reads.
+    pub fn set_order(&mut self, order: BitOrder) {
+        self.order = order;
+    }
+
+    /// Returns the position of the reader as a bit offset from the start of
+    /// the cache.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Moves the reader to the passed bit offset. The offset may be beyond
+    /// the end of the cache, in which case reads fail.
+    pub fn seek(&mut self, bit_offset: u64) {
+        self.pos = bit_offset;
+    }
+
+    /// Advances the reader by the passed number of bits.
+    pub fn skip(&mut self, bits: u64) {
+        self.pos = self.pos.saturating_add(bits);
+    }
+
+    /// Advances the reader to the start of the next byte, unless it is
+    /// already at the start of a byte.
+    pub fn align_to_byte(&mut self) {
+        self.pos = self.pos.saturating_add(7) & !7;
+    }
+
+    /// Returns true if the reader is at the start of a byte, false
+    /// otherwise.
+    pub fn is_byte_aligned(&self) -> bool {
+        self.pos & 7 == 0
+    }
+
+    /// Returns the number of bits between the reader and the end of the
+    /// cache.
+    pub fn remaining(&self) -> u64 {
+        self.cache.len().saturating_mul(8).saturating_sub(self.pos)
+    }
+
+    /// Reads `bits` bits, and advances past them. The first bit read is the
+    /// most or least significant bit of the value, according to the bit
+    /// order. Reading zero bits returns zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is greater than 64.
+    pub fn read_bits(&mut self, bits: u32) -> Result<u64> {
+        let value = self.peek_bits(bits)?;
+        self.pos += bits as u64;
+        Ok(value)
+    }
+
+    /// Reads `bits` bits without advancing past them. See
+    /// `BitReader::read_bits`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is greater than 64.
+    pub fn peek_bits(&mut self, bits: u32) -> Result<u64> {
+        assert!(bits <= 64, "cannot read more than 64 bits at once");
+        if (bits as u64) > self.remaining() {
+            let len = self.cache.len();
+            return Err(Error::OutOfRange {
+                offset: len.max(self.pos / 8),
+                len,
+            });
+        }
+        let mut value = 0u64;
+        let mut pos = self.pos;
+        let mut got = 0;
+        while got < bits {
+            let byte = self.byte(pos / 8)?;
+            let bit = (pos & 7) as u32;
+            let take = (8 - bit).min(bits - got);
+            let mask = (1u64 << take) - 1;
+            value = match self.order {
+                BitOrder::MsbFirst => (value << take) | ((byte as u64 >> (8 - bit - take)) & mask),
+                BitOrder::LsbFirst => value | (((byte as u64 >> bit) & mask) << got),
+            };
+            got += take;
+            pos += take as u64;
+        }
+        Ok(value)
+    }
+
+    /// Reads a single bit, and advances past it.
+    pub fn read_bit(&mut self) -> Result<bool> {
+        self.read_bits(1).map(|bit| bit != 0)
+    }
+
+    /// Reads `bits` bits as a two's complement signed value, and advances
+    /// past them. See `BitReader::read_bits`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is greater than 64.
+    pub fn read_signed_bits(&mut self, bits: u32) -> Result<i64> {
+        let value = self.read_bits(bits)?;
+        if bits == 0 || bits == 64 {
+            return Ok(value as i64);
+        }
+        let shift = 64 - bits;
+        Ok(((value << shift) as i64) >> shift)
+    }
+
+    /// Returns the byte at the passed offset, refilling the buffer from the
+    /// cache if needed.
+    fn byte(&mut self, offset: u64) -> Result<u8> {
+        if offset < self.buf_start || offset >= self.buf_start + self.buf.len() as u64 {
+            let buf = &mut self.buf;
+            buf.clear();
+            self.cache
+                .traverse_chunks(offset..offset + BUF_LEN, |chunk| {
+                    buf.extend_from_slice(chunk);
+                    Ok(())
+                })?;
+            self.buf_start = offset;
+        }
+        self.buf
+            .get((offset - self.buf_start) as usize)
+            .cloned()
+            .ok_or(Error::OutOfRange {
+                offset,
+                len: self.cache.len(),
+            })
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag that can be used to cancel a long running traversal.
+///
+/// `CancellationToken` is cheap to clone, and all clones share the same
+/// flag, so one clone can be handed to the code performing a traversal with
+/// `Cache::traverse_chunks_with`, while another is kept by a user interface
+/// or another thread to request cancellation. Once cancelled, a token stays
+/// cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new `CancellationToken` that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation of every traversal using this token or any of
+    /// its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if cancellation has been requested, false otherwise.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
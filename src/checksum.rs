@@ -0,0 +1,563 @@
+use std::convert::TryFrom;
+
+/// A checksum or hash algorithm supported by `Hasher` and `Cache::digest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// The CRC-32 checksum used by zlib, PNG and Ethernet.
+    Crc32,
+
+    /// The Adler-32 checksum used by zlib.
+    Adler32,
+
+    /// The MD5 message digest.
+    Md5,
+
+    /// The SHA-1 message digest.
+    Sha1,
+
+    /// The SHA-256 message digest.
+    Sha256,
+
+    /// The 64 bit xxHash, XXH64, with a seed of zero.
+    XxHash64,
+}
+
+impl Algorithm {
+    /// Returns the length of the algorithm's output in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            Algorithm::Crc32 | Algorithm::Adler32 => 4,
+            Algorithm::Md5 => 16,
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::XxHash64 => 8,
+        }
+    }
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Algorithm::Crc32 => "CRC-32",
+            Algorithm::Adler32 => "Adler-32",
+            Algorithm::Md5 => "MD5",
+            Algorithm::Sha1 => "SHA-1",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::XxHash64 => "XXH64",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The output of a checksum or hash algorithm.
+///
+/// The bytes of a checksum are in the order the algorithm conventionally
+/// presents them, so checksums that are integers, such as CRC-32, are
+/// big-endian. A checksum is displayed as lowercase hexadecimal, matching the
+/// output of common command line tools.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    algorithm: Algorithm,
+    bytes: Vec<u8>,
+}
+
+impl Checksum {
+    /// Returns the algorithm that produced the checksum.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Returns the bytes of the checksum.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for byte in self.bytes.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes a checksum incrementally.
+///
+/// `Hasher` is the streaming state behind `Cache::digest`, and can be fed
+/// directly from the chunks of `Cache::traverse_chunks` or
+/// `Cache::traverse_chunks_with` when a checksum needs to be combined with
+/// other processing, cancelled, or report progress.
+#[derive(Debug, Clone)]
+pub struct Hasher {
+    state: State,
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Crc32(u32),
+    Adler32(u32, u32),
+    Md5(Blocks, [u32; 4]),
+    Sha1(Blocks, [u32; 5]),
+    Sha256(Blocks, [u32; 8]),
+    XxHash64(XxHash64),
+}
+
+impl Hasher {
+    /// Creates a new `Hasher` for the passed algorithm.
+    pub fn new(algorithm: Algorithm) -> Self {
+        let state = match algorithm {
+            Algorithm::Crc32 => State::Crc32(!0),
+            Algorithm::Adler32 => State::Adler32(1, 0),
+            Algorithm::Md5 => State::Md5(Blocks::new(), MD5_INIT),
+            Algorithm::Sha1 => State::Sha1(Blocks::new(), SHA1_INIT),
+            Algorithm::Sha256 => State::Sha256(Blocks::new(), SHA256_INIT),
+            Algorithm::XxHash64 => State::XxHash64(XxHash64::new()),
+        };
+        Hasher { state }
+    }
+
+    /// Returns the algorithm of the hasher.
+    pub fn algorithm(&self) -> Algorithm {
+        match self.state {
+            State::Crc32(_) => Algorithm::Crc32,
+            State::Adler32(..) => Algorithm::Adler32,
+            State::Md5(..) => Algorithm::Md5,
+            State::Sha1(..) => Algorithm::Sha1,
+            State::Sha256(..) => Algorithm::Sha256,
+            State::XxHash64(_) => Algorithm::XxHash64,
+        }
+    }
+
+    /// Feeds bytes to the hasher.
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self.state {
+            State::Crc32(ref mut crc) => *crc = crc32_update(*crc, bytes),
+            State::Adler32(ref mut a, ref mut b) => adler32_update(a, b, bytes),
+            State::Md5(ref mut blocks, ref mut state) => {
+                blocks.update(bytes, |block| md5_compress(state, block))
+            }
+            State::Sha1(ref mut blocks, ref mut state) => {
+                blocks.update(bytes, |block| sha1_compress(state, block))
+            }
+            State::Sha256(ref mut blocks, ref mut state) => {
+                blocks.update(bytes, |block| sha256_compress(state, block))
+            }
+            State::XxHash64(ref mut xxh) => xxh.update(bytes),
+        }
+    }
+
+    /// Consumes the hasher and returns the checksum of all bytes fed to it.
+    pub fn finish(self) -> Checksum {
+        let algorithm = self.algorithm();
+        let bytes = match self.state {
+            State::Crc32(crc) => (!crc).to_be_bytes().to_vec(),
+            State::Adler32(a, b) => ((b << 16) | a).to_be_bytes().to_vec(),
+            State::Md5(mut blocks, mut state) => {
+                blocks.finish(false, |block| md5_compress(&mut state, block));
+                state.iter().flat_map(|word| word.to_le_bytes()).collect()
+            }
+            State::Sha1(mut blocks, mut state) => {
+                blocks.finish(true, |block| sha1_compress(&mut state, block));
+                state.iter().flat_map(|word| word.to_be_bytes()).collect()
+            }
+            State::Sha256(mut blocks, mut state) => {
+                blocks.finish(true, |block| sha256_compress(&mut state, block));
+                state.iter().flat_map(|word| word.to_be_bytes()).collect()
+            }
+            State::XxHash64(xxh) => xxh.finish().to_be_bytes().to_vec(),
+        };
+        Checksum { algorithm, bytes }
+    }
+}
+
+/// The lookup table for the reflected CRC-32 polynomial.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = crc;
+    for byte in bytes {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Computes the CRC-32 checksum of the passed bytes.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+const ADLER32_MOD: u32 = 65521;
+
+/// The largest number of bytes that can be summed before the Adler-32 sums
+/// must be reduced to avoid overflowing.
+const ADLER32_NMAX: usize = 5552;
+
+fn adler32_update(a: &mut u32, b: &mut u32, bytes: &[u8]) {
+    for run in bytes.chunks(ADLER32_NMAX) {
+        for byte in run {
+            *a += *byte as u32;
+            *b += *a;
+        }
+        *a %= ADLER32_MOD;
+        *b %= ADLER32_MOD;
+    }
+}
+
+/// Splits the input of a Merkle-Damgard hash, such as MD5 or SHA-256, into
+/// 64 byte blocks. Whole blocks are compressed directly from the input, and
+/// only the bytes of a partial block are buffered.
+#[derive(Clone)]
+struct Blocks {
+    buf: [u8; 64],
+    used: usize,
+    total: u64,
+}
+
+impl std::fmt::Debug for Blocks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Blocks")
+            .field("total", &self.total)
+            .finish()
+    }
+}
+
+impl Blocks {
+    fn new() -> Self {
+        Blocks {
+            buf: [0; 64],
+            used: 0,
+            total: 0,
+        }
+    }
+
+    fn update<F: FnMut(&[u8; 64])>(&mut self, bytes: &[u8], compress: F) {
+        let mut compress = compress;
+        let mut bytes = bytes;
+        self.total = self.total.wrapping_add(bytes.len() as u64);
+        if self.used > 0 {
+            let len = bytes.len().min(64 - self.used);
+            self.buf[self.used..self.used + len].copy_from_slice(&bytes[..len]);
+            self.used += len;
+            bytes = &bytes[len..];
+            if self.used < 64 {
+                return;
+            }
+            compress(&self.buf);
+            self.used = 0;
+        }
+        let mut blocks = bytes.chunks_exact(64);
+        for block in &mut blocks {
+            compress(<&[u8; 64]>::try_from(block).unwrap());
+        }
+        let rest = blocks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.used = rest.len();
+    }
+
+    /// Pads the final block with the length of the input in bits, in the
+    /// passed byte order, and compresses it.
+    fn finish<F: FnMut(&[u8; 64])>(&mut self, big_endian: bool, compress: F) {
+        let mut compress = compress;
+        let bits = self.total.wrapping_mul(8);
+        self.buf[self.used] = 0x80;
+        for byte in &mut self.buf[self.used + 1..] {
+            *byte = 0;
+        }
+        if self.used >= 56 {
+            compress(&self.buf);
+            self.buf = [0; 64];
+        }
+        self.buf[56..].copy_from_slice(&if big_endian {
+            bits.to_be_bytes()
+        } else {
+            bits.to_le_bytes()
+        });
+        compress(&self.buf);
+    }
+}
+
+fn words_le(block: &[u8; 64]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(<[u8; 4]>::try_from(bytes).unwrap());
+    }
+    words
+}
+
+fn words_be(block: &[u8; 64]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(<[u8; 4]>::try_from(bytes).unwrap());
+    }
+    words
+}
+
+const MD5_INIT: [u32; 4] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476];
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+const MD5_K: [u32; 64] = [
+    0xD76AA478, 0xE8C7B756, 0x242070DB, 0xC1BDCEEE, 0xF57C0FAF, 0x4787C62A, 0xA8304613, 0xFD469501,
+    0x698098D8, 0x8B44F7AF, 0xFFFF5BB1, 0x895CD7BE, 0x6B901122, 0xFD987193, 0xA679438E, 0x49B40821,
+    0xF61E2562, 0xC040B340, 0x265E5A51, 0xE9B6C7AA, 0xD62F105D, 0x02441453, 0xD8A1E681, 0xE7D3FBC8,
+    0x21E1CDE6, 0xC33707D6, 0xF4D50D87, 0x455A14ED, 0xA9E3E905, 0xFCEFA3F8, 0x676F02D9, 0x8D2A4C8A,
+    0xFFFA3942, 0x8771F681, 0x6D9D6122, 0xFDE5380C, 0xA4BEEA44, 0x4BDECFA9, 0xF6BB4B60, 0xBEBFBC70,
+    0x289B7EC6, 0xEAA127FA, 0xD4EF3085, 0x04881D05, 0xD9D4D039, 0xE6DB99E5, 0x1FA27CF8, 0xC4AC5665,
+    0xF4292244, 0x432AFF97, 0xAB9423A7, 0xFC93A039, 0x655B59C3, 0x8F0CCC92, 0xFFEFF47D, 0x85845DD1,
+    0x6FA87E4F, 0xFE2CE6E0, 0xA3014314, 0x4E0811A1, 0xF7537E82, 0xBD3AF235, 0x2AD7D2BB, 0xEB86D391,
+];
+
+fn md5_compress(state: &mut [u32; 4], block: &[u8; 64]) {
+    let m = words_le(block);
+    let [mut a, mut b, mut c, mut d] = *state;
+    for i in 0..64 {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let f = f.wrapping_add(a).wrapping_add(MD5_K[i]).wrapping_add(m[g]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[(i / 16) * 4 + i % 4]));
+    }
+    for (word, value) in state.iter_mut().zip([a, b, c, d].iter()) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+const SHA1_INIT: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8; 64]) {
+    let mut w = [0u32; 80];
+    w[..16].copy_from_slice(&words_be(block));
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, word) in w.iter().enumerate() {
+        let (f, k) = match i / 20 {
+            0 => ((b & c) | (!b & d), 0x5A827999),
+            1 => (b ^ c ^ d, 0x6ED9EBA1),
+            2 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e].iter()) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+const SHA256_INIT: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428A2F98, 0x71374491, 0xB5C0FBCF, 0xE9B5DBA5, 0x3956C25B, 0x59F111F1, 0x923F82A4, 0xAB1C5ED5,
+    0xD807AA98, 0x12835B01, 0x243185BE, 0x550C7DC3, 0x72BE5D74, 0x80DEB1FE, 0x9BDC06A7, 0xC19BF174,
+    0xE49B69C1, 0xEFBE4786, 0x0FC19DC6, 0x240CA1CC, 0x2DE92C6F, 0x4A7484AA, 0x5CB0A9DC, 0x76F988DA,
+    0x983E5152, 0xA831C66D, 0xB00327C8, 0xBF597FC7, 0xC6E00BF3, 0xD5A79147, 0x06CA6351, 0x14292967,
+    0x27B70A85, 0x2E1B2138, 0x4D2C6DFC, 0x53380D13, 0x650A7354, 0x766A0ABB, 0x81C2C92E, 0x92722C85,
+    0xA2BFE8A1, 0xA81A664B, 0xC24B8B70, 0xC76C51A3, 0xD192E819, 0xD6990624, 0xF40E3585, 0x106AA070,
+    0x19A4C116, 0x1E376C08, 0x2748774C, 0x34B0BCB5, 0x391C0CB3, 0x4ED8AA4A, 0x5B9CCA4F, 0x682E6FF3,
+    0x748F82EE, 0x78A5636F, 0x84C87814, 0x8CC70208, 0x90BEFFFA, 0xA4506CEB, 0xBEF9A3F7, 0xC67178F2,
+];
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    w[..16].copy_from_slice(&words_be(block));
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (word, k) in w.iter().zip(SHA256_K.iter()) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let temp1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(*k)
+            .wrapping_add(*word);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+const XXH_PRIME_1: u64 = 0x9E3779B185EBCA87;
+const XXH_PRIME_2: u64 = 0xC2B2AE3D27D4EB4F;
+const XXH_PRIME_3: u64 = 0x165667B19E3779F9;
+const XXH_PRIME_4: u64 = 0x85EBCA77C2B2AE63;
+const XXH_PRIME_5: u64 = 0x27D4EB2F165667C5;
+
+fn xxh_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME_1)
+}
+
+fn xxh_merge(hash: u64, acc: u64) -> u64 {
+    (hash ^ xxh_round(0, acc))
+        .wrapping_mul(XXH_PRIME_1)
+        .wrapping_add(XXH_PRIME_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(<[u8; 8]>::try_from(&bytes[..8]).unwrap())
+}
+
+/// The streaming state of XXH64, which consumes its input in 32 byte
+/// stripes.
+#[derive(Clone)]
+struct XxHash64 {
+    acc: [u64; 4],
+    buf: [u8; 32],
+    used: usize,
+    total: u64,
+}
+
+impl std::fmt::Debug for XxHash64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("XxHash64")
+            .field("total", &self.total)
+            .finish()
+    }
+}
+
+impl XxHash64 {
+    fn new() -> Self {
+        XxHash64 {
+            acc: [
+                XXH_PRIME_1.wrapping_add(XXH_PRIME_2),
+                XXH_PRIME_2,
+                0,
+                0u64.wrapping_sub(XXH_PRIME_1),
+            ],
+            buf: [0; 32],
+            used: 0,
+            total: 0,
+        }
+    }
+
+    fn stripe(acc: &mut [u64; 4], stripe: &[u8]) {
+        for (lane, acc) in acc.iter_mut().enumerate() {
+            *acc = xxh_round(*acc, read_u64(&stripe[lane * 8..]));
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        let mut bytes = bytes;
+        self.total = self.total.wrapping_add(bytes.len() as u64);
+        if self.used > 0 {
+            let len = bytes.len().min(32 - self.used);
+            self.buf[self.used..self.used + len].copy_from_slice(&bytes[..len]);
+            self.used += len;
+            bytes = &bytes[len..];
+            if self.used < 32 {
+                return;
+            }
+            XxHash64::stripe(&mut self.acc, &self.buf);
+            self.used = 0;
+        }
+        let mut stripes = bytes.chunks_exact(32);
+        for stripe in &mut stripes {
+            XxHash64::stripe(&mut self.acc, stripe);
+        }
+        let rest = stripes.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.used = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let [v1, v2, v3, v4] = self.acc;
+        let mut hash = if self.total >= 32 {
+            let hash = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            self.acc
+                .iter()
+                .fold(hash, |hash, acc| xxh_merge(hash, *acc))
+        } else {
+            XXH_PRIME_5
+        };
+        hash = hash.wrapping_add(self.total);
+        let mut rest = &self.buf[..self.used];
+        while rest.len() >= 8 {
+            hash ^= xxh_round(0, read_u64(rest));
+            hash = hash
+                .rotate_left(27)
+                .wrapping_mul(XXH_PRIME_1)
+                .wrapping_add(XXH_PRIME_4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let word = u32::from_le_bytes(<[u8; 4]>::try_from(&rest[..4]).unwrap()) as u64;
+            hash ^= word.wrapping_mul(XXH_PRIME_1);
+            hash = hash
+                .rotate_left(23)
+                .wrapping_mul(XXH_PRIME_2)
+                .wrapping_add(XXH_PRIME_3);
+            rest = &rest[4..];
+        }
+        for byte in rest {
+            hash ^= (*byte as u64).wrapping_mul(XXH_PRIME_5);
+            hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME_1);
+        }
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(XXH_PRIME_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(XXH_PRIME_3);
+        hash ^ (hash >> 32)
+    }
+}
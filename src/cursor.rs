@@ -0,0 +1,359 @@
+use super::{Cache, Error, Result};
+
+/// The longest LEB128 encoding of a 64 bit value.
+const LEB128_MAX_LEN: usize = 10;
+
+macro_rules! typed_at {
+    ($($name:ident, $t:ty, $from:ident, $order:expr;)*) => {
+        $(
+            #[doc = concat!(
+                "Reads a `", stringify!($t), "` in ", $order,
+                " byte order at the passed offset."
+            )]
+            fn $name(&self, offset: u64) -> Result<$t> {
+                self.array_at(offset).map(<$t>::$from)
+            }
+        )*
+    };
+}
+
+macro_rules! typed_read {
+    ($($name:ident, $at:ident, $t:ty, $order:expr;)*) => {
+        $(
+            #[doc = concat!(
+                "Reads a `", stringify!($t), "` in ", $order,
+                " byte order, and advances past it."
+            )]
+            pub fn $name(&mut self) -> Result<$t> {
+                let value = self.cache.$at(self.pos)?;
+                self.pos += std::mem::size_of::<$t>() as u64;
+                Ok(value)
+            }
+        )*
+    };
+}
+
+/// Reads typed values at arbitrary offsets of a cache.
+///
+/// `ReadAt` is implemented for every `Cache`, so its methods are available
+/// on any cache once the trait is in scope. Values are copied straight out
+/// of the chunks of the cache, without an intermediate buffer, and values
+/// that straddle chunk or page boundaries are read whole. Reading past the
+/// end of the cache returns `Error::OutOfRange` with the offset of the first
+/// missing byte.
+pub trait ReadAt: Cache {
+    /// Reads `N` bytes at the passed offset.
+    fn array_at<const N: usize>(&self, offset: u64) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        let read = self.read(offset, &mut buf)?;
+        if read < N {
+            return Err(Error::OutOfRange {
+                offset: offset + read as u64,
+                len: self.len(),
+            });
+        }
+        Ok(buf)
+    }
+
+    /// Reads `len` bytes at the passed offset. The length is checked before
+    /// any memory is allocated, so it may come from untrusted data.
+    fn bytes_at(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        if offset.saturating_add(len as u64) > self.len() {
+            return Err(Error::OutOfRange {
+                offset: offset.max(self.len()),
+                len: self.len(),
+            });
+        }
+        let mut buf = vec![0; len];
+        let read = self.read(offset, &mut buf)?;
+        if read < len {
+            return Err(Error::OutOfRange {
+                offset: offset + read as u64,
+                len: self.len(),
+            });
+        }
+        Ok(buf)
+    }
+
+    /// Reads a `u8` at the passed offset.
+    fn u8_at(&self, offset: u64) -> Result<u8> {
+        self.array_at::<1>(offset).map(|bytes| bytes[0])
+    }
+
+    /// Reads an `i8` at the passed offset.
+    fn i8_at(&self, offset: u64) -> Result<i8> {
+        self.array_at::<1>(offset).map(|bytes| bytes[0] as i8)
+    }
+
+    typed_at! {
+        u16_le_at, u16, from_le_bytes, "little-endian";
+        u16_be_at, u16, from_be_bytes, "big-endian";
+        u32_le_at, u32, from_le_bytes, "little-endian";
+        u32_be_at, u32, from_be_bytes, "big-endian";
+        u64_le_at, u64, from_le_bytes, "little-endian";
+        u64_be_at, u64, from_be_bytes, "big-endian";
+        i16_le_at, i16, from_le_bytes, "little-endian";
+        i16_be_at, i16, from_be_bytes, "big-endian";
+        i32_le_at, i32, from_le_bytes, "little-endian";
+        i32_be_at, i32, from_be_bytes, "big-endian";
+        i64_le_at, i64, from_le_bytes, "little-endian";
+        i64_be_at, i64, from_be_bytes, "big-endian";
+        f32_le_at, f32, from_le_bytes, "little-endian";
+        f32_be_at, f32, from_be_bytes, "big-endian";
+        f64_le_at, f64, from_le_bytes, "little-endian";
+        f64_be_at, f64, from_be_bytes, "big-endian";
+    }
+
+    /// Reads an unsigned LEB128 varint at the passed offset. Returns the
+    /// value and the length of its encoding in bytes. Returns
+    /// `Error::Malformed` if the value does not fit in 64 bits.
+    fn uleb128_at(&self, offset: u64) -> Result<(u64, usize)> {
+        let mut buf = [0; LEB128_MAX_LEN];
+        let read = self.read(offset, &mut buf)?;
+        let mut value = 0;
+        for (idx, byte) in buf[..read].iter().enumerate() {
+            if idx == LEB128_MAX_LEN - 1 && *byte > 1 {
+                return Err(Error::Malformed {
+                    offset,
+                    msg: "LEB128 value overflows 64 bits",
+                });
+            }
+            value |= ((byte & 0x7F) as u64) << (7 * idx);
+            if byte & 0x80 == 0 {
+                return Ok((value, idx + 1));
+            }
+        }
+        leb128_end(self, offset, read)
+    }
+
+    /// Reads a signed LEB128 varint at the passed offset. Returns the value
+    /// and the length of its encoding in bytes. Returns `Error::Malformed`
+    /// if the value does not fit in 64 bits.
+    fn sleb128_at(&self, offset: u64) -> Result<(i64, usize)> {
+        let mut buf = [0; LEB128_MAX_LEN];
+        let read = self.read(offset, &mut buf)?;
+        let mut value = 0;
+        for (idx, byte) in buf[..read].iter().enumerate() {
+            if idx == LEB128_MAX_LEN - 1 && *byte != 0 && *byte != 0x7F {
+                return Err(Error::Malformed {
+                    offset,
+                    msg: "LEB128 value overflows 64 bits",
+                });
+            }
+            let shift = 7 * idx as u32;
+            value |= ((byte & 0x7F) as i64) << shift;
+            if byte & 0x80 == 0 {
+                if shift + 7 < 64 && byte & 0x40 != 0 {
+                    value |= -1 << (shift + 7);
+                }
+                return Ok((value, idx + 1));
+            }
+        }
+        leb128_end(self, offset, read)
+    }
+
+    /// Reads a NUL-terminated string at the passed offset. Returns the bytes
+    /// before the terminator, which is not included. Returns
+    /// `Error::OutOfRange` if the cache ends before a terminator.
+    ///
+    /// The whole string is read into memory, so `cstr_at_bounded` should be
+    /// used on untrusted data.
+    fn cstr_at(&self, offset: u64) -> Result<Vec<u8>> {
+        self.cstr_at_bounded(offset, usize::MAX)
+    }
+
+    /// Reads a NUL-terminated string of at most `max_len` bytes before the
+    /// terminator at the passed offset, like `cstr_at`. Returns
+    /// `Error::Malformed` if no terminator follows within `max_len` bytes,
+    /// and `Error::OutOfRange` if the cache ends first.
+    fn cstr_at_bounded(&self, offset: u64, max_len: usize) -> Result<Vec<u8>> {
+        let len = cstr_len(self, offset, max_len)?;
+        self.bytes_at(offset, len)
+    }
+}
+
+impl<C: Cache + ?Sized> ReadAt for C {}
+
+/// Returns the length of the NUL-terminated string of at most `max_len`
+/// bytes at `offset`, not counting the terminator, without reading it into
+/// memory. See `ReadAt::cstr_at_bounded`.
+pub(crate) fn cstr_len<C: Cache + ?Sized>(cache: &C, offset: u64, max_len: usize) -> Result<usize> {
+    let limit = offset.saturating_add(max_len as u64).saturating_add(1);
+    match cache.find(&[0], offset..limit.min(cache.len()))? {
+        Some(end) => Ok((end - offset) as usize),
+        None if limit > cache.len() => Err(Error::OutOfRange {
+            offset: offset.max(cache.len()),
+            len: cache.len(),
+        }),
+        None => Err(Error::Malformed {
+            offset,
+            msg: "string is not terminated within the length limit",
+        }),
+    }
+}
+
+/// Returns the error for a LEB128 varint that does not end within the
+/// `read` bytes available at `offset`.
+fn leb128_end<C: Cache + ?Sized, T>(cache: &C, offset: u64, read: usize) -> Result<T> {
+    if read < LEB128_MAX_LEN {
+        Err(Error::OutOfRange {
+            offset: offset + read as u64,
+            len: cache.len(),
+        })
+    } else {
+        Err(Error::Malformed {
+            offset,
+            msg: "LEB128 value is longer than 10 bytes",
+        })
+    }
+}
+
+/// A cursor that reads typed values from a cache in sequence.
+///
+/// Where `CacheReader` provides raw `std::io::Read` over a cache it owns,
+/// `CacheCursor` borrows a cache and reads integers and floats in either
+/// byte order, LEB128 varints, NUL-terminated strings and fixed-size arrays,
+/// advancing past each value read. Any number of cursors can read the same
+/// cache at once. See `ReadAt` for reading at arbitrary offsets instead. If
+/// a read fails, the position of the cursor is left unchanged.
+pub struct CacheCursor<'a, C: Cache + ?Sized> {
+    cache: &'a C,
+    pos: u64,
+}
+
+impl<'a, C: Cache + ?Sized> Clone for CacheCursor<'a, C> {
+    fn clone(&self) -> Self {
+        CacheCursor {
+            cache: self.cache,
+            pos: self.pos,
+        }
+    }
+}
+
+impl<'a, C: Cache + ?Sized> CacheCursor<'a, C> {
+    /// Creates a new `CacheCursor` over the passed cache, with its position
+    /// initialized to byte zero.
+    pub fn new(cache: &'a C) -> Self {
+        CacheCursor { cache, pos: 0 }
+    }
+
+    /// Creates a new `CacheCursor` over the passed cache, with its position
+    /// initialized to the passed offset.
+    pub fn at(cache: &'a C, offset: u64) -> Self {
+        CacheCursor { cache, pos: offset }
+    }
+
+    /// Returns the cache being read.
+    pub fn cache(&self) -> &'a C {
+        self.cache
+    }
+
+    /// Returns the position of the cursor as a byte offset from the
+    /// beginning of the cache.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Moves the cursor to the passed offset. The offset may be beyond the
+    /// end of the cache, in which case reads fail.
+    pub fn set_position(&mut self, offset: u64) {
+        self.pos = offset;
+    }
+
+    /// Advances the cursor by the passed number of bytes.
+    pub fn skip(&mut self, len: u64) {
+        self.pos = self.pos.saturating_add(len);
+    }
+
+    /// Returns the number of bytes between the cursor and the end of the
+    /// cache.
+    pub fn remaining(&self) -> u64 {
+        self.cache.len().saturating_sub(self.pos)
+    }
+
+    /// Returns true if the cursor is at or beyond the end of the cache,
+    /// false otherwise.
+    pub fn is_at_end(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Reads `N` bytes, and advances past them.
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.cache.array_at(self.pos)?;
+        self.pos += N as u64;
+        Ok(bytes)
+    }
+
+    /// Reads `len` bytes, and advances past them.
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let bytes = self.cache.bytes_at(self.pos, len)?;
+        self.pos += len as u64;
+        Ok(bytes)
+    }
+
+    /// Reads a `u8`, and advances past it.
+    pub fn read_u8(&mut self) -> Result<u8> {
+        let value = self.cache.u8_at(self.pos)?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    /// Reads an `i8`, and advances past it.
+    pub fn read_i8(&mut self) -> Result<i8> {
+        let value = self.cache.i8_at(self.pos)?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    typed_read! {
+        read_u16_le, u16_le_at, u16, "little-endian";
+        read_u16_be, u16_be_at, u16, "big-endian";
+        read_u32_le, u32_le_at, u32, "little-endian";
+        read_u32_be, u32_be_at, u32, "big-endian";
+        read_u64_le, u64_le_at, u64, "little-endian";
+        read_u64_be, u64_be_at, u64, "big-endian";
+        read_i16_le, i16_le_at, i16, "little-endian";
+        read_i16_be, i16_be_at, i16, "big-endian";
+        read_i32_le, i32_le_at, i32, "little-endian";
+        read_i32_be, i32_be_at, i32, "big-endian";
+        read_i64_le, i64_le_at, i64, "little-endian";
+        read_i64_be, i64_be_at, i64, "big-endian";
+        read_f32_le, f32_le_at, f32, "little-endian";
+        read_f32_be, f32_be_at, f32, "big-endian";
+        read_f64_le, f64_le_at, f64, "little-endian";
+        read_f64_be, f64_be_at, f64, "big-endian";
+    }
+
+    /// Reads an unsigned LEB128 varint, and advances past it. See
+    /// `ReadAt::uleb128_at`.
+    pub fn read_uleb128(&mut self) -> Result<u64> {
+        let (value, len) = self.cache.uleb128_at(self.pos)?;
+        self.pos += len as u64;
+        Ok(value)
+    }
+
+    /// Reads a signed LEB128 varint, and advances past it. See
+    /// `ReadAt::sleb128_at`.
+    pub fn read_sleb128(&mut self) -> Result<i64> {
+        let (value, len) = self.cache.sleb128_at(self.pos)?;
+        self.pos += len as u64;
+        Ok(value)
+    }
+
+    /// Reads a NUL-terminated string, and advances past it and its
+    /// terminator. See `ReadAt::cstr_at`.
+    pub fn read_cstr(&mut self) -> Result<Vec<u8>> {
+        let bytes = self.cache.cstr_at(self.pos)?;
+        self.pos += bytes.len() as u64 + 1;
+        Ok(bytes)
+    }
+
+    /// Reads a NUL-terminated string of at most `max_len` bytes before the
+    /// terminator, and advances past it and its terminator. See
+    /// `ReadAt::cstr_at_bounded`.
+    pub fn read_cstr_bounded(&mut self, max_len: usize) -> Result<Vec<u8>> {
+        let bytes = self.cache.cstr_at_bounded(self.pos, max_len)?;
+        self.pos += bytes.len() as u64 + 1;
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,438 @@
+use super::{bounds, Cache, Encoding, Result};
+use std::collections::VecDeque;
+use std::ops::{Range, RangeBounds};
+
+/// The number of bytes decoded at once by `DecodeChars`, so that the range
+/// is decoded lazily.
+const STEP: u64 = 64 * 1024;
+
+/// The characters of Windows-1252 from 0x80 to 0x9F. The bytes it leaves
+/// undefined are `None`.
+const WINDOWS_1252: [Option<char>; 32] = [
+    Some('\u{20AC}'),
+    None,
+    Some('\u{201A}'),
+    Some('\u{0192}'),
+    Some('\u{201E}'),
+    Some('\u{2026}'),
+    Some('\u{2020}'),
+    Some('\u{2021}'),
+    Some('\u{02C6}'),
+    Some('\u{2030}'),
+    Some('\u{0160}'),
+    Some('\u{2039}'),
+    Some('\u{0152}'),
+    None,
+    Some('\u{017D}'),
+    None,
+    None,
+    Some('\u{2018}'),
+    Some('\u{2019}'),
+    Some('\u{201C}'),
+    Some('\u{201D}'),
+    Some('\u{2022}'),
+    Some('\u{2013}'),
+    Some('\u{2014}'),
+    Some('\u{02DC}'),
+    Some('\u{2122}'),
+    Some('\u{0161}'),
+    Some('\u{203A}'),
+    Some('\u{0153}'),
+    None,
+    Some('\u{017E}'),
+    Some('\u{0178}'),
+];
+
+/// A piece of a range of a cache decoded by `decode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextChunk<'a> {
+    /// Decoded text.
+    Text(&'a str),
+
+    /// A sequence of bytes that is not valid in the encoding, such as a
+    /// UTF-8 sequence that is cut short or a lone UTF-16 surrogate.
+    Malformed(&'a [u8]),
+}
+
+/// A character of a range of a cache decoded by `DecodeChars`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecodedChar {
+    /// A decoded character.
+    Char(char),
+
+    /// A sequence of bytes of the passed length that is not valid in the
+    /// encoding.
+    Malformed(usize),
+}
+
+/// Returns the number of bytes that encode the passed character in the
+/// passed encoding.
+fn encoded_len(encoding: Encoding, c: char) -> u64 {
+    match encoding {
+        Encoding::Utf8 => c.len_utf8() as u64,
+        Encoding::Utf16Le | Encoding::Utf16Be => 2 * c.len_utf16() as u64,
+        _ => 1,
+    }
+}
+
+/// Decodes a series of chunks, carrying incomplete sequences from one chunk
+/// to the next.
+struct Decoder {
+    encoding: Encoding,
+    pos: u64,
+    partial: [u8; 4],
+    partial_len: usize,
+    text: String,
+    text_start: u64,
+}
+
+impl Decoder {
+    fn new(encoding: Encoding, pos: u64) -> Self {
+        Decoder {
+            encoding,
+            pos,
+            partial: [0; 4],
+            partial_len: 0,
+            text: String::new(),
+            text_start: pos,
+        }
+    }
+
+    /// Returns the offset of the first byte of the incomplete sequence.
+    fn partial_start(&self) -> u64 {
+        self.pos - self.partial_len as u64
+    }
+
+    /// Decodes a chunk that starts where the previous chunk ended.
+    fn feed<F>(&mut self, chunk: &[u8], f: &mut F) -> Result<()>
+    where
+        F: FnMut(u64, TextChunk) -> Result<()>,
+    {
+        match self.encoding {
+            Encoding::Utf8 => self.feed_utf8(chunk, f),
+            Encoding::Utf16Le | Encoding::Utf16Be => self.feed_bytes(chunk, f),
+            _ if chunk.is_ascii() => {
+                // ASCII is the same in every other supported encoding.
+                if !chunk.is_empty() {
+                    // The chunk was checked to be ASCII, so this cannot fail.
+                    f(
+                        self.pos,
+                        TextChunk::Text(std::str::from_utf8(chunk).unwrap()),
+                    )?;
+                }
+                self.pos += chunk.len() as u64;
+                Ok(())
+            }
+            _ => self.feed_bytes(chunk, f),
+        }
+    }
+
+    fn feed_utf8<F>(&mut self, chunk: &[u8], f: &mut F) -> Result<()>
+    where
+        F: FnMut(u64, TextChunk) -> Result<()>,
+    {
+        let mut rest = chunk;
+        if self.partial_len > 0 {
+            // Complete the sequence carried from the previous chunk.
+            let start = self.partial_start();
+            let plen = self.partial_len;
+            let take = rest.len().min(4 - plen);
+            let mut joint = [0; 4];
+            joint[..plen].copy_from_slice(&self.partial[..plen]);
+            joint[plen..plen + take].copy_from_slice(&rest[..take]);
+            let joint = &joint[..plen + take];
+            let valid = match std::str::from_utf8(joint) {
+                Ok(text) => text.len(),
+                Err(e) => e.valid_up_to(),
+            };
+            let len = if valid > 0 {
+                // The bytes were validated by `from_utf8` above.
+                f(
+                    start,
+                    TextChunk::Text(std::str::from_utf8(&joint[..valid]).unwrap()),
+                )?;
+                valid
+            } else {
+                match std::str::from_utf8(joint).unwrap_err().error_len() {
+                    Some(len) => {
+                        f(start, TextChunk::Malformed(&joint[..len]))?;
+                        len
+                    }
+                    None => {
+                        self.partial[plen..plen + take].copy_from_slice(&rest[..take]);
+                        self.partial_len += take;
+                        self.pos += take as u64;
+                        return Ok(());
+                    }
+                }
+            };
+            // A carried sequence is a valid prefix of a character, so the
+            // character or the error always ends within the bytes taken
+            // from this chunk.
+            let used = len - plen;
+            self.partial_len = 0;
+            self.pos += used as u64;
+            rest = &rest[used..];
+        }
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    f(self.pos, TextChunk::Text(text))?;
+                    self.pos += rest.len() as u64;
+                    return Ok(());
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    if valid > 0 {
+                        // The bytes were validated by `from_utf8` above.
+                        let text = std::str::from_utf8(&rest[..valid]).unwrap();
+                        f(self.pos, TextChunk::Text(text))?;
+                        self.pos += valid as u64;
+                    }
+                    match e.error_len() {
+                        Some(len) => {
+                            f(self.pos, TextChunk::Malformed(&rest[valid..valid + len]))?;
+                            self.pos += len as u64;
+                            rest = &rest[valid + len..];
+                        }
+                        None => {
+                            let tail = &rest[valid..];
+                            self.partial[..tail.len()].copy_from_slice(tail);
+                            self.partial_len = tail.len();
+                            self.pos += tail.len() as u64;
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn feed_bytes<F>(&mut self, chunk: &[u8], f: &mut F) -> Result<()>
+    where
+        F: FnMut(u64, TextChunk) -> Result<()>,
+    {
+        for byte in chunk {
+            let offset = self.pos;
+            self.pos += 1;
+            match self.encoding {
+                Encoding::Utf16Le | Encoding::Utf16Be => {
+                    self.partial[self.partial_len] = *byte;
+                    self.partial_len += 1;
+                    self.feed_utf16(f)?;
+                }
+                Encoding::Latin1 => self.push(offset, *byte as char),
+                Encoding::Windows1252 => match byte {
+                    0x80..=0x9F => match WINDOWS_1252[*byte as usize - 0x80] {
+                        Some(c) => self.push(offset, c),
+                        None => self.malformed(offset, &[*byte], f)?,
+                    },
+                    _ => self.push(offset, *byte as char),
+                },
+                _ if byte.is_ascii() => self.push(offset, *byte as char),
+                _ => self.malformed(offset, &[*byte], f)?,
+            }
+        }
+        self.flush(f)
+    }
+
+    /// Decodes the complete code units of the incomplete sequence, if any.
+    fn feed_utf16<F>(&mut self, f: &mut F) -> Result<()>
+    where
+        F: FnMut(u64, TextChunk) -> Result<()>,
+    {
+        if self.partial_len & 1 == 1 {
+            return Ok(());
+        }
+        let big_endian = self.encoding == Encoding::Utf16Be;
+        let unit = |bytes: &[u8]| {
+            if big_endian {
+                u16::from_be_bytes([bytes[0], bytes[1]])
+            } else {
+                u16::from_le_bytes([bytes[0], bytes[1]])
+            }
+        };
+        let start = self.partial_start();
+        let high = unit(&self.partial[..2]);
+        if self.partial_len == 2 {
+            match high {
+                0xD800..=0xDBFF => return Ok(()),
+                0xDC00..=0xDFFF => {
+                    let bytes = [self.partial[0], self.partial[1]];
+                    self.partial_len = 0;
+                    return self.malformed(start, &bytes, f);
+                }
+                _ => {
+                    self.partial_len = 0;
+                    // Units outside the surrogate range are always characters.
+                    self.push(start, std::char::from_u32(high as u32).unwrap());
+                    return Ok(());
+                }
+            }
+        }
+        let low = unit(&self.partial[2..4]);
+        if (0xDC00..0xE000).contains(&low) {
+            let code = 0x10000 + (((high as u32) - 0xD800) << 10) + (low as u32 - 0xDC00);
+            self.partial_len = 0;
+            // Surrogate pairs always encode a valid character.
+            self.push(start, std::char::from_u32(code).unwrap());
+            return Ok(());
+        }
+        // The high surrogate is not followed by a low one, so it is
+        // reported alone and the second unit is decoded afresh.
+        let bytes = [self.partial[0], self.partial[1]];
+        self.partial.copy_within(2..4, 0);
+        self.partial_len = 2;
+        self.malformed(start, &bytes, f)?;
+        self.feed_utf16(f)
+    }
+
+    fn push(&mut self, offset: u64, c: char) {
+        if self.text.is_empty() {
+            self.text_start = offset;
+        }
+        self.text.push(c);
+    }
+
+    fn malformed<F>(&mut self, offset: u64, bytes: &[u8], f: &mut F) -> Result<()>
+    where
+        F: FnMut(u64, TextChunk) -> Result<()>,
+    {
+        self.flush(f)?;
+        f(offset, TextChunk::Malformed(bytes))
+    }
+
+    fn flush<F>(&mut self, f: &mut F) -> Result<()>
+    where
+        F: FnMut(u64, TextChunk) -> Result<()>,
+    {
+        if !self.text.is_empty() {
+            f(self.text_start, TextChunk::Text(&self.text))?;
+            self.text.clear();
+        }
+        Ok(())
+    }
+
+    /// Reports any incomplete sequence at the end of the range as
+    /// malformed.
+    fn finish<F>(&mut self, f: &mut F) -> Result<()>
+    where
+        F: FnMut(u64, TextChunk) -> Result<()>,
+    {
+        self.flush(f)?;
+        if self.partial_len > 0 {
+            let partial = self.partial;
+            let len = self.partial_len;
+            self.partial_len = 0;
+            f(self.pos - len as u64, TextChunk::Malformed(&partial[..len]))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the passed range of the cache as text in the passed encoding,
+/// and calls `f` with the offset and contents of each piece of text and
+/// each malformed sequence, in ascending order of offset.
+///
+/// The range is traversed with `Cache::traverse_chunks`, and sequences
+/// that straddle chunk or page boundaries are decoded whole. Text is passed
+/// in pieces no larger than a chunk, and UTF-8 text, as well as ASCII text
+/// in any encoding but UTF-16, is passed straight from the chunks of the
+/// cache without copying. An incomplete sequence at the end of the range is
+/// reported as malformed. Bytes from 0x80 in ASCII, and the five bytes that
+/// Windows-1252 leaves undefined, are malformed. Returning an error from `f`
+/// ends the traversal.
+pub fn decode<C, R, F>(cache: &C, range: R, encoding: Encoding, f: F) -> Result<()>
+where
+    C: Cache + ?Sized,
+    R: RangeBounds<u64>,
+    F: FnMut(u64, TextChunk) -> Result<()>,
+{
+    let mut f = f;
+    let range = bounds(&range, cache.len());
+    let mut decoder = Decoder::new(encoding, range.start);
+    cache.traverse_chunks(range, |chunk| decoder.feed(chunk, &mut f))?;
+    decoder.finish(&mut f)
+}
+
+/// An iterator over the characters of a range of a cache decoded as text.
+///
+/// Each item is the offset of the first byte of a character or malformed
+/// sequence, and the character or the length of the sequence. See `decode`
+/// for the handling of chunk boundaries and malformed bytes. The range is
+/// decoded lazily, 64 KiB at a time.
+///
+/// If an error occurs, it is returned and the iteration ends.
+pub struct DecodeChars<'a, C: Cache + ?Sized> {
+    cache: &'a C,
+    range: Range<u64>,
+    decoder: Decoder,
+    pending: VecDeque<(u64, DecodedChar)>,
+    done: bool,
+}
+
+impl<'a, C: Cache + ?Sized> DecodeChars<'a, C> {
+    /// Creates an iterator over the characters of the passed range of the
+    /// cache, decoded in the passed encoding.
+    pub fn new<R: RangeBounds<u64>>(cache: &'a C, range: R, encoding: Encoding) -> Self {
+        let range = bounds(&range, cache.len());
+        DecodeChars {
+            cache,
+            decoder: Decoder::new(encoding, range.start),
+            range,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Decodes the next step of the range.
+    fn step(&mut self) -> Result<()> {
+        let encoding = self.decoder.encoding;
+        let pending = &mut self.pending;
+        let mut f = |offset: u64, chunk: TextChunk| {
+            match chunk {
+                TextChunk::Text(text) => {
+                    let mut offset = offset;
+                    for c in text.chars() {
+                        pending.push_back((offset, DecodedChar::Char(c)));
+                        offset += encoded_len(encoding, c);
+                    }
+                }
+                TextChunk::Malformed(bytes) => {
+                    pending.push_back((offset, DecodedChar::Malformed(bytes.len())))
+                }
+            }
+            Ok(())
+        };
+        if self.range.start >= self.range.end {
+            self.done = true;
+            return self.decoder.finish(&mut f);
+        }
+        let end = self.range.start.saturating_add(STEP).min(self.range.end);
+        let decoder = &mut self.decoder;
+        self.cache
+            .traverse_chunks(self.range.start..end, |chunk| decoder.feed(chunk, &mut f))?;
+        self.range.start = end;
+        Ok(())
+    }
+}
+
+impl<'a, C: Cache + ?Sized> Iterator for DecodeChars<'a, C> {
+    type Item = Result<(u64, DecodedChar)>;
+
+    fn next(&mut self) -> Option<Result<(u64, DecodedChar)>> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.step() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use super::{Cache, Result};
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+/// The number of bytes of each cache compared at once, and the span
+/// searched for a resynchronization point after a mismatch.
+const WINDOW: usize = 64 * 1024;
+
+/// The number of equal bytes that must follow a resynchronization point.
+const ANCHOR_LEN: usize = 32;
+
+/// The span first searched for a resynchronization point. The span grows by
+/// a factor of `RESYNC_GROWTH` up to `WINDOW` until a point is found, so
+/// that the small mismatches of typical edits are resolved cheaply.
+const MIN_RESYNC_SPAN: usize = 256;
+
+const RESYNC_GROWTH: usize = 16;
+
+/// How far beyond the window an insertion or deletion is searched for when
+/// no resynchronization point is found within the window.
+const FAR: u64 = 1 << 20;
+
+/// A range of two caches reported by `Diff`.
+///
+/// Each range holds the corresponding byte ranges of the old cache, `a`, and
+/// the new cache, `b`. The range of the side without bytes, such as `a` for
+/// an insertion, is empty and positioned where the bytes were inserted or
+/// deleted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DiffRange {
+    /// The bytes of both ranges are equal.
+    Equal {
+        /// The range of the old cache.
+        a: Range<u64>,
+        /// The range of the new cache.
+        b: Range<u64>,
+    },
+
+    /// The bytes of the old range were replaced by those of the new range.
+    /// The ranges may differ in length.
+    Changed {
+        /// The range of the old cache.
+        a: Range<u64>,
+        /// The range of the new cache.
+        b: Range<u64>,
+    },
+
+    /// The bytes of the new range were inserted into the old cache.
+    Inserted {
+        /// The empty range of the old cache where the bytes were inserted.
+        a: Range<u64>,
+        /// The range of the new cache.
+        b: Range<u64>,
+    },
+
+    /// The bytes of the old range were deleted from the old cache.
+    Deleted {
+        /// The range of the old cache.
+        a: Range<u64>,
+        /// The empty range of the new cache where the bytes were deleted.
+        b: Range<u64>,
+    },
+}
+
+impl DiffRange {
+    /// Returns the range of the old cache.
+    pub fn a_range(&self) -> Range<u64> {
+        match self {
+            DiffRange::Equal { a, .. }
+            | DiffRange::Changed { a, .. }
+            | DiffRange::Inserted { a, .. }
+            | DiffRange::Deleted { a, .. } => a.clone(),
+        }
+    }
+
+    /// Returns the range of the new cache.
+    pub fn b_range(&self) -> Range<u64> {
+        match self {
+            DiffRange::Equal { b, .. }
+            | DiffRange::Changed { b, .. }
+            | DiffRange::Inserted { b, .. }
+            | DiffRange::Deleted { b, .. } => b.clone(),
+        }
+    }
+
+    /// Returns true if the range is `DiffRange::Equal`, false otherwise.
+    pub fn is_equal(&self) -> bool {
+        matches!(self, DiffRange::Equal { .. })
+    }
+}
+
+/// Compares two caches, and returns an iterator over the ranges where they
+/// are equal or differ, in ascending order. See `Diff`.
+pub fn diff<'a, A: Cache + ?Sized, B: Cache + ?Sized>(a: &'a A, b: &'a B) -> Diff<'a, A, B> {
+    Diff {
+        a,
+        b,
+        len_a: a.len(),
+        len_b: b.len(),
+        pos_a: 0,
+        pos_b: 0,
+        aligned: a.len() == b.len(),
+        window_a: Window::new(),
+        window_b: Window::new(),
+        pending: VecDeque::new(),
+        done: false,
+    }
+}
+
+/// The bytes of a cache buffered for comparison.
+struct Window {
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Window {
+            start: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Returns up to `len` bytes of the cache starting at `pos`, which are
+    /// fewer only at the end of the cache. Twice as many bytes are buffered
+    /// as requested, so that advancing through the cache in small steps
+    /// rarely reads from it.
+    fn get<C: Cache + ?Sized>(&mut self, cache: &C, pos: u64, len: usize) -> Result<&[u8]> {
+        let end = self.start + self.data.len() as u64;
+        if pos < self.start || (pos + len as u64).min(cache.len()) > end {
+            self.data.resize(len * 2, 0);
+            let read = cache.read(pos, &mut self.data)?;
+            self.data.truncate(read);
+            self.start = pos;
+        }
+        let from = (pos - self.start) as usize;
+        Ok(&self.data[from..(from + len).min(self.data.len())])
+    }
+}
+
+/// Returns the offsets in `a` and `b` of the nearest pair of equal runs of
+/// `anchor` bytes, minimizing the sum of the offsets.
+fn nearest_anchor(a: &[u8], b: &[u8], anchor: usize) -> Option<(usize, usize)> {
+    let mut grams: HashMap<&[u8], usize> = HashMap::with_capacity(b.len() - anchor + 1);
+    for j in 0..=b.len() - anchor {
+        grams.entry(&b[j..j + anchor]).or_insert(j);
+    }
+    let mut best: Option<(usize, usize)> = None;
+    for i in 0..=a.len() - anchor {
+        let best_sum = best.map_or(usize::MAX, |(best_i, best_j)| best_i + best_j);
+        if i >= best_sum {
+            break;
+        }
+        if let Some(j) = grams.get(&a[i..i + anchor]) {
+            if i + j < best_sum {
+                best = Some((i, *j));
+            }
+        }
+    }
+    best
+}
+
+/// An iterator over the differences between two caches.
+///
+/// This type is returned by `diff`. The caches are read in step through
+/// bounded windows, so caches of any size are compared in constant memory.
+///
+/// Caches of the same length are compared position by position, which is
+/// fast and suits files patched in place. Caches of different lengths, and
+/// caches of the same length that stay different for 64 KiB without 32
+/// equal bytes, are resynchronized after a mismatch: the nearest point
+/// within a window at which both caches are equal for at least 32 bytes is
+/// found, and the bytes before it are reported as changed, inserted or
+/// deleted.
+/// The window starts small and grows up to 64 KiB until a point is found.
+/// Insertions and deletions larger than the window are searched for up to
+/// a mebibyte ahead. If no resynchronization point is found, a window of
+/// each cache is reported as changed and the comparison continues after
+/// it.
+///
+/// If an error occurs, it is returned and the iteration ends.
+pub struct Diff<'a, A: Cache + ?Sized, B: Cache + ?Sized> {
+    a: &'a A,
+    b: &'a B,
+    len_a: u64,
+    len_b: u64,
+    pos_a: u64,
+    pos_b: u64,
+    aligned: bool,
+    window_a: Window,
+    window_b: Window,
+    pending: VecDeque<DiffRange>,
+    done: bool,
+}
+
+impl<'a, A: Cache + ?Sized, B: Cache + ?Sized> Diff<'a, A, B> {
+    /// Queues a range of `len_a` bytes of the old cache and `len_b` bytes of
+    /// the new cache, and advances past them.
+    fn push(&mut self, len_a: u64, len_b: u64, range: fn(Range<u64>, Range<u64>) -> DiffRange) {
+        let a = self.pos_a..self.pos_a + len_a;
+        let b = self.pos_b..self.pos_b + len_b;
+        self.pos_a = a.end;
+        self.pos_b = b.end;
+        self.pending.push_back(range(a, b));
+    }
+
+    /// Returns the number of equal bytes at the current positions, if
+    /// `equal` is true, or the number of differing bytes otherwise.
+    fn run_len(&mut self, equal: bool) -> Result<u64> {
+        let mut total = 0;
+        loop {
+            let a = self.window_a.get(self.a, self.pos_a + total, WINDOW)?;
+            let b = self.window_b.get(self.b, self.pos_b + total, WINDOW)?;
+            let len = a.len().min(b.len());
+            if len == 0 {
+                return Ok(total);
+            }
+            if equal && a[..len] == b[..len] {
+                total += len as u64;
+                continue;
+            }
+            let run = a[..len]
+                .iter()
+                .zip(&b[..len])
+                .position(|(a, b)| (a == b) != equal)
+                .unwrap_or(len);
+            total += run as u64;
+            if run < len {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Returns the number of bytes before the next offset at which both
+    /// caches are equal for `ANCHOR_LEN` bytes at the same positions, or the
+    /// window size if there is none within a window.
+    fn aligned_gap(&mut self) -> Result<u64> {
+        let a = self.window_a.get(self.a, self.pos_a, WINDOW)?;
+        let b = self.window_b.get(self.b, self.pos_b, WINDOW)?;
+        let len = a.len().min(b.len());
+        let mut equal = 0;
+        for (idx, (a, b)) in a[..len].iter().zip(&b[..len]).enumerate() {
+            if a != b {
+                equal = 0;
+                continue;
+            }
+            equal += 1;
+            if equal == ANCHOR_LEN {
+                return Ok((idx + 1 - ANCHOR_LEN) as u64);
+            }
+        }
+        Ok(len as u64)
+    }
+
+    /// Returns the number of bytes of each cache before the nearest point
+    /// where both caches are equal again.
+    fn resync(&mut self) -> Result<(u64, u64)> {
+        let mut span = MIN_RESYNC_SPAN;
+        loop {
+            let a = self.window_a.get(self.a, self.pos_a, span)?;
+            let b = self.window_b.get(self.b, self.pos_b, span)?;
+            let anchor = ANCHOR_LEN.min(a.len()).min(b.len());
+            // A nearer point than the one found would lie within the span.
+            if let Some((i, j)) = nearest_anchor(a, b, anchor) {
+                if i + j + anchor <= span || span == WINDOW {
+                    return Ok((i as u64, j as u64));
+                }
+            }
+            if span == WINDOW {
+                break;
+            }
+            span = (span * RESYNC_GROWTH).min(WINDOW);
+        }
+        let a = self.window_a.get(self.a, self.pos_a, WINDOW)?;
+        let b = self.window_b.get(self.b, self.pos_b, WINDOW)?;
+        let anchor = ANCHOR_LEN.min(a.len()).min(b.len());
+        let fallback = a.len().min(b.len()) as u64;
+        if anchor < ANCHOR_LEN {
+            return Ok((fallback, fallback));
+        }
+        let head_a = a[..anchor].to_vec();
+        let head_b = b[..anchor].to_vec();
+        let inserted = self
+            .b
+            .find(&head_a, self.pos_b..self.pos_b.saturating_add(FAR))?
+            .map(|offset| offset - self.pos_b);
+        let deleted = self
+            .a
+            .find(&head_b, self.pos_a..self.pos_a.saturating_add(FAR))?
+            .map(|offset| offset - self.pos_a);
+        Ok(match (inserted, deleted) {
+            (Some(j), Some(i)) if i < j => (i, 0),
+            (Some(j), _) => (0, j),
+            (None, Some(i)) => (i, 0),
+            (None, None) => (fallback, fallback),
+        })
+    }
+
+    /// Queues the next ranges. Returns false at the end of both caches.
+    fn step(&mut self) -> Result<bool> {
+        let rest_a = self.len_a - self.pos_a;
+        let rest_b = self.len_b - self.pos_b;
+        if rest_a == 0 && rest_b == 0 {
+            return Ok(false);
+        }
+        if rest_a == 0 || rest_b == 0 {
+            self.push(rest_a, rest_b, |a, b| {
+                if a.start == a.end {
+                    DiffRange::Inserted { a, b }
+                } else {
+                    DiffRange::Deleted { a, b }
+                }
+            });
+            return Ok(true);
+        }
+        let equal = self.run_len(true)?;
+        if equal > 0 {
+            self.push(equal, equal, |a, b| DiffRange::Equal { a, b });
+            return Ok(true);
+        }
+        if self.aligned && self.aligned_gap()? < WINDOW as u64 {
+            let changed = self.run_len(false)?;
+            self.push(changed, changed, |a, b| DiffRange::Changed { a, b });
+            return Ok(true);
+        }
+        let (skip_a, skip_b) = self.resync()?;
+        let changed = skip_a.min(skip_b);
+        if changed > 0 {
+            self.push(changed, changed, |a, b| DiffRange::Changed { a, b });
+        }
+        if skip_a > changed {
+            self.push(skip_a - changed, 0, |a, b| DiffRange::Deleted { a, b });
+        }
+        if skip_b > changed {
+            self.push(0, skip_b - changed, |a, b| DiffRange::Inserted { a, b });
+        }
+        Ok(true)
+    }
+}
+
+impl<'a, A: Cache + ?Sized, B: Cache + ?Sized> Iterator for Diff<'a, A, B> {
+    type Item = Result<DiffRange>;
+
+    fn next(&mut self) -> Option<Result<DiffRange>> {
+        if let Some(range) = self.pending.pop_front() {
+            return Some(Ok(range));
+        }
+        if self.done {
+            return None;
+        }
+        match self.step() {
+            Ok(true) => self.pending.pop_front().map(Ok),
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,1045 @@
+use super::journal::{Journal, Record};
+use super::{bounds, Cache, Error, Fingerprint, Fingerprinted, Operation, Result, ResultExt};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::ops::{Range, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const NAME: &str = "EditCache";
+
+/// The default maximum number of undo steps kept by an `EditCache`.
+const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+/// The default maximum size of the undo history of an `EditCache`.
+const DEFAULT_HISTORY_SIZE_LIMIT: usize = 256 << 20;
+
+/// The smallest buffer of added bytes that is compacted.
+const MIN_COMPACT_LEN: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Buffer {
+    Base,
+    Added,
+}
+
+/// A contiguous run of bytes from either the base cache or the buffer of
+/// added bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Piece {
+    buffer: Buffer,
+    offset: u64,
+    len: u64,
+}
+
+impl Piece {
+    fn base(len: u64) -> Vec<Piece> {
+        if len == 0 {
+            Vec::new()
+        } else {
+            vec![Piece {
+                buffer: Buffer::Base,
+                offset: 0,
+                len,
+            }]
+        }
+    }
+}
+
+/// A handle to a mark registered with an `EditCache`.
+///
+/// A mark is a position in the edited content that moves with the bytes
+/// around it as edits are made. Marks are created with
+/// `EditCache::add_mark`, and their current offset is returned by
+/// `EditCache::mark_offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mark(u64);
+
+/// The side a mark sticks to when bytes are inserted at its offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gravity {
+    /// The mark stays before bytes inserted at its offset, like the start of
+    /// a selection.
+    Left,
+
+    /// The mark moves after bytes inserted at its offset, like a cursor.
+    Right,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MarkState {
+    offset: u64,
+    gravity: Gravity,
+}
+
+/// The journal of an `EditCache`, along with the means to fingerprint its
+/// base when the journal restarts. The function is captured when journaling
+/// starts, where the source of the base is known to be `Fingerprinted`.
+struct Journaling<C> {
+    journal: Journal,
+    fingerprint: fn(&mut C) -> Result<Fingerprint>,
+}
+
+/// Validates the base cache, and returns the fingerprint of its source.
+fn current_fingerprint<C: Cache>(base: &mut C) -> Result<Fingerprint>
+where
+    C::Source: Fingerprinted,
+{
+    base.validate()?;
+    Ok(base.fingerprint())
+}
+
+/// A replacement of `inserted` pieces starting at index `at` for the
+/// `removed` pieces, which reverses a change made to the piece table.
+struct Splice {
+    at: usize,
+    removed: Vec<Piece>,
+    inserted: usize,
+}
+
+/// An undo step: the splices that reverse the changes made to the piece
+/// table by an edit or a group of edits, along with the length of the
+/// content and the offsets of the marks before them.
+struct Step {
+    splices: Vec<Splice>,
+    len: u64,
+    marks: Vec<(Mark, u64)>,
+    added: usize,
+}
+
+impl Step {
+    /// Returns the memory held by the step, counted as the size of its
+    /// splices and the pieces they record, and the bytes that were added to
+    /// the buffer while it was recorded. Marks are not counted, so that the
+    /// history is trimmed the same way when a journal is replayed.
+    fn size(&self) -> usize {
+        let pieces: usize = self.splices.iter().map(|s| s.removed.len()).sum();
+        self.splices.len() * std::mem::size_of::<Splice>()
+            + pieces * std::mem::size_of::<Piece>()
+            + self.added
+    }
+}
+
+/// An editable, copy-on-write view over another cache.
+///
+/// `EditCache` layers a piece table over an existing cache. The edited
+/// content is described by a sequence of pieces, each of which refers to a
+/// range of bytes in either the base cache or an in-memory buffer holding
+/// all bytes that were ever inserted or written. Edits never touch the base
+/// cache or its source, and only the new bytes of each edit are stored, so
+/// deleting or moving large ranges costs the same as deleting or moving
+/// small ones.
+///
+/// `EditCache` implements `Cache` itself, so `Cache::traverse_chunks` and
+/// `CacheReader` see the edited content. Offsets passed to the editing
+/// methods always refer to the edited content, not the base cache.
+///
+/// Every edit can be undone and redone. Each call to an editing method is
+/// one undo step, unless it is made between `EditCache::begin_group` and
+/// `EditCache::end_group`, in which case the whole group is one step. Each
+/// step records the pieces that its edits replaced rather than copies of
+/// the data, so a step costs memory in proportion to the pieces it touched,
+/// and undoing or redoing it costs the same as making it. The history is
+/// bounded by a number of steps, set with `EditCache::set_history_limit`,
+/// and by the memory it holds, set with `EditCache::set_history_size_limit`,
+/// and the oldest steps are forgotten first. Added bytes that neither the
+/// content nor the history refer to any more are freed as the buffer of
+/// added bytes grows.
+///
+/// When the source of the base cache is a `std::fs::File`, the edited
+/// content can be saved with `EditCache::save_to` or
+/// `EditCache::save_in_place`, after which the cache is re-based onto the
+/// saved file.
+///
+/// Marks registered with `EditCache::add_mark` track positions in the
+/// content, such as bookmarks or the ends of a selection. Inserting bytes
+/// before a mark, or deleting bytes before it, shifts the mark, and a mark
+/// inside a deleted range moves to the start of the range. Overwriting bytes
+/// leaves marks in place. Undoing or redoing a step restores the offsets
+/// that marks had before the step, while marks added since then are clamped
+/// to the content.
+///
+/// Edits can be recorded in a journal file as they are made, by creating the
+/// cache with `EditCache::with_journal`. If the process crashes before the
+/// edits are saved, `EditCache::recover` replays the journal over the
+/// unchanged base, restoring the edits and the undo history. Whenever the
+/// cache is re-based, such as after saving, the journal is restarted.
+pub struct EditCache<C: Cache> {
+    base: C,
+    added: Vec<u8>,
+    compact_len: usize,
+    pieces: Vec<Piece>,
+    len: u64,
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    recording: bool,
+    history_limit: usize,
+    history_size: usize,
+    history_size_limit: usize,
+    group_depth: usize,
+    group_recorded: bool,
+    path: Option<PathBuf>,
+    journal: Option<Journaling<C>>,
+    marks: HashMap<Mark, MarkState>,
+    next_mark: u64,
+}
+
+impl<C: Cache> EditCache<C> {
+    /// Creates a new `EditCache` over the passed cache, with no edits.
+    pub fn new(base: C) -> Self {
+        let len = base.len();
+        EditCache {
+            base,
+            added: Vec::new(),
+            compact_len: MIN_COMPACT_LEN,
+            pieces: Piece::base(len),
+            len,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            recording: false,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            history_size: 0,
+            history_size_limit: DEFAULT_HISTORY_SIZE_LIMIT,
+            group_depth: 0,
+            group_recorded: false,
+            path: None,
+            journal: None,
+            marks: HashMap::new(),
+            next_mark: 0,
+        }
+    }
+
+    /// Creates a new `EditCache` over the passed cache, with no edits, that
+    /// records every edit in a journal file at `journal`. Any existing file
+    /// at `journal` is truncated. The base cache is validated first, so that
+    /// the journal records the current fingerprint of its source.
+    pub fn with_journal<P: AsRef<Path>>(base: C, journal: P) -> Result<Self>
+    where
+        C::Source: Fingerprinted,
+    {
+        let mut base = base;
+        let fingerprint = current_fingerprint(&mut base)?;
+        let journal = Journal::create(journal.as_ref(), &fingerprint)?;
+        let mut cache = EditCache::new(base);
+        cache.journal = Some(Journaling {
+            journal,
+            fingerprint: current_fingerprint::<C>,
+        });
+        Ok(cache)
+    }
+
+    /// Recovers the edits recorded in the journal file at `journal` over the
+    /// passed cache, which must be a cache of the same, unmodified source the
+    /// journal was written against. The edits and the undo history are
+    /// replayed up to the last edit that was completely written, and
+    /// subsequent edits are appended to the journal.
+    ///
+    /// Returns `Error::Journal` if the file is not a journal, or if the
+    /// source of the base cache was modified after the journal was written.
+    pub fn recover<P: AsRef<Path>>(base: C, journal: P) -> Result<Self>
+    where
+        C::Source: Fingerprinted,
+    {
+        let mut base = base;
+        let fingerprint = current_fingerprint(&mut base)?;
+        let mut cache = EditCache::new(base);
+        let journal = Journal::replay(journal.as_ref(), &fingerprint, |record| {
+            match record {
+                Record::Overwrite(offset, bytes) => cache.overwrite(offset, bytes)?,
+                Record::Insert(offset, bytes) => cache.insert(offset, bytes)?,
+                Record::Delete(start, end) => cache.delete(start..end),
+                Record::Revert => cache.revert(),
+                Record::BeginGroup => cache.begin_group(),
+                Record::EndGroup => cache.end_group(),
+                Record::Undo => {
+                    cache.undo();
+                }
+                Record::Redo => {
+                    cache.redo();
+                }
+                Record::HistoryLimit(limit) => {
+                    cache.set_history_limit(usize::try_from(limit).unwrap_or(usize::MAX))
+                }
+                Record::HistorySizeLimit(limit) => {
+                    cache.set_history_size_limit(usize::try_from(limit).unwrap_or(usize::MAX))
+                }
+            }
+            Ok(())
+        })?;
+        cache.journal = Some(Journaling {
+            journal,
+            fingerprint: current_fingerprint::<C>,
+        });
+        Ok(cache)
+    }
+
+    /// Flushes the journal to disk, so that the edits made so far survive a
+    /// system crash. Edits are written to the journal as they are made, but
+    /// a failure to write them is only reported here, after which no further
+    /// edits are recorded until the journal restarts. Does nothing if the
+    /// cache has no journal.
+    pub fn sync_journal(&mut self) -> Result<()> {
+        match self.journal {
+            Some(ref mut journaling) => journaling.journal.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the path of the file that `EditCache::save_in_place` saves to.
+    /// This should be the path of the file that the base cache was created
+    /// from.
+    pub fn set_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.path = Some(path.as_ref().to_path_buf());
+    }
+
+    /// Returns the path of the file that `EditCache::save_in_place` saves
+    /// to, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns an immutable reference to the base cache.
+    pub fn base(&self) -> &C {
+        &self.base
+    }
+
+    /// Returns true if the edited content differs in structure from the
+    /// base cache, false otherwise.
+    pub fn is_modified(&self) -> bool {
+        self.pieces != Piece::base(self.base.len())
+    }
+
+    /// Discards all edits, restoring the content of the base cache. Reverting
+    /// is itself an edit, and can be undone.
+    pub fn revert(&mut self) {
+        if self.is_modified() {
+            self.record();
+            let count = self.pieces.len();
+            self.splice(0..count, Piece::base(self.base.len()));
+            self.len = self.base.len();
+            self.clamp_marks();
+            self.log(Record::Revert);
+        }
+    }
+
+    /// Replaces bytes starting at `offset` with `bytes`. Bytes written past
+    /// the end of the content extend it. Returns `Error::OutOfRange` if
+    /// `offset` is beyond the end of the content.
+    pub fn overwrite(&mut self, offset: u64, bytes: &[u8]) -> Result<()> {
+        self.check_offset(offset)?;
+        if !bytes.is_empty() {
+            self.record();
+            let len = self.len;
+            let end = offset.saturating_add(bytes.len() as u64);
+            self.remove(offset..end.min(len));
+            self.add(offset, bytes);
+            if end > len {
+                self.shift_marks(len, end - len);
+            }
+            self.log(Record::Overwrite(offset, bytes));
+        }
+        Ok(())
+    }
+
+    /// Inserts `bytes` at `offset`, shifting the following content. Returns
+    /// `Error::OutOfRange` if `offset` is beyond the end of the content.
+    pub fn insert(&mut self, offset: u64, bytes: &[u8]) -> Result<()> {
+        self.check_offset(offset)?;
+        if !bytes.is_empty() {
+            self.record();
+            self.add(offset, bytes);
+            self.shift_marks(offset, bytes.len() as u64);
+            self.log(Record::Insert(offset, bytes));
+        }
+        Ok(())
+    }
+
+    /// Deletes the bytes in the passed range, shifting the following
+    /// content. The range is clamped to the content, like the range passed
+    /// to `Cache::traverse_chunks`.
+    pub fn delete<R: RangeBounds<u64>>(&mut self, range: R) {
+        let range = bounds(&range, self.len);
+        if range.start < range.end {
+            self.record();
+            self.log(Record::Delete(range.start, range.end));
+            for mark in self.marks.values_mut() {
+                if mark.offset >= range.end {
+                    mark.offset -= range.end - range.start;
+                } else if mark.offset > range.start {
+                    mark.offset = range.start;
+                }
+            }
+            self.remove(range);
+        }
+    }
+
+    /// Registers a new mark at `offset` with the passed gravity, and returns
+    /// its handle. Returns `Error::OutOfRange` if `offset` is beyond the end
+    /// of the content.
+    pub fn add_mark(&mut self, offset: u64, gravity: Gravity) -> Result<Mark> {
+        self.check_offset(offset)?;
+        let mark = Mark(self.next_mark);
+        self.next_mark += 1;
+        self.marks.insert(mark, MarkState { offset, gravity });
+        Ok(mark)
+    }
+
+    /// Unregisters a mark. Returns true if the mark was registered, false
+    /// otherwise.
+    pub fn remove_mark(&mut self, mark: Mark) -> bool {
+        self.marks.remove(&mark).is_some()
+    }
+
+    /// Moves a mark to `offset`. Returns `Error::OutOfRange` if `offset` is
+    /// beyond the end of the content. Returns false if the mark is not
+    /// registered, true otherwise.
+    pub fn move_mark(&mut self, mark: Mark, offset: u64) -> Result<bool> {
+        self.check_offset(offset)?;
+        match self.marks.get_mut(&mark) {
+            Some(state) => {
+                state.offset = offset;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the current offset of a mark, or `None` if the mark is not
+    /// registered.
+    pub fn mark_offset(&self, mark: Mark) -> Option<u64> {
+        self.marks.get(&mark).map(|state| state.offset)
+    }
+
+    /// Returns the gravity of a mark, or `None` if the mark is not
+    /// registered.
+    pub fn mark_gravity(&self, mark: Mark) -> Option<Gravity> {
+        self.marks.get(&mark).map(|state| state.gravity)
+    }
+
+    /// Starts a group of edits that are undone and redone as a single step.
+    /// Groups may be nested, in which case the outermost group is the step.
+    pub fn begin_group(&mut self) {
+        self.group_depth += 1;
+        self.log(Record::BeginGroup);
+    }
+
+    /// Ends the group of edits started by the matching call to
+    /// `EditCache::begin_group`.
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth == 0 {
+            self.group_recorded = false;
+        }
+        self.log(Record::EndGroup);
+    }
+
+    /// Returns true if there is an edit that can be undone, false otherwise.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Returns true if there is an undone edit that can be redone, false
+    /// otherwise.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Undoes the most recent undo step. Any open groups are ended first.
+    /// Returns true if a step was undone, false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        self.group_depth = 0;
+        self.group_recorded = false;
+        match self.undo.pop_back() {
+            Some(step) => {
+                self.history_size -= step.size();
+                let inverse = self.apply(step);
+                self.redo.push(inverse);
+                self.log(Record::Undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redoes the most recently undone step. Any open groups are ended first.
+    /// Returns true if a step was redone, false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        self.group_depth = 0;
+        self.group_recorded = false;
+        match self.redo.pop() {
+            Some(step) => {
+                let inverse = self.apply(step);
+                self.history_size += inverse.size();
+                self.undo.push_back(inverse);
+                self.trim_history();
+                self.log(Record::Redo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the maximum number of undo steps that are kept, forgetting the
+    /// oldest steps if there are more. A limit of zero disables undo.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        self.log(Record::HistoryLimit(limit as u64));
+        self.trim_history();
+    }
+
+    /// Returns the maximum number of undo steps that are kept.
+    pub fn history_limit(&self) -> usize {
+        self.history_limit
+    }
+
+    /// Sets the maximum size of the undo history in bytes, forgetting the
+    /// oldest steps while the history is larger. The size of a step counts
+    /// the pieces it records and the bytes added to the content by its
+    /// edits. The most recent step is always kept, even if it is larger
+    /// than the limit on its own. The default limit is 256 MiB.
+    pub fn set_history_size_limit(&mut self, limit: usize) {
+        self.history_size_limit = limit;
+        self.log(Record::HistorySizeLimit(limit as u64));
+        self.trim_history();
+    }
+
+    /// Returns the maximum size of the undo history in bytes.
+    pub fn history_size_limit(&self) -> usize {
+        self.history_size_limit
+    }
+
+    /// Returns the current size of the undo history in bytes, as counted
+    /// against `EditCache::history_size_limit`.
+    pub fn history_size(&self) -> usize {
+        self.history_size
+    }
+
+    /// Starts a new undo step before an edit, unless the edit is part of a
+    /// group that already has a step. Any undone steps are lost.
+    fn record(&mut self) {
+        if self.group_depth > 0 {
+            if self.group_recorded {
+                return;
+            }
+            self.group_recorded = true;
+        }
+        self.redo.clear();
+        self.recording = self.history_limit > 0;
+        if self.recording {
+            let step = Step {
+                splices: Vec::new(),
+                len: self.len,
+                marks: self.mark_offsets(),
+                added: 0,
+            };
+            self.history_size += step.size();
+            self.undo.push_back(step);
+            self.trim_history();
+        }
+        self.compact();
+    }
+
+    /// Forgets the oldest undo steps until the history is within its
+    /// limits, keeping the most recent step unless undo is disabled.
+    fn trim_history(&mut self) {
+        while self.undo.len() > self.history_limit
+            || (self.undo.len() > 1 && self.history_size > self.history_size_limit)
+        {
+            if let Some(step) = self.undo.pop_front() {
+                self.history_size -= step.size();
+            }
+        }
+        if self.undo.is_empty() {
+            self.recording = false;
+        }
+    }
+
+    fn mark_offsets(&self) -> Vec<(Mark, u64)> {
+        self.marks
+            .iter()
+            .map(|(mark, state)| (*mark, state.offset))
+            .collect()
+    }
+
+    /// Reverses the splices of a step, restoring the length and the mark
+    /// offsets it recorded, and returns the step that reverses it in turn.
+    fn apply(&mut self, step: Step) -> Step {
+        let mut inverse = Step {
+            splices: Vec::with_capacity(step.splices.len()),
+            len: self.len,
+            marks: self.mark_offsets(),
+            added: step.added,
+        };
+        for splice in step.splices.into_iter().rev() {
+            let range = splice.at..splice.at + splice.inserted;
+            let inserted = splice.removed.len();
+            let removed = self.pieces.splice(range, splice.removed).collect();
+            inverse.splices.push(Splice {
+                at: splice.at,
+                removed,
+                inserted,
+            });
+        }
+        self.len = step.len;
+        self.clamp_marks();
+        for (mark, offset) in step.marks {
+            if let Some(state) = self.marks.get_mut(&mark) {
+                state.offset = offset;
+            }
+        }
+        inverse
+    }
+
+    /// Replaces the pieces in `range` with `pieces`, and records the splice
+    /// that reverses it in the current undo step, if any.
+    fn splice(&mut self, range: Range<usize>, pieces: Vec<Piece>) {
+        let at = range.start;
+        let inserted = pieces.len();
+        let removed: Vec<Piece> = self.pieces.splice(range, pieces).collect();
+        if self.recording {
+            if let Some(step) = self.undo.back_mut() {
+                self.history_size +=
+                    std::mem::size_of::<Splice>() + removed.len() * std::mem::size_of::<Piece>();
+                step.splices.push(Splice {
+                    at,
+                    removed,
+                    inserted,
+                });
+                self.trim_history();
+            }
+        }
+    }
+
+    /// Counts bytes added to the buffer against the current undo step, if
+    /// any.
+    fn charge(&mut self, added: usize) {
+        if self.recording {
+            if let Some(step) = self.undo.back_mut() {
+                step.added += added;
+                self.history_size += added;
+                self.trim_history();
+            }
+        }
+    }
+
+    /// Drops the added bytes that neither the content nor the history refer
+    /// to, once the buffer has doubled in size since it was last compacted,
+    /// and moves the rest to the start of the buffer.
+    fn compact(&mut self) {
+        if self.added.len() < self.compact_len {
+            return;
+        }
+        let mut used: Vec<Range<u64>> =
+            self.pieces
+                .iter()
+                .chain(
+                    self.undo.iter().chain(self.redo.iter()).flat_map(|step| {
+                        step.splices.iter().flat_map(|splice| splice.removed.iter())
+                    }),
+                )
+                .filter(|piece| piece.buffer == Buffer::Added)
+                .map(|piece| piece.offset..piece.offset + piece.len)
+                .collect();
+        used.sort_unstable_by_key(|range| range.start);
+        // Each kept range of the old buffer, with its offset in the new one.
+        let mut kept: Vec<(Range<u64>, u64)> = Vec::new();
+        for range in used {
+            match kept.last_mut() {
+                Some((last, _)) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => kept.push((range, 0)),
+            }
+        }
+        let mut added = Vec::new();
+        for (range, offset) in kept.iter_mut() {
+            *offset = added.len() as u64;
+            added.extend_from_slice(&self.added[range.start as usize..range.end as usize]);
+        }
+        let remap = |piece: &mut Piece| {
+            if piece.buffer == Buffer::Added {
+                let idx = kept.partition_point(|(range, _)| range.start <= piece.offset) - 1;
+                let (ref range, offset) = kept[idx];
+                piece.offset = offset + piece.offset - range.start;
+            }
+        };
+        self.pieces.iter_mut().for_each(remap);
+        for step in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            for splice in step.splices.iter_mut() {
+                splice.removed.iter_mut().for_each(remap);
+            }
+        }
+        self.added = added;
+        self.compact_len = (self.added.len() * 2).max(MIN_COMPACT_LEN);
+    }
+
+    /// Shifts marks for `len` bytes inserted at `offset`.
+    fn shift_marks(&mut self, offset: u64, len: u64) {
+        for mark in self.marks.values_mut() {
+            if mark.offset > offset || (mark.offset == offset && mark.gravity == Gravity::Right) {
+                mark.offset += len;
+            }
+        }
+    }
+
+    /// Moves marks beyond the end of the content to the end.
+    fn clamp_marks(&mut self) {
+        let len = self.len;
+        for mark in self.marks.values_mut() {
+            mark.offset = mark.offset.min(len);
+        }
+    }
+
+    /// Appends an edit to the journal, if any.
+    fn log(&mut self, record: Record) {
+        if let Some(ref mut journaling) = self.journal {
+            journaling.journal.append(&record);
+        }
+    }
+
+    /// Discards all edits and the history, including the added bytes, and
+    /// restarts the journal for the current base.
+    fn reset(&mut self) -> Result<()> {
+        self.added.clear();
+        self.compact_len = MIN_COMPACT_LEN;
+        self.pieces = Piece::base(self.base.len());
+        self.len = self.base.len();
+        self.undo.clear();
+        self.redo.clear();
+        self.recording = false;
+        self.history_size = 0;
+        self.group_recorded = false;
+        self.clamp_marks();
+        if let Some(ref mut journaling) = self.journal {
+            let fingerprint = (journaling.fingerprint)(&mut self.base)?;
+            journaling.journal.restart(&fingerprint)?;
+        }
+        Ok(())
+    }
+
+    fn check_offset(&self, offset: u64) -> Result<()> {
+        if offset > self.len {
+            Err(Error::OutOfRange {
+                offset,
+                len: self.len,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Splits the piece containing `offset` so that a piece starts at
+    /// `offset`, and returns the index of that piece. Returns the number of
+    /// pieces if `offset` is the end of the content.
+    fn split(&mut self, offset: u64) -> usize {
+        let mut pos = 0;
+        for idx in 0..self.pieces.len() {
+            let piece = self.pieces[idx];
+            if offset == pos {
+                return idx;
+            }
+            if offset < pos + piece.len {
+                let head = offset - pos;
+                let halves = vec![
+                    Piece { len: head, ..piece },
+                    Piece {
+                        offset: piece.offset + head,
+                        len: piece.len - head,
+                        ..piece
+                    },
+                ];
+                self.splice(idx..idx + 1, halves);
+                return idx + 1;
+            }
+            pos += piece.len;
+        }
+        self.pieces.len()
+    }
+
+    fn remove(&mut self, range: Range<u64>) {
+        if range.start < range.end {
+            let first = self.split(range.start);
+            let last = self.split(range.end);
+            self.splice(first..last, Vec::new());
+            self.len -= range.end - range.start;
+        }
+    }
+
+    fn add(&mut self, offset: u64, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let idx = self.split(offset);
+        let added_len = self.added.len() as u64;
+        self.added.extend_from_slice(bytes);
+        self.charge(bytes.len());
+        let len = bytes.len() as u64;
+        // Typing at the end of the previous insertion extends its piece.
+        match idx.checked_sub(1).map(|prev| self.pieces[prev]) {
+            Some(prev) if prev.buffer == Buffer::Added && prev.offset + prev.len == added_len => {
+                let extended = Piece {
+                    len: prev.len + len,
+                    ..prev
+                };
+                self.splice(idx - 1..idx, vec![extended]);
+            }
+            _ => {
+                let piece = Piece {
+                    buffer: Buffer::Added,
+                    offset: added_len,
+                    len,
+                };
+                self.splice(idx..idx, vec![piece]);
+            }
+        }
+        self.len += len;
+    }
+}
+
+/// Distinguishes temporary files created by concurrent saves.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates a new temporary file in the directory of `path`.
+fn create_temp(path: &Path) -> Result<(File, PathBuf)> {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    loop {
+        let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let temp = dir.join(format!(".{}.{}.{}.tmp", name, std::process::id(), count));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((file, temp)),
+            Err(ref e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e).context(NAME, Operation::Open, None),
+        }
+    }
+}
+
+/// Flushes the directory entry of `path` to disk, so that a rename into the
+/// directory survives a crash. This is only possible on Unix.
+fn sync_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        if let Some(dir) = path.parent() {
+            let dir = if dir == Path::new("") {
+                Path::new(".")
+            } else {
+                dir
+            };
+            File::open(dir)
+                .and_then(|dir| dir.sync_all())
+                .context(NAME, Operation::Sync, None)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+impl<C: Cache<Source = File>> EditCache<C> {
+    /// Saves the edited content to the file at `path`, atomically replacing
+    /// any file that exists there. The content is streamed into a temporary
+    /// file in the same directory, which is flushed to disk and then renamed
+    /// over `path`. Afterwards, the cache is re-based onto the saved file,
+    /// so the edits and the undo history are discarded, and `path` becomes
+    /// the path used by `EditCache::save_in_place`.
+    pub fn save_to<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let (mut temp, temp_path) = create_temp(path)?;
+        let result = self
+            .write_temp(&mut temp, path)
+            .and_then(|_| std::fs::rename(&temp_path, path).context(NAME, Operation::Rename, None));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result?;
+        sync_dir(path)?;
+        self.rebase(path)
+    }
+
+    /// Saves the edited content to the file set with `EditCache::set_path`,
+    /// or the file last saved to with `EditCache::save_to`. If the length of
+    /// the content is unchanged, and every edit replaced bytes with newly
+    /// written bytes, only the modified ranges of the file are rewritten.
+    /// Otherwise, the file is replaced atomically as by `EditCache::save_to`.
+    /// Afterwards, the cache is re-based onto the saved file.
+    pub fn save_in_place(&mut self) -> Result<()> {
+        let path = match self.path.clone() {
+            Some(path) => path,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "no path to save to",
+                ))
+                .context(NAME, Operation::Write, None)
+            }
+        };
+        match self.dirty_ranges() {
+            Some(dirty) => {
+                let mut file = OpenOptions::new().write(true).open(&path).context(
+                    NAME,
+                    Operation::Open,
+                    None,
+                )?;
+                for (pos, piece) in dirty {
+                    let range = pos..pos + piece.len;
+                    let bytes =
+                        &self.added[piece.offset as usize..(piece.offset + piece.len) as usize];
+                    file.seek(SeekFrom::Start(pos)).context(
+                        NAME,
+                        Operation::Seek,
+                        Some(pos..pos),
+                    )?;
+                    file.write_all(bytes)
+                        .context(NAME, Operation::Write, Some(range))?;
+                }
+                file.sync_all().context(NAME, Operation::Sync, None)?;
+                self.rebase(&path)
+            }
+            None => self.save_to(&path),
+        }
+    }
+
+    /// Returns the pieces of added bytes along with their offsets, if the
+    /// content can be saved by writing only those pieces over the base.
+    fn dirty_ranges(&self) -> Option<Vec<(u64, Piece)>> {
+        if self.len != self.base.len() {
+            return None;
+        }
+        let mut dirty = Vec::new();
+        let mut pos = 0;
+        for piece in self.pieces.iter() {
+            match piece.buffer {
+                Buffer::Base if piece.offset != pos => return None,
+                Buffer::Base => {}
+                Buffer::Added => dirty.push((pos, *piece)),
+            }
+            pos += piece.len;
+        }
+        Some(dirty)
+    }
+
+    fn write_temp(&self, temp: &mut File, path: &Path) -> Result<()> {
+        let mut pos = 0;
+        self.traverse_chunks(.., |chunk| {
+            let range = pos..pos + chunk.len() as u64;
+            pos = range.end;
+            temp.write_all(chunk)
+                .context(NAME, Operation::Write, Some(range))
+        })?;
+        if let Ok(meta) = std::fs::metadata(path) {
+            temp.set_permissions(meta.permissions())
+                .context(NAME, Operation::Write, None)?;
+        }
+        temp.sync_all().context(NAME, Operation::Sync, None)
+    }
+
+    fn rebase(&mut self, path: &Path) -> Result<()> {
+        let file = File::open(path).context(NAME, Operation::Open, None)?;
+        self.replace_source(file)?;
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+}
+
+impl<C: Cache> Cache for EditCache<C> {
+    type Source = C::Source;
+
+    /// Destroys the cache and returns the source of the base cache. All
+    /// edits are discarded.
+    fn into_inner(self) -> Result<C::Source> {
+        self.base.into_inner()
+    }
+
+    /// Replaces the source of the base cache. All edits and the undo
+    /// history are discarded, and the journal, if any, is restarted.
+    fn replace_source(&mut self, source: C::Source) -> Result<C::Source> {
+        let old = self.base.replace_source(source)?;
+        self.reset()?;
+        Ok(old)
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the cache memory of the base cache, plus the memory holding
+    /// added bytes.
+    fn cache_size(&self) -> usize {
+        self.base.cache_size() + self.added.len()
+    }
+
+    /// Returns the fingerprint of the source of the base cache.
+    fn fingerprint(&self) -> Fingerprint {
+        self.base.fingerprint()
+    }
+
+    /// Validates the base cache. If the source of the base cache was
+    /// modified, the edits no longer apply to it, so all edits and the undo
+    /// history are discarded and the edited content becomes the new content
+    /// of the source.
+    fn validate(&mut self) -> Result<bool>
+    where
+        C::Source: Fingerprinted,
+    {
+        let changed = self.base.validate()?;
+        if changed {
+            self.reset()?;
+        }
+        Ok(changed)
+    }
+
+    fn traverse_chunks<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        let mut f = f;
+        let Range { start, end } = bounds(&range, self.len);
+        let mut pos = 0;
+        for piece in self.pieces.iter() {
+            if pos >= end {
+                break;
+            }
+            let piece_end = pos + piece.len;
+            if piece_end > start {
+                let from = piece.offset + start.saturating_sub(pos);
+                let to = piece.offset + piece.len - piece_end.saturating_sub(end);
+                match piece.buffer {
+                    Buffer::Base => self.base.traverse_chunks(from..to, &mut f)?,
+                    Buffer::Added => f(&self.added[from as usize..to as usize])?,
+                }
+            }
+            pos = piece_end;
+        }
+        Ok(())
+    }
+
+    fn traverse_chunks_rev<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        let mut f = f;
+        let Range { start, end } = bounds(&range, self.len);
+        let mut piece_end = self.len;
+        for piece in self.pieces.iter().rev() {
+            if piece_end <= start {
+                break;
+            }
+            let pos = piece_end - piece.len;
+            if pos < end {
+                let from = piece.offset + start.saturating_sub(pos);
+                let to = piece.offset + piece.len - piece_end.saturating_sub(end);
+                match piece.buffer {
+                    Buffer::Base => self.base.traverse_chunks_rev(from..to, &mut f)?,
+                    Buffer::Added => f(&self.added[from as usize..to as usize])?,
+                }
+            }
+            piece_end = pos;
+        }
+        Ok(())
+    }
+}
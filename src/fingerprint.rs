@@ -0,0 +1,160 @@
+use super::{Cache, CacheReader, Result};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::time::SystemTime;
+
+/// Identifying metadata of a cache source, used to detect modification.
+///
+/// A fingerprint always records the length of the source. Sources that can
+/// provide more information, such as `std::fs::File`, also record the last
+/// modification time and the file's inode and link count. Two fingerprints
+/// match when their lengths are equal and every other field recorded by both
+/// is equal, so a fingerprint that only knows the length of the source never
+/// causes a change to be missed on account of the fields it does not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+    inode: Option<u64>,
+    links: Option<u64>,
+}
+
+impl Fingerprint {
+    /// Creates a new `Fingerprint` that only records the length of the source.
+    pub fn new(len: u64) -> Self {
+        Fingerprint {
+            len,
+            modified: None,
+            inode: None,
+            links: None,
+        }
+    }
+
+    /// Returns the fingerprint with the passed last modification time recorded.
+    pub fn with_modified(self, modified: SystemTime) -> Self {
+        Fingerprint {
+            modified: Some(modified),
+            ..self
+        }
+    }
+
+    /// Returns the fingerprint with the passed inode number recorded.
+    pub fn with_inode(self, inode: u64) -> Self {
+        Fingerprint {
+            inode: Some(inode),
+            ..self
+        }
+    }
+
+    /// Returns the fingerprint with the passed number of hard links recorded.
+    /// A link count of zero means the source has been removed from the file
+    /// system, even though it may still be open.
+    pub fn with_links(self, links: u64) -> Self {
+        Fingerprint {
+            links: Some(links),
+            ..self
+        }
+    }
+
+    /// Returns the length of the source in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the source was empty, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the last modification time of the source, if known.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Returns the inode number of the source, if known.
+    pub fn inode(&self) -> Option<u64> {
+        self.inode
+    }
+
+    /// Returns the number of hard links to the source, if known.
+    pub fn links(&self) -> Option<u64> {
+        self.links
+    }
+
+    /// Returns true if the passed fingerprint describes the same unmodified
+    /// source, comparing only the fields that are known to both fingerprints.
+    pub fn matches(&self, other: &Fingerprint) -> bool {
+        fn same<V: PartialEq>(a: Option<V>, b: Option<V>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+        }
+        self.len == other.len
+            && same(self.modified, other.modified)
+            && same(self.inode, other.inode)
+    }
+
+    /// Returns true if the source described by `self` was linked into the
+    /// file system, but is no longer linked according to `current`.
+    pub(crate) fn removed_in(&self, current: &Fingerprint) -> bool {
+        match (self.links, current.links) {
+            (Some(before), Some(0)) => before != 0,
+            _ => false,
+        }
+    }
+}
+
+/// A function returning the fingerprint of a source, captured by a cache
+/// where the source is known to be `Fingerprinted`.
+pub(crate) type FingerprintFn<T> = fn(&mut T) -> Result<Fingerprint>;
+
+/// Cache sources that can report a `Fingerprint`.
+///
+/// The provided implementation of `Fingerprinted::fingerprint` only records
+/// the length of the source, which is determined by seeking to the end of
+/// the source. Any `std::io::Read` + `std::io::Seek` type can opt into
+/// `Cache::validate` by implementing this trait without overriding it.
+pub trait Fingerprinted: Read + Seek {
+    /// Returns the current fingerprint of the source.
+    fn fingerprint(&mut self) -> Result<Fingerprint> {
+        let pos = self.stream_position()?;
+        let len = self.seek(SeekFrom::End(0))?;
+        self.seek(SeekFrom::Start(pos))?;
+        Ok(Fingerprint::new(len))
+    }
+}
+
+impl Fingerprinted for File {
+    fn fingerprint(&mut self) -> Result<Fingerprint> {
+        let meta = self.metadata()?;
+        let mut fingerprint = Fingerprint::new(meta.len());
+        if let Ok(modified) = meta.modified() {
+            fingerprint = fingerprint.with_modified(modified);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            fingerprint = fingerprint.with_inode(meta.ino()).with_links(meta.nlink());
+        }
+        Ok(fingerprint)
+    }
+}
+
+impl<T: AsRef<[u8]>> Fingerprinted for Cursor<T> {
+    fn fingerprint(&mut self) -> Result<Fingerprint> {
+        Ok(Fingerprint::new(self.get_ref().as_ref().len() as u64))
+    }
+}
+
+impl<T: Fingerprinted> Fingerprinted for BufReader<T> {
+    fn fingerprint(&mut self) -> Result<Fingerprint> {
+        self.get_mut().fingerprint()
+    }
+}
+
+impl<C: Cache> Fingerprinted for CacheReader<C> {
+    fn fingerprint(&mut self) -> Result<Fingerprint> {
+        Ok(Fingerprint::new(self.cache().len()))
+    }
+}
@@ -1,9 +1,45 @@
-use super::Cache;
+use super::fingerprint::FingerprintFn;
+use super::{bounds, Cache, Error, Fingerprint, Fingerprinted, Operation, ResultExt};
 use std::io::{Read, Seek, SeekFrom};
-use std::ops::{Bound, RangeBounds};
+use std::ops::{Range, RangeBounds};
 
 use super::Result;
 
+const NAME: &str = "FullCache";
+
+/// Reads the entire source into a buffer, starting from byte zero.
+fn read_all<T: Read + Seek>(source: &mut T) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    source
+        .seek(SeekFrom::Start(0))
+        .context(NAME, Operation::Seek, Some(0..0))?;
+    if let Err(e) = source.read_to_end(&mut data) {
+        let pos = data.len() as u64;
+        return Err(e).context(NAME, Operation::Read, Some(pos..pos));
+    }
+    Ok(data)
+}
+
+/// Reads the entire source into a buffer, and returns it with the
+/// fingerprint of the source. The fingerprint is taken before reading, so
+/// that a modification made while reading is detected by the next call to
+/// `Cache::validate`. Without a fingerprint function, the fingerprint only
+/// records the length of the data.
+fn load<T: Read + Seek>(
+    source: &mut T,
+    fingerprinter: Option<FingerprintFn<T>>,
+) -> Result<(Vec<u8>, Fingerprint)> {
+    let fingerprint = match fingerprinter {
+        Some(fingerprinter) => {
+            Some(fingerprinter(source).context(NAME, Operation::Metadata, None)?)
+        }
+        None => None,
+    };
+    let data = read_all(source)?;
+    let fingerprint = fingerprint.unwrap_or_else(|| Fingerprint::new(data.len() as u64));
+    Ok((data, fingerprint))
+}
+
 /// A simple cache that reads the entire source into contiguous memory.
 ///
 /// `FullCache` reads the entire source into a buffer on creation and
@@ -19,16 +55,41 @@ use super::Result;
 pub struct FullCache<T: Read + Seek> {
     source: T,
     data: Vec<u8>,
+    fingerprint: Fingerprint,
+    fingerprinter: Option<FingerprintFn<T>>,
 }
 
 impl<T: Read + Seek> FullCache<T> {
-    /// Creates a new `FullCache` containing the passed source.
+    /// Creates a new `FullCache` containing the passed source. The
+    /// fingerprint of the source only records its length until the first
+    /// call to `Cache::validate`. See `FullCache::new_fingerprinted`.
     pub fn new(source: T) -> Result<Self> {
+        Self::with_fingerprinter(source, None)
+    }
+
+    pub(crate) fn with_fingerprinter(
+        source: T,
+        fingerprinter: Option<FingerprintFn<T>>,
+    ) -> Result<Self> {
         let mut source = source;
-        let mut data = Vec::new();
-        source.seek(SeekFrom::Start(0))?;
-        source.read_to_end(&mut data)?;
-        Ok(FullCache { source, data })
+        let (data, fingerprint) = load(&mut source, fingerprinter)?;
+        Ok(FullCache {
+            source,
+            data,
+            fingerprint,
+            fingerprinter,
+        })
+    }
+}
+
+impl<T: Fingerprinted> FullCache<T> {
+    /// Creates a new `FullCache` containing the passed source, and records
+    /// the full fingerprint of the source, so that the first call to
+    /// `Cache::validate` detects any modification made after construction.
+    /// Sources passed to `Cache::replace_source` are fingerprinted the same
+    /// way.
+    pub fn new_fingerprinted(source: T) -> Result<Self> {
+        Self::with_fingerprinter(source, Some(T::fingerprint))
     }
 }
 
@@ -37,10 +98,23 @@ impl<T: Read + Seek> Cache for FullCache<T> {
 
     fn into_inner(self) -> Result<T> {
         let mut source = self.source;
-        source.seek(SeekFrom::Start(0))?;
+        source
+            .seek(SeekFrom::Start(0))
+            .context(NAME, Operation::Seek, Some(0..0))?;
         Ok(source)
     }
 
+    fn replace_source(&mut self, source: T) -> Result<T> {
+        let mut source = source;
+        let (data, fingerprint) = load(&mut source, self.fingerprinter)?;
+        let mut old = std::mem::replace(&mut self.source, source);
+        self.fingerprint = fingerprint;
+        self.data = data;
+        old.seek(SeekFrom::Start(0))
+            .context(NAME, Operation::Seek, Some(0..0))?;
+        Ok(old)
+    }
+
     fn len(&self) -> u64 {
         self.data.len() as u64
     }
@@ -49,6 +123,30 @@ impl<T: Read + Seek> Cache for FullCache<T> {
         self.data.len()
     }
 
+    fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    fn validate(&mut self) -> Result<bool>
+    where
+        T: Fingerprinted,
+    {
+        self.fingerprinter = Some(T::fingerprint);
+        let current = self
+            .source
+            .fingerprint()
+            .context(NAME, Operation::Metadata, None)?;
+        if self.fingerprint.removed_in(&current) {
+            return Err(Error::SourceRemoved);
+        }
+        let changed = !self.fingerprint.matches(&current);
+        if changed {
+            self.data = read_all(&mut self.source)?;
+        }
+        self.fingerprint = current;
+        Ok(changed)
+    }
+
     fn traverse_chunks<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
         &self,
         range: R,
@@ -56,42 +154,18 @@ impl<T: Read + Seek> Cache for FullCache<T> {
     ) -> Result<()> {
         let mut f = f;
         let len = self.data.len() as u64;
-        let start = match range.start_bound() {
-            Bound::Included(start) => {
-                if *start >= len {
-                    return Ok(());
-                } else {
-                    *start
-                }
-            }
-            Bound::Excluded(start) => {
-                let start = *start + 1;
-                if start > len {
-                    return Ok(());
-                } else {
-                    start
-                }
-            }
-            Bound::Unbounded => 0,
-        };
-        let end = match range.end_bound() {
-            Bound::Included(end) => {
-                if *end >= len {
-                    len
-                } else {
-                    *end + 1
-                }
-            }
-            Bound::Excluded(end) => {
-                if *end > len {
-                    len
-                } else {
-                    *end
-                }
-            }
-            Bound::Unbounded => len,
-        };
-        f(&self.data[start as usize..end as usize])?;
+        let Range { start, end } = bounds(&range, len);
+        if start < end {
+            f(&self.data[start as usize..end as usize])?;
+        }
         Ok(())
     }
+
+    fn traverse_chunks_rev<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        self.traverse_chunks(range, f)
+    }
 }
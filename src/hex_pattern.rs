@@ -0,0 +1,297 @@
+use super::search::scan;
+use super::{bounds, Cache, Error, Result};
+use memchr::memmem::Finder;
+use std::ops::{Range, RangeBounds};
+use std::str::FromStr;
+
+/// A byte pattern with wildcards, as used to search binaries.
+///
+/// Each byte of the pattern has a value and a mask, and a byte matches when
+/// its bits selected by the mask equal those of the value. Patterns are
+/// usually parsed from text with `HexPattern::parse`, where each byte is
+/// written as two hexadecimal digits, and either digit may be `?` to match
+/// any value of that nibble:
+///
+/// ```text
+/// 48 8B ?? ?? 00 F?
+/// ```
+///
+/// A byte may also be followed by `/` and an explicit mask in hexadecimal,
+/// such as `40/F0`, which matches any byte whose high nibble is 4.
+/// Whitespace between bytes is optional.
+///
+/// Searching uses the longest run of fully specified bytes in the pattern
+/// as an anchor, so patterns with at least one fully specified byte are
+/// found quickly, and verifies the remaining bytes of each candidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexPattern {
+    values: Vec<u8>,
+    masks: Vec<u8>,
+    anchor: Range<usize>,
+}
+
+fn hex_digit(c: char) -> Option<u8> {
+    c.to_digit(16).map(|digit| digit as u8)
+}
+
+impl HexPattern {
+    /// Creates a pattern matching the passed bytes exactly.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        HexPattern::build(bytes.to_vec(), vec![0xFF; bytes.len()])
+    }
+
+    /// Creates a pattern from the passed values and masks. Returns
+    /// `Error::Pattern` if the slices differ in length.
+    pub fn with_masks(values: &[u8], masks: &[u8]) -> Result<Self> {
+        if values.len() != masks.len() {
+            return Err(Error::Pattern(format!(
+                "{} values but {} masks",
+                values.len(),
+                masks.len()
+            )));
+        }
+        let values = values.iter().zip(masks).map(|(v, m)| v & m).collect();
+        Ok(HexPattern::build(values, masks.to_vec()))
+    }
+
+    /// Parses a pattern from text, as described in the type documentation.
+    /// Returns `Error::Pattern` if the text is not a valid pattern.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut values = Vec::new();
+        let mut masks = Vec::new();
+        let mut chars = text
+            .char_indices()
+            .filter(|(_, c)| !c.is_whitespace())
+            .peekable();
+        let invalid = |idx: usize, c: char| {
+            Error::Pattern(format!(
+                "invalid character '{}' at byte {} of pattern",
+                c, idx
+            ))
+        };
+        while let Some((idx, high)) = chars.next() {
+            let (low_idx, low) = match chars.next() {
+                Some(low) => low,
+                None => {
+                    return Err(Error::Pattern(format!(
+                        "incomplete byte at byte {} of pattern",
+                        idx
+                    )))
+                }
+            };
+            let mut value = 0;
+            let mut mask = 0;
+            for (shift, idx, c) in [(4, idx, high), (0, low_idx, low)].iter().cloned() {
+                if c != '?' {
+                    value |= hex_digit(c).ok_or_else(|| invalid(idx, c))? << shift;
+                    mask |= 0xF << shift;
+                }
+            }
+            if let Some((_, '/')) = chars.peek() {
+                chars.next();
+                let mut explicit = 0;
+                for _ in 0..2 {
+                    match chars.next() {
+                        Some((idx, c)) => {
+                            explicit =
+                                (explicit << 4) | hex_digit(c).ok_or_else(|| invalid(idx, c))?
+                        }
+                        None => {
+                            return Err(Error::Pattern("incomplete mask at end of pattern".into()))
+                        }
+                    }
+                }
+                mask &= explicit;
+            }
+            values.push(value & mask);
+            masks.push(mask);
+        }
+        Ok(HexPattern::build(values, masks))
+    }
+
+    fn build(values: Vec<u8>, masks: Vec<u8>) -> Self {
+        let mut anchor = 0..0;
+        let mut start = 0;
+        for (idx, mask) in masks.iter().enumerate() {
+            if *mask != 0xFF {
+                start = idx + 1;
+            } else if idx + 1 - start > anchor.len() {
+                anchor = start..idx + 1;
+            }
+        }
+        HexPattern {
+            values,
+            masks,
+            anchor,
+        }
+    }
+
+    /// Returns the length of the pattern in bytes.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if the pattern has no bytes, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the values of the pattern's bytes. Bits not selected by the
+    /// masks are zero.
+    pub fn values(&self) -> &[u8] {
+        &self.values
+    }
+
+    /// Returns the masks of the pattern's bytes.
+    pub fn masks(&self) -> &[u8] {
+        &self.masks
+    }
+
+    /// Returns true if the passed bytes begin with a match of the pattern,
+    /// false otherwise.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() >= self.len()
+            && self
+                .values
+                .iter()
+                .zip(&self.masks)
+                .zip(bytes)
+                .all(|((value, mask), byte)| byte & mask == *value)
+    }
+
+    /// Returns the offset of the first match of the pattern that lies
+    /// entirely within the passed range of the cache, or `None` if there is
+    /// none. Matches that straddle chunk or page boundaries are found. An
+    /// empty pattern matches at the start of the range.
+    pub fn find<C: Cache + ?Sized, R: RangeBounds<u64>>(
+        &self,
+        cache: &C,
+        range: R,
+    ) -> Result<Option<u64>> {
+        self.find_in(cache, bounds(&range, cache.len()))
+    }
+
+    /// Returns an iterator over the offsets of the non-overlapping matches of
+    /// the pattern that lie entirely within the passed range of the cache, in
+    /// ascending order. See `HexPattern::find`.
+    pub fn find_iter<'a, C: Cache + ?Sized, R: RangeBounds<u64>>(
+        &'a self,
+        cache: &'a C,
+        range: R,
+    ) -> HexFindIter<'a, C> {
+        HexFindIter {
+            pattern: self,
+            cache,
+            range: bounds(&range, cache.len()),
+            done: false,
+        }
+    }
+
+    fn find_in<C: Cache + ?Sized>(&self, cache: &C, range: Range<u64>) -> Result<Option<u64>> {
+        let len = self.len();
+        if len == 0 {
+            return Ok(Some(range.start));
+        }
+        if range.end - range.start < len as u64 {
+            return Ok(None);
+        }
+        let finder = Finder::new(&self.values[self.anchor.clone()]);
+        scan(cache, range, len - 1, |offset, window| {
+            self.find_window(&finder, window)
+                .map(|idx| offset + idx as u64)
+        })
+    }
+
+    /// Returns the index of the first match entirely within the window.
+    fn find_window(&self, finder: &Finder, window: &[u8]) -> Option<usize> {
+        let len = self.len();
+        if window.len() < len {
+            return None;
+        }
+        if self.anchor.is_empty() {
+            return (0..=window.len() - len).find(|idx| self.matches(&window[*idx..]));
+        }
+        // An anchor found at `idx` past the anchor's offset in the pattern
+        // is a candidate match starting at `idx`. The search resumes one
+        // byte past each rejected candidate, since anchors may overlap.
+        let mut start = 0;
+        while let Some(found) = finder.find(&window[self.anchor.start + start..]) {
+            let idx = start + found;
+            if idx + len > window.len() {
+                return None;
+            }
+            if self.matches(&window[idx..]) {
+                return Some(idx);
+            }
+            start = idx + 1;
+        }
+        None
+    }
+}
+
+impl FromStr for HexPattern {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        HexPattern::parse(text)
+    }
+}
+
+impl std::fmt::Display for HexPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (idx, (value, mask)) in self.values.iter().zip(&self.masks).enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            let nibbles = [(mask >> 4, value >> 4), (mask & 0xF, value & 0xF)];
+            if nibbles.iter().all(|(mask, _)| *mask == 0 || *mask == 0xF) {
+                for (mask, value) in nibbles.iter() {
+                    if *mask == 0 {
+                        write!(f, "?")?;
+                    } else {
+                        write!(f, "{:X}", value)?;
+                    }
+                }
+            } else {
+                write!(f, "{:02X}/{:02X}", value, mask)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the offsets of the non-overlapping matches of a
+/// `HexPattern` in a cache.
+///
+/// This type is returned by `HexPattern::find_iter`. If an error occurs, it
+/// is returned and the iteration ends.
+pub struct HexFindIter<'a, C: Cache + ?Sized> {
+    pattern: &'a HexPattern,
+    cache: &'a C,
+    range: Range<u64>,
+    done: bool,
+}
+
+impl<'a, C: Cache + ?Sized> Iterator for HexFindIter<'a, C> {
+    type Item = Result<u64>;
+
+    fn next(&mut self) -> Option<Result<u64>> {
+        if self.done || self.range.start > self.range.end {
+            return None;
+        }
+        match self.pattern.find_in(self.cache, self.range.clone()) {
+            Ok(Some(offset)) => {
+                self.range.start = offset + self.pattern.len().max(1) as u64;
+                Some(Ok(offset))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
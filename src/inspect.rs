@@ -0,0 +1,266 @@
+use super::{Cache, Endian, Error, Result};
+use std::convert::TryInto;
+
+/// The number of bytes read by `inspect`, which is the size of a GUID.
+const INSPECT_LEN: usize = 16;
+
+/// The number of seconds between 1601-01-01, the epoch of FILETIME, and
+/// 1970-01-01, the Unix epoch.
+const FILETIME_UNIX_DIFF: i64 = 11_644_473_600;
+
+macro_rules! typed_get {
+    ($($name:ident, $t:ty;)*) => {
+        $(
+            #[doc = concat!(
+                "Returns the bytes as a `", stringify!($t), "` in the passed",
+                " byte order, or `None` if too few bytes were available."
+            )]
+            pub fn $name(&self, endian: Endian) -> Option<$t> {
+                let bytes = self.array()?;
+                Some(match endian {
+                    Endian::Little => <$t>::from_le_bytes(bytes),
+                    Endian::Big => <$t>::from_be_bytes(bytes),
+                })
+            }
+        )*
+    };
+}
+
+/// A calendar date and time, as decoded by `Inspection`.
+///
+/// Unix and FILETIME timestamps are decoded in UTC. DOS timestamps have no
+/// time zone, and are usually in the local time of the machine that wrote
+/// them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DateTime {
+    /// The year, which may be negative or beyond 9999 for timestamps far
+    /// from the epoch.
+    pub year: i64,
+    /// The month, from 1 to 12.
+    pub month: u8,
+    /// The day of the month, from 1 to 31.
+    pub day: u8,
+    /// The hour, from 0 to 23.
+    pub hour: u8,
+    /// The minute, from 0 to 59.
+    pub minute: u8,
+    /// The second, from 0 to 59.
+    pub second: u8,
+    /// The fraction of the second in nanoseconds.
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    /// Creates a date and time from a number of seconds and nanoseconds
+    /// since the Unix epoch, in UTC.
+    pub fn from_unix(secs: i64, nanosecond: u32) -> Self {
+        let days = secs.div_euclid(86_400);
+        let time = secs.rem_euclid(86_400);
+        // The days are converted to a date with Howard Hinnant's
+        // `civil_from_days` algorithm.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        DateTime {
+            year: yoe + era * 400 + (month <= 2) as i64,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+            nanosecond,
+        }
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        if self.nanosecond != 0 {
+            write!(f, ".{:09}", self.nanosecond)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the bytes at an offset of a cache, and returns an `Inspection`
+/// that interprets them as every common type at once. Up to 16 bytes are
+/// read with a single call to `Cache::traverse_chunks`, fewer only at the
+/// end of the cache. Returns `Error::OutOfRange` if the offset is at or
+/// beyond the end of the cache.
+pub fn inspect<C: Cache + ?Sized>(cache: &C, offset: u64) -> Result<Inspection> {
+    let len = cache.len();
+    if offset >= len {
+        return Err(Error::OutOfRange { offset, len });
+    }
+    let mut bytes = [0; INSPECT_LEN];
+    let mut read = 0;
+    cache.traverse_chunks(offset..offset.saturating_add(INSPECT_LEN as u64), |chunk| {
+        bytes[read..read + chunk.len()].copy_from_slice(chunk);
+        read += chunk.len();
+        Ok(())
+    })?;
+    Ok(Inspection {
+        offset,
+        bytes,
+        len: read,
+    })
+}
+
+/// The bytes at an offset of a cache, interpreted as integers, floats,
+/// characters, timestamps and GUIDs, as shown by the data inspector of a
+/// hex editor.
+///
+/// This type is returned by `inspect`. Each interpretation starts at the
+/// first byte, and returns `None` if too few bytes were available near the
+/// end of the cache, or if the bytes are not a valid value of the type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Inspection {
+    offset: u64,
+    bytes: [u8; INSPECT_LEN],
+    len: usize,
+}
+
+impl Inspection {
+    /// Returns the offset that was inspected.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the bytes that were read, which are at most 16.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    fn array<const N: usize>(&self) -> Option<[u8; N]> {
+        self.bytes().get(..N)?.try_into().ok()
+    }
+
+    /// Returns the second pair of bytes as a `u16` in the passed byte order.
+    fn second_u16(&self, endian: Endian) -> Option<u16> {
+        let bytes = [*self.bytes().get(2)?, *self.bytes().get(3)?];
+        Some(match endian {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    /// Returns the first byte as a `u8`.
+    pub fn u8(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Returns the first byte as an `i8`.
+    pub fn i8(&self) -> i8 {
+        self.bytes[0] as i8
+    }
+
+    typed_get! {
+        u16, u16;
+        u32, u32;
+        u64, u64;
+        i16, i16;
+        i32, i32;
+        i64, i64;
+        f32, f32;
+        f64, f64;
+    }
+
+    /// Decodes a UTF-8 character, and returns it with the length of its
+    /// encoding in bytes.
+    pub fn utf8_char(&self) -> Option<(char, usize)> {
+        let len = match self.bytes[0] {
+            0x00..=0x7F => 1,
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return None,
+        };
+        let s = std::str::from_utf8(self.bytes().get(..len)?).ok()?;
+        s.chars().next().map(|c| (c, len))
+    }
+
+    /// Decodes a UTF-16 character in the passed byte order, and returns it
+    /// with the length of its encoding in bytes.
+    pub fn utf16_char(&self, endian: Endian) -> Option<(char, usize)> {
+        let high = self.u16(endian)?;
+        if !(0xD800..0xE000).contains(&high) {
+            return std::char::from_u32(high as u32).map(|c| (c, 2));
+        }
+        let low = self.second_u16(endian)?;
+        let c = std::char::decode_utf16([high, low].iter().cloned()).next()?;
+        c.ok().map(|c| (c, 4))
+    }
+
+    /// Decodes a 32 bit Unix timestamp, the signed number of seconds since
+    /// 1970-01-01 UTC.
+    pub fn unix_time32(&self, endian: Endian) -> Option<DateTime> {
+        self.i32(endian)
+            .map(|secs| DateTime::from_unix(secs as i64, 0))
+    }
+
+    /// Decodes a 64 bit Unix timestamp, the signed number of seconds since
+    /// 1970-01-01 UTC.
+    pub fn unix_time64(&self, endian: Endian) -> Option<DateTime> {
+        self.i64(endian).map(|secs| DateTime::from_unix(secs, 0))
+    }
+
+    /// Decodes a Windows FILETIME, the number of 100 nanosecond intervals
+    /// since 1601-01-01 UTC.
+    pub fn filetime(&self, endian: Endian) -> Option<DateTime> {
+        let ticks = self.u64(endian)?;
+        let secs = (ticks / 10_000_000) as i64 - FILETIME_UNIX_DIFF;
+        Some(DateTime::from_unix(secs, (ticks % 10_000_000) as u32 * 100))
+    }
+
+    /// Decodes an MS-DOS timestamp, as stored in FAT directory entries and
+    /// ZIP headers: a 16 bit time followed by a 16 bit date. Returns `None`
+    /// if any part of the date or time is out of range.
+    pub fn dos_time(&self, endian: Endian) -> Option<DateTime> {
+        let time = self.u16(endian)?;
+        let date = self.second_u16(endian)?;
+        let datetime = DateTime {
+            year: 1980 + (date >> 9) as i64,
+            month: (date >> 5 & 0xF) as u8,
+            day: (date & 0x1F) as u8,
+            hour: (time >> 11) as u8,
+            minute: (time >> 5 & 0x3F) as u8,
+            second: (time & 0x1F) as u8 * 2,
+            nanosecond: 0,
+        };
+        let valid = (1..=12).contains(&datetime.month)
+            && datetime.day >= 1
+            && datetime.hour < 24
+            && datetime.minute < 60
+            && datetime.second < 60;
+        Some(datetime).filter(|_| valid)
+    }
+
+    /// Formats the 16 bytes as a GUID, such as
+    /// `{00112233-4455-6677-8899-AABBCCDDEEFF}`. With `Endian::Little`, the
+    /// first three groups are little-endian, as GUIDs are stored by Windows.
+    /// With `Endian::Big`, the bytes are in order, as UUIDs are stored by
+    /// RFC 4122.
+    pub fn guid(&self, endian: Endian) -> Option<String> {
+        let mut b: [u8; INSPECT_LEN] = self.array()?;
+        if endian == Endian::Little {
+            b[..4].reverse();
+            b[4..6].reverse();
+            b[6..8].reverse();
+        }
+        Some(format!(
+            "{{{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+            b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7], b[8], b[9], b[10], b[11], b[12],
+            b[13], b[14], b[15]
+        ))
+    }
+}
@@ -0,0 +1,310 @@
+use super::checksum::crc32;
+use super::{Error, Fingerprint, Operation, Result, ResultExt};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Journal errors are reported as errors of the `EditCache` writing it.
+const NAME: &str = "EditCache";
+
+const MAGIC: &[u8; 8] = b"HXCJRNL1";
+
+/// The length of the header: the magic number, the fingerprint of the base,
+/// and the checksum of both.
+const HEADER_LEN: usize = 49;
+
+/// The length of the length and checksum preceding each record.
+const RECORD_HEAD_LEN: u64 = 12;
+
+const MODIFIED: u8 = 1;
+const INODE: u8 = 2;
+const LINKS: u8 = 4;
+
+/// An edit applied to an `EditCache`, as stored in its journal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Record<'a> {
+    Overwrite(u64, &'a [u8]),
+    Insert(u64, &'a [u8]),
+    Delete(u64, u64),
+    Revert,
+    BeginGroup,
+    EndGroup,
+    Undo,
+    Redo,
+    HistoryLimit(u64),
+    HistorySizeLimit(u64),
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(buf)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(buf)
+}
+
+impl<'a> Record<'a> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match *self {
+            Record::Overwrite(offset, bytes) | Record::Insert(offset, bytes) => {
+                buf.push(if let Record::Overwrite(..) = self {
+                    1
+                } else {
+                    2
+                });
+                buf.extend_from_slice(&offset.to_le_bytes());
+                buf.extend_from_slice(bytes);
+            }
+            Record::Delete(start, end) => {
+                buf.push(3);
+                buf.extend_from_slice(&start.to_le_bytes());
+                buf.extend_from_slice(&end.to_le_bytes());
+            }
+            Record::Revert => buf.push(4),
+            Record::BeginGroup => buf.push(5),
+            Record::EndGroup => buf.push(6),
+            Record::Undo => buf.push(7),
+            Record::Redo => buf.push(8),
+            Record::HistoryLimit(limit) => {
+                buf.push(9);
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+            Record::HistorySizeLimit(limit) => {
+                buf.push(10);
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+        }
+    }
+
+    fn decode(payload: &'a [u8]) -> Option<Self> {
+        let (op, args) = payload.split_first()?;
+        let record = match (*op, args.len()) {
+            (1, len) if len >= 8 => Record::Overwrite(read_u64(args), &args[8..]),
+            (2, len) if len >= 8 => Record::Insert(read_u64(args), &args[8..]),
+            (3, 16) => Record::Delete(read_u64(args), read_u64(&args[8..])),
+            (4, 0) => Record::Revert,
+            (5, 0) => Record::BeginGroup,
+            (6, 0) => Record::EndGroup,
+            (7, 0) => Record::Undo,
+            (8, 0) => Record::Redo,
+            (9, 8) => Record::HistoryLimit(read_u64(args)),
+            (10, 8) => Record::HistorySizeLimit(read_u64(args)),
+            _ => return None,
+        };
+        Some(record)
+    }
+}
+
+fn encode_header(fingerprint: &Fingerprint) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    let mut flags = 0;
+    header[..8].copy_from_slice(MAGIC);
+    header[8..16].copy_from_slice(&fingerprint.len().to_le_bytes());
+    if let Some(modified) = fingerprint
+        .modified()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+    {
+        flags |= MODIFIED;
+        header[17..25].copy_from_slice(&modified.as_secs().to_le_bytes());
+        header[25..29].copy_from_slice(&modified.subsec_nanos().to_le_bytes());
+    }
+    if let Some(inode) = fingerprint.inode() {
+        flags |= INODE;
+        header[29..37].copy_from_slice(&inode.to_le_bytes());
+    }
+    if let Some(links) = fingerprint.links() {
+        flags |= LINKS;
+        header[37..45].copy_from_slice(&links.to_le_bytes());
+    }
+    header[16] = flags;
+    let crc = crc32(&header[..45]);
+    header[45..].copy_from_slice(&crc.to_le_bytes());
+    header
+}
+
+fn decode_header(header: &[u8; HEADER_LEN]) -> Option<Fingerprint> {
+    if &header[..8] != MAGIC || crc32(&header[..45]) != read_u32(&header[45..]) {
+        return None;
+    }
+    let flags = header[16];
+    let mut fingerprint = Fingerprint::new(read_u64(&header[8..]));
+    if flags & MODIFIED != 0 {
+        let since_epoch = Duration::new(read_u64(&header[17..]), read_u32(&header[25..]));
+        fingerprint = fingerprint.with_modified(UNIX_EPOCH + since_epoch);
+    }
+    if flags & INODE != 0 {
+        fingerprint = fingerprint.with_inode(read_u64(&header[29..]));
+    }
+    if flags & LINKS != 0 {
+        fingerprint = fingerprint.with_links(read_u64(&header[37..]));
+    }
+    Some(fingerprint)
+}
+
+/// An append-only file of the edits applied to an `EditCache`.
+///
+/// The journal starts with a header recording the fingerprint of the base
+/// source, followed by one record per edit. Each record is preceded by its
+/// length and CRC-32 checksum, so that a record torn by a crash is detected
+/// and recovery stops at the last complete record. Records are written
+/// without buffering as the edits are made, so they survive the process
+/// crashing, but they are only guaranteed to survive the system crashing
+/// after `Journal::sync`.
+pub(crate) struct Journal {
+    file: File,
+    buf: Vec<u8>,
+    error: Option<std::io::Error>,
+}
+
+impl Journal {
+    /// Creates the journal file at `path`, truncating any existing file, and
+    /// writes the header for a base with the passed fingerprint.
+    pub(crate) fn create(path: &Path, fingerprint: &Fingerprint) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .context(NAME, Operation::Open, None)?;
+        let mut journal = Journal {
+            file,
+            buf: Vec::new(),
+            error: None,
+        };
+        journal.restart(fingerprint)?;
+        Ok(journal)
+    }
+
+    /// Opens the journal file at `path`, checks that it was written against a
+    /// base with the passed fingerprint, and calls `f` on each complete
+    /// record in order. Any torn record at the end of the journal is
+    /// discarded, and the returned journal appends after the last complete
+    /// record.
+    pub(crate) fn replay<F: FnMut(Record) -> Result<()>>(
+        path: &Path,
+        fingerprint: &Fingerprint,
+        f: F,
+    ) -> Result<Self> {
+        let mut f = f;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .context(NAME, Operation::Open, None)?;
+        let len = file
+            .metadata()
+            .context(NAME, Operation::Metadata, None)?
+            .len();
+        if len < HEADER_LEN as u64 {
+            return Err(Error::Journal("journal header is missing"));
+        }
+        let mut reader = BufReader::new(&file);
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header).context(
+            NAME,
+            Operation::Read,
+            Some(0..HEADER_LEN as u64),
+        )?;
+        match decode_header(&header) {
+            Some(recorded) if recorded.matches(fingerprint) => {}
+            Some(_) => {
+                return Err(Error::Journal(
+                    "base was modified after the journal was written",
+                ))
+            }
+            None => return Err(Error::Journal("journal header is corrupt")),
+        }
+        let mut pos = HEADER_LEN as u64;
+        let mut payload = Vec::new();
+        while len - pos >= RECORD_HEAD_LEN {
+            let mut head = [0; RECORD_HEAD_LEN as usize];
+            let range = pos..pos + RECORD_HEAD_LEN;
+            reader
+                .read_exact(&mut head)
+                .context(NAME, Operation::Read, Some(range))?;
+            let size = read_u64(&head);
+            if size > len - pos - RECORD_HEAD_LEN {
+                break;
+            }
+            let range = pos + RECORD_HEAD_LEN..pos + RECORD_HEAD_LEN + size;
+            payload.resize(size as usize, 0);
+            reader
+                .read_exact(&mut payload)
+                .context(NAME, Operation::Read, Some(range))?;
+            if crc32(&payload) != read_u32(&head[8..]) {
+                break;
+            }
+            match Record::decode(&payload) {
+                Some(record) => f(record)?,
+                None => break,
+            }
+            pos += RECORD_HEAD_LEN + size;
+        }
+        drop(reader);
+        if pos < len {
+            file.set_len(pos)
+                .context(NAME, Operation::Write, Some(pos..len))?;
+        }
+        file.seek(SeekFrom::Start(pos))
+            .context(NAME, Operation::Seek, Some(pos..pos))?;
+        Ok(Journal {
+            file,
+            buf: payload,
+            error: None,
+        })
+    }
+
+    /// Discards all records, and writes a new header for a base with the
+    /// passed fingerprint.
+    pub(crate) fn restart(&mut self, fingerprint: &Fingerprint) -> Result<()> {
+        self.error = None;
+        self.file.set_len(0).context(NAME, Operation::Write, None)?;
+        self.file
+            .seek(SeekFrom::Start(0))
+            .context(NAME, Operation::Seek, Some(0..0))?;
+        self.file.write_all(&encode_header(fingerprint)).context(
+            NAME,
+            Operation::Write,
+            Some(0..HEADER_LEN as u64),
+        )?;
+        self.file.sync_data().context(NAME, Operation::Sync, None)
+    }
+
+    /// Appends a record to the journal. If writing fails, the failure is
+    /// kept and reported by `Journal::sync`, and no further records are
+    /// written, since the journal could not be replayed past the gap.
+    pub(crate) fn append(&mut self, record: &Record) {
+        if self.error.is_some() {
+            return;
+        }
+        self.buf.clear();
+        self.buf.resize(RECORD_HEAD_LEN as usize, 0);
+        record.encode(&mut self.buf);
+        let size = self.buf.len() as u64 - RECORD_HEAD_LEN;
+        let crc = crc32(&self.buf[RECORD_HEAD_LEN as usize..]);
+        self.buf[..8].copy_from_slice(&size.to_le_bytes());
+        self.buf[8..12].copy_from_slice(&crc.to_le_bytes());
+        if let Err(e) = self.file.write_all(&self.buf) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flushes the journal to disk. Returns the failure of any earlier
+    /// append, in which case the journal is incomplete.
+    pub(crate) fn sync(&mut self) -> Result<()> {
+        if let Some(ref e) = self.error {
+            return Err(std::io::Error::new(e.kind(), e.to_string())).context(
+                NAME,
+                Operation::Write,
+                None,
+            );
+        }
+        self.file.sync_data().context(NAME, Operation::Sync, None)
+    }
+}
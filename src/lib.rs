@@ -13,36 +13,230 @@
 //!
 //! This crate additionally provides the `CacheReader` type, which wraps a
 //! cache and implements `std::io::Read` and `std::io::Seek`.
+//!
+//! Sources that implement `Fingerprinted` can be checked for modification
+//! with `Cache::validate`, which refreshes the cache when the source has
+//! changed since it was last read. On Linux, the `watch` feature adds
+//! `WatchedCache`, which uses inotify to refresh a cache over a file
+//! automatically when the file is changed on disk.
+//!
+//! `EditCache` layers a copy-on-write piece table over any cache, so that
+//! the cached content can be edited without modifying the source. Edits can
+//! be recorded in a journal file and recovered after a crash.
+//!
+//! `Cache::digest` computes CRC-32, Adler-32, MD5, SHA-1, SHA-256 and XXH64
+//! checksums of any range of a cache, and the `digest` feature adds
+//! `Cache::digest_with` for any hash function implementing the RustCrypto
+//! `digest::Digest` trait.
+//!
+//! `CacheCursor` reads integers, floats, LEB128 varints and strings from a
+//! cache in sequence, and `ReadAt` reads them at arbitrary offsets of any
+//! cache. `BitReader` reads values at bit granularity, in either bit order.
+//! `inspect` interprets the bytes at an offset as integers, floats,
+//! characters, timestamps and GUIDs at once, for a data inspector.
+//!
+//! `Cache::find` and `Cache::find_iter` search any cache for a byte pattern,
+//! including matches that straddle chunk or page boundaries, and
+//! `Cache::rfind` searches backward using `Cache::traverse_chunks_rev`.
+//! `HexPattern` searches for byte patterns with wildcards and masks. The
+//! `regex` feature adds `RegexSearch`, which searches any cache with a
+//! `regex::bytes::Regex`.
+//!
+//! `diff` compares two caches of any size in bounded memory, reporting the
+//! ranges that are equal, changed, inserted or deleted.
+//!
+//! `LineIndex` records the offsets of lines of text in a sparse table of
+//! checkpoints, built incrementally, so that large logs can be navigated by
+//! line number without scanning from the start.
+//!
+//! `Strings` extracts runs of printable characters in ASCII, UTF-8 and
+//! UTF-16, like the `strings` utility. `decode` and `DecodeChars` decode a
+//! range as text in UTF-8, UTF-16, Latin-1 or Windows-1252, including
+//! characters that straddle chunk boundaries, and report malformed bytes
+//! with their offsets.
+//!
+//! `Template` describes the layout of binary formats with structs, arrays,
+//! enums and conditional fields, built in Rust or parsed from a small text
+//! format, and lays it over a cache as a lazily evaluated tree of `Field`
+//! values with offsets and sizes.
+//!
+//! `Histogram` counts the byte values of a range, and `EntropySeries`
+//! computes the entropy of each fixed-size block of a range, which reveals
+//! compressed and encrypted regions.
+//!
+//! Long running traversals can be cancelled with a `CancellationToken` and
+//! report their progress through `Cache::traverse_chunks_with`.
 
-#![cfg_attr(feature = "cargo-clippy", deny(clippy::all))]
+#![deny(clippy::all)]
 #![deny(warnings)]
 
+mod analysis;
 mod auto_cache;
+mod bit_reader;
 mod cache_reader;
+mod cancel;
+mod checksum;
+mod cursor;
+mod decode;
+mod diff;
+mod edit_cache;
+mod fingerprint;
 mod full_cache;
+mod hex_pattern;
+mod inspect;
+mod journal;
+mod line_index;
+#[cfg(feature = "regex")]
+mod regex_search;
+mod search;
+mod strings;
 mod swap_cache;
+mod template;
+mod template_parser;
+#[cfg(all(feature = "watch", target_os = "linux"))]
+mod watch;
 
 #[cfg(test)]
 mod tests;
 
+pub use analysis::{EntropySeries, Histogram};
 pub use auto_cache::AutoCache;
+pub use bit_reader::{BitOrder, BitReader};
 pub use cache_reader::CacheReader;
+pub use cancel::CancellationToken;
+pub use checksum::{Algorithm, Checksum, Hasher};
+pub use cursor::{CacheCursor, ReadAt};
+pub use decode::{decode, DecodeChars, DecodedChar, TextChunk};
+pub use diff::{diff, Diff, DiffRange};
+pub use edit_cache::{EditCache, Gravity, Mark};
+pub use fingerprint::{Fingerprint, Fingerprinted};
 pub use full_cache::FullCache;
-pub use swap_cache::SwapCache;
+pub use hex_pattern::{HexFindIter, HexPattern};
+pub use inspect::{inspect, DateTime, Inspection};
+pub use line_index::{LineIndex, Lines};
+#[cfg(feature = "regex")]
+pub use regex_search::{RegexFindIter, RegexMatch, RegexSearch};
+pub use search::FindIter;
+pub use strings::{Encoding, Strings};
+pub use swap_cache::{SwapCache, WritePolicy};
+pub use template::{
+    Endian, EnumDef, Expr, Field, FieldType, Primitive, StructDef, Template, Value,
+};
+#[cfg(all(feature = "watch", target_os = "linux"))]
+pub use watch::{Change, WatchedCache};
 
 use std::convert::From;
 use std::io::{Read, Seek};
-use std::ops::RangeBounds;
+use std::ops::{Bound, Range, RangeBounds};
+
+/// The operation on a cache source that caused an IO error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Opening a file to be used as a source.
+    Open,
+    /// Seeking within the source.
+    Seek,
+    /// Reading data from the source.
+    Read,
+    /// Writing data to a file or source.
+    Write,
+    /// Flushing written data to disk.
+    Sync,
+    /// Renaming a file over another file.
+    Rename,
+    /// Querying metadata of the source, such as its fingerprint.
+    Metadata,
+    /// Watching the source for changes.
+    Watch,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Operation::Open => "open",
+            Operation::Seek => "seek",
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::Sync => "sync",
+            Operation::Rename => "rename",
+            Operation::Metadata => "query metadata",
+            Operation::Watch => "watch",
+        })
+    }
+}
+
+/// Describes where an IO error occurred.
+///
+/// A context records the cache type that encountered the error, the
+/// operation being performed on the source, and the byte range of the
+/// source that the operation applied to, if any. For seeks, the range is
+/// empty and starts at the target offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    cache: &'static str,
+    operation: Operation,
+    range: Option<Range<u64>>,
+}
+
+impl Context {
+    /// Creates a new `Context`.
+    pub fn new(cache: &'static str, operation: Operation, range: Option<Range<u64>>) -> Self {
+        Context {
+            cache,
+            operation,
+            range,
+        }
+    }
+
+    /// Returns the name of the cache type that encountered the error.
+    pub fn cache(&self) -> &'static str {
+        self.cache
+    }
+
+    /// Returns the operation that failed.
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// Returns the byte range of the source the failed operation applied
+    /// to, if any.
+    pub fn range(&self) -> Option<Range<u64>> {
+        self.range.clone()
+    }
+}
+
+impl std::fmt::Display for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} failed to {}", self.cache, self.operation)?;
+        match self.range {
+            Some(ref range) if range.start == range.end => write!(f, " at byte {}", range.start),
+            Some(ref range) => write!(f, " bytes {}..{}", range.start, range.end),
+            None => Ok(()),
+        }
+    }
+}
 
 #[derive(Debug)]
+#[non_exhaustive]
 /// Error type for `hxcvtr-file-cache`
 ///
-/// Errors can be either an IO error, a mutex poison error, or a zero cache error.
+/// Errors can be either an IO error, a mutex poison error, a zero cache error, a
+/// source removed error, an out of range error, a cancelled error, a journal
+/// error, a pattern error, a malformed data error, an invalid argument error,
+/// or a template error. More kinds of errors may be added in the future.
+///
+/// An IO error with a `Context` displays the context, and returns the
+/// underlying `std::io::Error` from `std::error::Error::source`. IO errors
+/// without a context and user errors display the wrapped error itself, and
+/// return its source.
 pub enum Error {
     /// Error emitted by `std::io::Read::read` or `std::io::Seek::seek`. These errors
     /// indicate that a problem was encountered reading the cache source. See the
-    /// standard library documentation for more information.
-    IO(std::io::Error),
+    /// standard library documentation for more information. Errors encountered
+    /// by the caches in this crate carry a `Context` describing the operation
+    /// and byte range that failed.
+    IO(std::io::Error, Option<Context>),
 
     /// Error emitted by `std::sync::Mutex::lock`. Swap cache provides thread safe
     /// interior mutability by wrapping its primary functionality within a mutex.
@@ -56,6 +250,58 @@ pub enum Error {
     /// memory.
     ZeroCache(&'static str),
 
+    /// This error indicates that the source of the cache was removed from the
+    /// file system. It is emitted by `Cache::validate` when the fingerprint of
+    /// the source reports that it is no longer linked into the file system. The
+    /// cached data is left as it was before the call to `Cache::validate`.
+    SourceRemoved,
+
+    /// This error indicates that an offset passed to an editing method, such
+    /// as `EditCache::insert`, is beyond the end of the content, which is `len`
+    /// bytes long. It is also emitted by `ReadAt` and `CacheCursor` when a value
+    /// extends past the end of the cache, in which case `offset` is the offset
+    /// of the first missing byte.
+    OutOfRange {
+        /// The offset that was passed.
+        offset: u64,
+        /// The length of the content at the time of the call.
+        len: u64,
+    },
+
+    /// This error indicates that a traversal was stopped because its
+    /// `CancellationToken` was cancelled. See `Cache::traverse_chunks_with`.
+    Cancelled,
+
+    /// This error indicates that an edit journal could not be recovered,
+    /// either because it is not a journal, or because the base it records
+    /// edits against has been modified since the journal was written. See
+    /// `EditCache::recover`.
+    Journal(&'static str),
+
+    /// This error indicates that a search pattern, such as a `HexPattern`, is
+    /// invalid. The message describes the problem.
+    Pattern(String),
+
+    /// This error indicates that data read from a cache is not a valid value of
+    /// the type being read, such as a LEB128 varint that overflows 64 bits.
+    /// `offset` is the offset of the first byte of the value.
+    Malformed {
+        /// The offset of the value.
+        offset: u64,
+        /// A description of the problem.
+        msg: &'static str,
+    },
+
+    /// This error indicates that an argument passed to a function is outside
+    /// the values it accepts, such as a `Histogram` or `EntropySeries`
+    /// computed over blocks of zero bytes. The message describes the problem.
+    InvalidArgument(&'static str),
+
+    /// This error indicates that a `Template` is invalid, either because its
+    /// text cannot be parsed or because it cannot be evaluated, such as when
+    /// it refers to an undefined field. The message describes the problem.
+    Template(String),
+
     /// This error is only generated by the user. Primarily, this error should
     /// be returned by the closure passed into `Cache::traverse_chunks` when
     /// the traversal needs to abort early, whether due to an error or not.
@@ -64,14 +310,23 @@ pub enum Error {
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Error::IO(e)
+        Error::IO(e, None)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::IO(e, None) => e,
+            Error::IO(ref io, Some(_)) => std::io::Error::new(io.kind(), e),
+            e => std::io::Error::other(e),
+        }
     }
 }
 
 impl<T> From<std::sync::PoisonError<T>> for Error {
     fn from(e: std::sync::PoisonError<T>) -> Self {
-        use std::error::Error;
-        self::Error::Poison(std::string::String::from(e.description()))
+        Error::Poison(e.to_string())
     }
 }
 
@@ -80,36 +335,79 @@ impl Error {
         Error::ZeroCache(msg)
     }
 
-    /// Returns true if the error is an IO error, false otherwise.
-    pub fn is_io_error(&self) -> bool {
+    /// Attaches a context to an IO error that does not have one yet. Other
+    /// errors are returned unchanged.
+    fn or_context(
+        self,
+        cache: &'static str,
+        operation: Operation,
+        range: Option<Range<u64>>,
+    ) -> Self {
         match self {
-            Error::IO(_) => true,
-            _ => false,
+            Error::IO(e, None) => Error::IO(e, Some(Context::new(cache, operation, range))),
+            e => e,
         }
     }
 
+    /// Returns true if the error is an IO error, false otherwise.
+    pub fn is_io_error(&self) -> bool {
+        matches!(self, Error::IO(..))
+    }
+
     /// Returns true if the error is a poison error, false otherwise.
     pub fn is_poison_error(&self) -> bool {
-        match self {
-            Error::Poison(_) => true,
-            _ => false,
-        }
+        matches!(self, Error::Poison(_))
     }
 
     /// Returns true if the error is a zero cache error, false otherwise.
     pub fn is_zero_cache_error(&self) -> bool {
-        match self {
-            Error::ZeroCache(_) => true,
-            _ => false,
-        }
+        matches!(self, Error::ZeroCache(_))
+    }
+
+    /// Returns true if the error is a source removed error, false otherwise.
+    pub fn is_source_removed_error(&self) -> bool {
+        matches!(self, Error::SourceRemoved)
+    }
+
+    /// Returns true if the error is an out of range error, false otherwise.
+    pub fn is_out_of_range_error(&self) -> bool {
+        matches!(self, Error::OutOfRange { .. })
+    }
+
+    /// Returns true if the error is a cancelled error, false otherwise.
+    pub fn is_cancelled_error(&self) -> bool {
+        matches!(self, Error::Cancelled)
+    }
+
+    /// Returns true if the error is a journal error, false otherwise.
+    pub fn is_journal_error(&self) -> bool {
+        matches!(self, Error::Journal(_))
+    }
+
+    /// Returns true if the error is a pattern error, false otherwise.
+    pub fn is_pattern_error(&self) -> bool {
+        matches!(self, Error::Pattern(_))
+    }
+
+    /// Returns true if the error is a malformed data error, false otherwise.
+    pub fn is_malformed_error(&self) -> bool {
+        matches!(self, Error::Malformed { .. })
+    }
+
+    /// Returns true if the error is an invalid argument error, false
+    /// otherwise.
+    pub fn is_invalid_argument_error(&self) -> bool {
+        matches!(self, Error::InvalidArgument(_))
+    }
+
+    /// Returns true if the error is a template error, false otherwise.
+    pub fn is_template_error(&self) -> bool {
+        matches!(self, Error::Template(_))
     }
 
     /// Returns true if the error is an other error, false otherwise.
     pub fn is_other_error(&self) -> bool {
-        match self {
-            Error::Other(_) => true,
-            _ => false,
-        }
+        matches!(self, Error::Other(_))
     }
 
     /// Allow the user to create an error for `Cache::traverse_chunks`.
@@ -117,16 +415,24 @@ impl Error {
         Error::Other(Box::new(e))
     }
 
+    /// Returns the context of an IO error, if any.
+    pub fn context(&self) -> Option<&Context> {
+        match self {
+            Error::IO(_, context) => context.as_ref(),
+            _ => None,
+        }
+    }
+
     /// Consume the error and return the contained `std::io::Error`, if any.
     pub fn into_io_error(self) -> Option<std::io::Error> {
         match self {
-            Error::IO(e) => Some(e),
+            Error::IO(e, _) => Some(e),
             _ => None,
         }
     }
 
     /// Consume the error and return the contained other error, if any.
-    pub fn into_other_error(self) -> Option<Box<std::error::Error + Send + Sync>> {
+    pub fn into_other_error(self) -> Option<Box<dyn std::error::Error + Send + Sync>> {
         match self {
             Error::Other(e) => Some(e),
             _ => None,
@@ -134,14 +440,38 @@ impl Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IO(e, Some(_)) => Some(e),
+            Error::IO(e, None) => e.source(),
+            Error::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Error::IO(e) => e.fmt(f),
+            Error::IO(e, None) => e.fmt(f),
+            Error::IO(_, Some(context)) => context.fmt(f),
             Error::Poison(msg) => write!(f, "Poison Error: {}", msg),
             Error::ZeroCache(msg) => write!(f, "Zero Cache Error: {}", msg),
+            Error::SourceRemoved => write!(f, "Source Removed Error: source no longer exists"),
+            Error::OutOfRange { offset, len } => write!(
+                f,
+                "Out Of Range Error: offset {} is beyond the end of {} bytes",
+                offset, len
+            ),
+            Error::Cancelled => write!(f, "Cancelled Error: traversal was cancelled"),
+            Error::Journal(msg) => write!(f, "Journal Error: {}", msg),
+            Error::Pattern(msg) => write!(f, "Pattern Error: {}", msg),
+            Error::Malformed { offset, msg } => {
+                write!(f, "Malformed Data Error: {} at byte {}", msg, offset)
+            }
+            Error::InvalidArgument(msg) => write!(f, "Invalid Argument Error: {}", msg),
+            Error::Template(msg) => write!(f, "Template Error: {}", msg),
             Error::Other(e) => e.fmt(f),
         }
     }
@@ -150,9 +480,55 @@ impl std::fmt::Display for Error {
 /// A `std::result::Result` with `hxcvtr_file_cache::Error` as the error type.
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[allow(clippy::len_without_is_empty)]
+/// Attaches a `Context` to the IO errors of results within this crate.
+trait ResultExt<T> {
+    fn context(
+        self,
+        cache: &'static str,
+        operation: Operation,
+        range: Option<Range<u64>>,
+    ) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn context(
+        self,
+        cache: &'static str,
+        operation: Operation,
+        range: Option<Range<u64>>,
+    ) -> Result<T> {
+        self.map_err(|e| e.into().or_context(cache, operation, range))
+    }
+}
+
+/// The largest chunk passed to the closure of `Cache::traverse_chunks_with`.
+const PROGRESS_STEP: usize = 1 << 20;
+
+/// The size of the windows copied by the provided implementation of
+/// `Cache::traverse_chunks_rev`.
+const REV_STEP: usize = 64 * 1024;
+
+/// Resolves a range of byte offsets against a source of `len` bytes. The
+/// returned range is clamped to the source, and is empty if the passed range
+/// does not overlap the source.
+fn bounds<R: RangeBounds<u64>>(range: &R, len: u64) -> Range<u64> {
+    let start = match range.start_bound() {
+        Bound::Included(start) => *start,
+        Bound::Excluded(start) => start.saturating_add(1),
+        Bound::Unbounded => 0,
+    }
+    .min(len);
+    let end = match range.end_bound() {
+        Bound::Included(end) => end.saturating_add(1),
+        Bound::Excluded(end) => *end,
+        Bound::Unbounded => len,
+    }
+    .min(len);
+    start..end.max(start)
+}
 
 /// The common interface for the cache types in this crate.
+#[allow(clippy::len_without_is_empty)]
 pub trait Cache {
     /// The type of the source that is begin cached.
     type Source: Read + Seek;
@@ -160,6 +536,21 @@ pub trait Cache {
     /// Destroys the cache and returns the contained source.
     fn into_inner(self) -> Result<Self::Source>;
 
+    /// Replaces the source of the cache with the passed source and returns the
+    /// previous source. All cached data is discarded, and the cache behaves as
+    /// if it were newly constructed with the passed source, while keeping its
+    /// configuration. The returned source is positioned at byte zero.
+    ///
+    /// The provided implementation returns an `std::io::ErrorKind::Unsupported`
+    /// IO error, for caches that cannot change their source.
+    fn replace_source(&mut self, source: Self::Source) -> Result<Self::Source> {
+        let _ = source;
+        Err(Error::from(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "cache does not support replacing its source",
+        )))
+    }
+
     /// Returns the length of underlying source in bytes.
     fn len(&self) -> u64;
 
@@ -169,6 +560,40 @@ pub trait Cache {
     /// management.
     fn cache_size(&self) -> usize;
 
+    /// Returns the fingerprint of the source recorded at the last call to
+    /// `Cache::validate`. Until the first call, the fingerprint is the one
+    /// recorded at construction, which only records the length of the source
+    /// unless the cache was created with a `new_fingerprinted` constructor.
+    ///
+    /// The provided implementation returns a fingerprint of the current
+    /// length of the cache.
+    fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::new(self.len())
+    }
+
+    /// Checks the source for modification since it was last read, and
+    /// invalidates all cached data if the source has changed. Returns true
+    /// if the source was modified, in which case `Cache::len` reflects the
+    /// new length of the source, and false otherwise.
+    ///
+    /// The cache compares the current fingerprint of the source against the
+    /// fingerprint recorded at the previous call to `validate`, or at
+    /// construction. Caches created with `new` only know the length of the
+    /// source until the first call, so callers that need to detect every
+    /// modification should create the cache with `new_fingerprinted`, or call
+    /// `validate` once immediately after constructing it. If the source has
+    /// been removed from the file system, `Error::SourceRemoved` is returned
+    /// and the cached data is left untouched.
+    ///
+    /// The provided implementation always returns false, for caches that
+    /// cannot observe their source.
+    fn validate(&mut self) -> Result<bool>
+    where
+        Self::Source: Fingerprinted,
+    {
+        Ok(false)
+    }
+
     /// Calls a closure on a series of memory chunks that cover the passed
     /// range, where the range represents the start and end byte offsets
     /// into the source. Chunks passed to the closure are guaranteed to be
@@ -181,6 +606,78 @@ pub trait Cache {
         f: F,
     ) -> Result<()>;
 
+    /// Behaves like `Cache::traverse_chunks`, but passes the chunks in
+    /// descending byte offset order, starting at the end of the range. The
+    /// bytes within each chunk are still in ascending order. This is suited
+    /// to reading backward from the end of the source, such as trailers or
+    /// the tail of a log.
+    ///
+    /// The provided implementation copies the range into a buffer one
+    /// window at a time from the end, using `Cache::traverse_chunks`.
+    /// Caches that can pass their chunks directly should override it.
+    fn traverse_chunks_rev<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        let mut f = f;
+        let Range { start, mut end } = bounds(&range, self.len());
+        let mut buf = Vec::with_capacity(REV_STEP.min((end - start) as usize));
+        while end > start {
+            let window_start = end.saturating_sub(REV_STEP as u64).max(start);
+            buf.clear();
+            self.traverse_chunks(window_start..end, |chunk| {
+                buf.extend_from_slice(chunk);
+                Ok(())
+            })?;
+            if !buf.is_empty() {
+                f(&buf)?;
+            }
+            end = window_start;
+        }
+        Ok(())
+    }
+
+    /// Behaves like `Cache::traverse_chunks`, but can be cancelled and reports
+    /// its progress. Before each chunk is passed to `f`, the traversal checks
+    /// `token`, and returns `Error::Cancelled` if the token was cancelled.
+    /// After each chunk, `progress` is called with the number of bytes
+    /// traversed so far and the total number of bytes in the traversal. Large
+    /// chunks are split so that cancellation and progress are checked at least
+    /// once per mebibyte, even for `FullCache`.
+    fn traverse_chunks_with<R, P, F>(
+        &self,
+        range: R,
+        token: &CancellationToken,
+        progress: P,
+        f: F,
+    ) -> Result<()>
+    where
+        R: RangeBounds<u64>,
+        P: FnMut(u64, u64),
+        F: FnMut(&[u8]) -> Result<()>,
+    {
+        let mut progress = progress;
+        let mut f = f;
+        let range = bounds(&range, self.len());
+        let total = range.end - range.start;
+        let mut done = 0;
+        if token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        self.traverse_chunks(range, |chunk| {
+            for chunk in chunk.chunks(PROGRESS_STEP) {
+                if token.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                f(chunk)?;
+                done += chunk.len() as u64;
+                progress(done, total);
+            }
+            Ok(())
+        })
+    }
+
     /// Fills a buffer with data from the source starting at the passed byte
     /// offset. Returns the number of bytes read into the buffer. The returned
     /// size will be less than the size of the buffer if the end of the source
@@ -188,10 +685,76 @@ pub trait Cache {
     fn read(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
         use std::io::Write;
         let mut total = 0;
-        self.traverse_chunks(offset..buffer.len() as u64, |chunk| {
-            total += (&mut buffer[total..]).write(chunk)?;
+        self.traverse_chunks(
+            offset..offset.saturating_add(buffer.len() as u64),
+            |chunk| {
+                total += (&mut buffer[total..]).write(chunk)?;
+                Ok(())
+            },
+        )?;
+        Ok(total)
+    }
+
+    /// Returns the offset of the first occurrence of `pattern` that lies
+    /// entirely within the passed range, or `None` if there is none. Matches
+    /// that straddle chunk or page boundaries are found. An empty pattern
+    /// matches at the start of the range.
+    fn find<R: RangeBounds<u64>>(&self, pattern: &[u8], range: R) -> Result<Option<u64>> {
+        let range = bounds(&range, self.len());
+        search::find(self, &memchr::memmem::Finder::new(pattern), range)
+    }
+
+    /// Returns the offset of the last occurrence of `pattern` that lies
+    /// entirely within the passed range, or `None` if there is none. The
+    /// range is traversed backward with `Cache::traverse_chunks_rev`, so
+    /// occurrences near the end of the range are found quickly. An empty
+    /// pattern matches at the end of the range.
+    fn rfind<R: RangeBounds<u64>>(&self, pattern: &[u8], range: R) -> Result<Option<u64>> {
+        let range = bounds(&range, self.len());
+        search::rfind(self, &memchr::memmem::FinderRev::new(pattern), range)
+    }
+
+    /// Returns an iterator over the offsets of the non-overlapping
+    /// occurrences of `pattern` that lie entirely within the passed range,
+    /// in ascending order. See `Cache::find`.
+    fn find_iter<'a, R: RangeBounds<u64>>(
+        &'a self,
+        pattern: &'a [u8],
+        range: R,
+    ) -> FindIter<'a, Self> {
+        FindIter::new(self, pattern, bounds(&range, self.len()))
+    }
+
+    /// Computes the checksum of the passed range with the passed algorithm.
+    /// The chunks of the range are fed to the algorithm as they are
+    /// traversed, without being copied. Use `Hasher` with
+    /// `Cache::traverse_chunks_with` for a checksum that can be cancelled or
+    /// reports its progress.
+    fn digest<R: RangeBounds<u64>>(&self, range: R, algorithm: Algorithm) -> Result<Checksum> {
+        let mut hasher = Hasher::new(algorithm);
+        self.traverse_chunks(range, |chunk| {
+            hasher.update(chunk);
             Ok(())
         })?;
-        Ok(total)
+        Ok(hasher.finish())
+    }
+
+    /// Computes the hash of the passed range with any hash function
+    /// implementing the RustCrypto `digest::Digest` trait, such as those of
+    /// the `sha2` or `sha3` crates. The chunks of the range are fed to the
+    /// hash function as they are traversed, without being copied.
+    ///
+    /// This method is only available with the `digest` feature enabled.
+    #[cfg(feature = "digest")]
+    fn digest_with<D: ::digest::Digest, R: RangeBounds<u64>>(
+        &self,
+        range: R,
+    ) -> Result<::digest::Output<D>> {
+        let mut hasher = D::new();
+        self.traverse_chunks(range, |chunk| {
+            hasher.update(chunk);
+            Ok(())
+        })?;
+        Ok(hasher.finalize())
     }
 }
@@ -0,0 +1,273 @@
+use super::search::Stop;
+use super::{bounds, Cache, CancellationToken, Error, Result};
+use std::ops::{Range, RangeBounds};
+
+/// The default number of lines between checkpoints.
+const DEFAULT_INTERVAL: u64 = 1024;
+
+/// An index of the lines of a text cache, for jumping to a line or finding
+/// the line of an offset without scanning from the start.
+///
+/// The index records the offset of every `interval`th line in a table of
+/// checkpoints, so it takes 8 bytes per `interval` lines, and a lookup only
+/// scans the lines between the nearest checkpoint and its target. Lines are
+/// terminated by `\n` or by the end of the cache, and a `\n` at the end of
+/// the cache does not start another line. A `\r` before a `\n` is part of
+/// its line.
+///
+/// The index is built incrementally: `LineIndex::update` scans a bounded
+/// number of bytes at a time, for building from an event loop, and
+/// `LineIndex::build` scans the rest of the cache, and can be cancelled and
+/// resumed. `LineIndex` is `Send`, so it can be built on a background thread
+/// over a cache that is `Sync`. Every query is answered correctly by a
+/// partial index, but scans the part of the cache that is not yet indexed.
+/// If the cache changes other than by growing, such as after
+/// `Cache::validate` reports a modification, the index must be cleared with
+/// `LineIndex::clear`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    interval: u64,
+    checkpoints: Vec<u64>,
+    indexed: u64,
+    newlines: u64,
+}
+
+impl LineIndex {
+    /// Creates an empty index with a checkpoint every 1024 lines.
+    pub fn new() -> Self {
+        Self::with_interval(DEFAULT_INTERVAL)
+    }
+
+    /// Creates an empty index with a checkpoint every `interval` lines.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn with_interval(interval: u64) -> Self {
+        assert!(interval != 0, "checkpoint interval must not be zero");
+        LineIndex {
+            interval,
+            checkpoints: vec![0],
+            indexed: 0,
+            newlines: 0,
+        }
+    }
+
+    /// Returns the number of lines between checkpoints.
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Returns the number of bytes of the cache that have been indexed.
+    pub fn indexed_len(&self) -> u64 {
+        self.indexed
+    }
+
+    /// Returns true if the whole cache has been indexed, false otherwise.
+    pub fn is_complete<C: Cache + ?Sized>(&self, cache: &C) -> bool {
+        self.indexed >= cache.len()
+    }
+
+    /// Discards the index, so that it is rebuilt from the start of the
+    /// cache.
+    pub fn clear(&mut self) {
+        self.checkpoints.truncate(1);
+        self.indexed = 0;
+        self.newlines = 0;
+    }
+
+    /// Indexes up to `max_bytes` more bytes of the cache. Returns true if
+    /// the whole cache has been indexed, false otherwise. If an error
+    /// occurs, the bytes indexed before it are kept.
+    pub fn update<C: Cache + ?Sized>(&mut self, cache: &C, max_bytes: u64) -> Result<bool> {
+        let end = self.indexed.saturating_add(max_bytes);
+        cache.traverse_chunks(self.indexed..end, |chunk| {
+            self.index_chunk(chunk);
+            Ok(())
+        })?;
+        Ok(self.is_complete(cache))
+    }
+
+    /// Indexes the rest of the cache. The traversal uses
+    /// `Cache::traverse_chunks_with`, so it can be cancelled with `token`
+    /// and reports its progress to `progress`. If it is cancelled or an
+    /// error occurs, the bytes indexed before it are kept, and a later call
+    /// resumes from there.
+    pub fn build<C, P>(&mut self, cache: &C, token: &CancellationToken, progress: P) -> Result<()>
+    where
+        C: Cache + ?Sized,
+        P: FnMut(u64, u64),
+    {
+        cache.traverse_chunks_with(self.indexed.., token, progress, |chunk| {
+            self.index_chunk(chunk);
+            Ok(())
+        })
+    }
+
+    fn index_chunk(&mut self, chunk: &[u8]) {
+        for idx in memchr::memchr_iter(b'\n', chunk) {
+            self.newlines += 1;
+            if self.newlines == self.checkpoints.len() as u64 * self.interval {
+                self.checkpoints.push(self.indexed + idx as u64 + 1);
+            }
+        }
+        self.indexed += chunk.len() as u64;
+    }
+
+    /// Returns the number of the first line and the offset of the nearest
+    /// checkpoint at or before the passed line.
+    fn checkpoint_before_line(&self, line: u64) -> (u64, u64) {
+        let idx = (line / self.interval).min(self.checkpoints.len() as u64 - 1);
+        (idx * self.interval, self.checkpoints[idx as usize])
+    }
+
+    /// Returns the offset of the start of the passed line, or `None` if the
+    /// cache has fewer lines.
+    pub fn line_to_offset<C: Cache + ?Sized>(&self, cache: &C, line: u64) -> Result<Option<u64>> {
+        let (mut current, mut pos) = self.checkpoint_before_line(line);
+        if current < line {
+            let mut found = None;
+            let result = cache.traverse_chunks(pos.., |chunk| {
+                for idx in memchr::memchr_iter(b'\n', chunk) {
+                    current += 1;
+                    if current == line {
+                        found = Some(pos + idx as u64 + 1);
+                        return Err(Error::new_other(Stop));
+                    }
+                }
+                pos += chunk.len() as u64;
+                Ok(())
+            });
+            match (result, found) {
+                (_, Some(offset)) => pos = offset,
+                (Ok(()), None) => return Ok(None),
+                (Err(e), None) => return Err(e),
+            }
+        }
+        Ok(Some(pos).filter(|pos| *pos < cache.len()))
+    }
+
+    /// Returns the number of the line containing the byte at the passed
+    /// offset. A `\n` belongs to the line it ends. Returns
+    /// `Error::OutOfRange` if the offset is at or beyond the end of the
+    /// cache.
+    pub fn offset_to_line<C: Cache + ?Sized>(&self, cache: &C, offset: u64) -> Result<u64> {
+        let len = cache.len();
+        if offset >= len {
+            return Err(Error::OutOfRange { offset, len });
+        }
+        let idx = self.checkpoints.partition_point(|start| *start <= offset) - 1;
+        let (newlines, _) = count_newlines(cache, self.checkpoints[idx]..offset)?;
+        Ok(idx as u64 * self.interval + newlines)
+    }
+
+    /// Returns the number of lines in the cache. Only the lines after the
+    /// last checkpoint are counted, so this is fast once the index is
+    /// complete.
+    pub fn line_count<C: Cache + ?Sized>(&self, cache: &C) -> Result<u64> {
+        let last = self.checkpoints.len() - 1;
+        let start = self.checkpoints[last];
+        let (newlines, last_byte) = count_newlines(cache, start..)?;
+        let unterminated = last_byte.is_some_and(|byte| byte != b'\n');
+        Ok(last as u64 * self.interval + newlines + unterminated as u64)
+    }
+
+    /// Returns an iterator over the passed range of lines of the cache. The
+    /// start of the first line is found through the index, and the rest are
+    /// found by scanning forward from it. See `Lines`.
+    pub fn lines<'a, C: Cache + ?Sized, R: RangeBounds<u64>>(
+        &'a self,
+        cache: &'a C,
+        lines: R,
+    ) -> Lines<'a, C> {
+        let lines = bounds(&lines, u64::MAX);
+        Lines {
+            index: self,
+            cache,
+            line: lines.start,
+            end: lines.end,
+            pos: None,
+            done: false,
+        }
+    }
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        LineIndex::new()
+    }
+}
+
+/// Counts the newlines in the passed range of the cache, and returns the
+/// count and the last byte of the range, if any.
+fn count_newlines<C: Cache + ?Sized, R: RangeBounds<u64>>(
+    cache: &C,
+    range: R,
+) -> Result<(u64, Option<u8>)> {
+    let mut newlines = 0;
+    let mut last = None;
+    cache.traverse_chunks(range, |chunk| {
+        newlines += memchr::memchr_iter(b'\n', chunk).count() as u64;
+        last = chunk.last().cloned().or(last);
+        Ok(())
+    })?;
+    Ok((newlines, last))
+}
+
+/// An iterator over a range of the lines of a cache.
+///
+/// This type is returned by `LineIndex::lines`. Each item is the number of a
+/// line and the range of its bytes, without its terminating `\n`. Lines are
+/// found lazily, one at a time, so the range of lines may be unbounded.
+///
+/// If an error occurs, it is returned and the iteration ends.
+pub struct Lines<'a, C: Cache + ?Sized> {
+    index: &'a LineIndex,
+    cache: &'a C,
+    line: u64,
+    end: u64,
+    pos: Option<u64>,
+    done: bool,
+}
+
+impl<'a, C: Cache + ?Sized> Lines<'a, C> {
+    fn next_line(&mut self) -> Result<Option<(u64, Range<u64>)>> {
+        let len = self.cache.len();
+        let start = match self.pos {
+            Some(pos) => pos,
+            None => match self.index.line_to_offset(self.cache, self.line)? {
+                Some(pos) => pos,
+                None => return Ok(None),
+            },
+        };
+        if self.line >= self.end || start >= len {
+            return Ok(None);
+        }
+        let end = self.cache.find(b"\n", start..)?.unwrap_or(len);
+        let line = self.line;
+        self.line += 1;
+        self.pos = Some(end + 1);
+        Ok(Some((line, start..end)))
+    }
+}
+
+impl<'a, C: Cache + ?Sized> Iterator for Lines<'a, C> {
+    type Item = Result<(u64, Range<u64>)>;
+
+    fn next(&mut self) -> Option<Result<(u64, Range<u64>)>> {
+        if self.done {
+            return None;
+        }
+        match self.next_line() {
+            Ok(Some(line)) => Some(Ok(line)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
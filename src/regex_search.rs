@@ -0,0 +1,215 @@
+use super::search::Stop;
+use super::{bounds, Cache, Error, Result};
+use regex::bytes::Regex;
+use std::ops::{Range, RangeBounds};
+
+/// The number of bytes kept as context around a match, so that assertions
+/// such as `\b` see the characters preceding and following it.
+const CONTEXT: usize = 4;
+
+/// The number of bytes appended to the window between searches, so that
+/// large chunks, such as the single chunk of a `FullCache`, are not copied
+/// whole.
+const STEP: usize = 64 * 1024;
+
+/// A match found by `RegexSearch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegexMatch {
+    offset: u64,
+    len: u64,
+}
+
+impl RegexMatch {
+    /// Returns the offset of the first byte of the match.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the length of the match in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the match is empty, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the range of bytes covered by the match.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// A `regex::bytes::Regex` that searches the contents of a cache.
+///
+/// The cache is searched in overlapping windows, so that only a small part
+/// of it is in memory at once. Windows overlap by the maximum match length,
+/// so every match no longer than the maximum is found, even when it
+/// straddles chunk or page boundaries. Matches that would be longer than the
+/// maximum may be missed or reported shorter than they would be against the
+/// whole range. The searched range is treated as the whole haystack, so `^`
+/// and `$` match at the start and end of the range.
+///
+/// This type is only available with the `regex` feature enabled.
+#[derive(Debug, Clone)]
+pub struct RegexSearch {
+    regex: Regex,
+    max_len: usize,
+}
+
+impl RegexSearch {
+    /// Compiles `pattern` with `regex::bytes::Regex` for searches with the
+    /// passed maximum match length in bytes. Returns `Error::Pattern` if the
+    /// pattern is invalid or the maximum match length is zero.
+    pub fn new(pattern: &str, max_len: usize) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|e| Error::Pattern(e.to_string()))?;
+        RegexSearch::from_regex(regex, max_len)
+    }
+
+    /// Creates a search with an already compiled regex and the passed maximum
+    /// match length in bytes. Returns `Error::Pattern` if the maximum match
+    /// length is zero.
+    pub fn from_regex(regex: Regex, max_len: usize) -> Result<Self> {
+        if max_len == 0 {
+            return Err(Error::Pattern(
+                "maximum match length must not be zero".into(),
+            ));
+        }
+        Ok(RegexSearch { regex, max_len })
+    }
+
+    /// Returns the regex being searched for.
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    /// Returns the maximum match length in bytes.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Returns the first match within the passed range of the cache, or
+    /// `None` if there is none.
+    pub fn find<C: Cache + ?Sized, R: RangeBounds<u64>>(
+        &self,
+        cache: &C,
+        range: R,
+    ) -> Result<Option<RegexMatch>> {
+        let range = bounds(&range, cache.len());
+        self.find_from(cache, range.clone(), range.start)
+    }
+
+    /// Returns an iterator over the non-overlapping matches within the passed
+    /// range of the cache, in ascending order. See `RegexSearch::find`.
+    pub fn find_iter<'a, C: Cache + ?Sized, R: RangeBounds<u64>>(
+        &'a self,
+        cache: &'a C,
+        range: R,
+    ) -> RegexFindIter<'a, C> {
+        let range = bounds(&range, cache.len());
+        RegexFindIter {
+            search: self,
+            cache,
+            pos: range.start,
+            range,
+            done: false,
+        }
+    }
+
+    /// Returns the first match starting at or after `from` within `range`.
+    /// The bytes before `from` are only used as context.
+    fn find_from<C: Cache + ?Sized>(
+        &self,
+        cache: &C,
+        range: Range<u64>,
+        from: u64,
+    ) -> Result<Option<RegexMatch>> {
+        // Bytes up to the maximum match length plus context before the end
+        // of the window are settled, and cannot be changed by more data.
+        let lookahead = self.max_len as u64 + CONTEXT as u64;
+        let mut buf_start = from.saturating_sub(CONTEXT as u64).max(range.start);
+        let mut buf: Vec<u8> = Vec::new();
+        let mut pos = from;
+        let mut found = None;
+        let result = cache.traverse_chunks(buf_start..range.end, |chunk| {
+            for piece in chunk.chunks(STEP) {
+                buf.extend_from_slice(piece);
+                let buf_end = buf_start + buf.len() as u64;
+                if buf_end < pos + lookahead {
+                    continue;
+                }
+                match self.regex.find_at(&buf, (pos - buf_start) as usize) {
+                    Some(m) if buf_start + m.start() as u64 + lookahead <= buf_end => {
+                        found = Some(RegexMatch {
+                            offset: buf_start + m.start() as u64,
+                            len: m.len() as u64,
+                        });
+                        return Err(Error::new_other(Stop));
+                    }
+                    Some(m) => pos = buf_start + m.start() as u64,
+                    None => pos = pos.max(buf_end - lookahead + 1),
+                }
+                let keep = pos.saturating_sub(CONTEXT as u64).max(buf_start);
+                buf.drain(..(keep - buf_start) as usize);
+                buf_start = keep;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => {}
+            Err(_) if found.is_some() => return Ok(found),
+            Err(e) => return Err(e),
+        }
+        if pos > buf_start + buf.len() as u64 {
+            return Ok(None);
+        }
+        Ok(self
+            .regex
+            .find_at(&buf, (pos - buf_start) as usize)
+            .map(|m| RegexMatch {
+                offset: buf_start + m.start() as u64,
+                len: m.len() as u64,
+            }))
+    }
+}
+
+/// An iterator over the non-overlapping matches of a `RegexSearch` in a
+/// cache.
+///
+/// This type is returned by `RegexSearch::find_iter`. If an error occurs, it
+/// is returned and the iteration ends.
+pub struct RegexFindIter<'a, C: Cache + ?Sized> {
+    search: &'a RegexSearch,
+    cache: &'a C,
+    range: Range<u64>,
+    pos: u64,
+    done: bool,
+}
+
+impl<'a, C: Cache + ?Sized> Iterator for RegexFindIter<'a, C> {
+    type Item = Result<RegexMatch>;
+
+    fn next(&mut self) -> Option<Result<RegexMatch>> {
+        if self.done || self.pos > self.range.end {
+            return None;
+        }
+        match self
+            .search
+            .find_from(self.cache, self.range.clone(), self.pos)
+        {
+            Ok(Some(m)) => {
+                self.pos = m.offset + m.len.max(1);
+                Some(Ok(m))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use super::{Cache, Error, Result};
+use memchr::memmem::{Finder, FinderRev};
+use std::ops::Range;
+
+/// Returned by the closure passed to `Cache::traverse_chunks` to end a scan
+/// early once a match is found.
+#[derive(Debug)]
+pub(crate) struct Stop;
+
+impl std::fmt::Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "scan stopped")
+    }
+}
+
+impl std::error::Error for Stop {}
+
+/// Traverses `range` of the cache, calling `f` with the offset and contents
+/// of a series of windows, until `f` returns a value. Every run of up to
+/// `overlap + 1` consecutive bytes in the range is contained whole in at
+/// least one window, including runs that straddle chunk boundaries, and the
+/// windows are passed in ascending order of their end offset. Returns the
+/// first value returned by `f`, if any.
+pub(crate) fn scan<C, T, F>(cache: &C, range: Range<u64>, overlap: usize, f: F) -> Result<Option<T>>
+where
+    C: Cache + ?Sized,
+    F: FnMut(u64, &[u8]) -> Option<T>,
+{
+    let mut f = f;
+    let mut found = None;
+    let mut carry: Vec<u8> = Vec::with_capacity(overlap * 2);
+    let mut pos = range.start;
+    let result = cache.traverse_chunks(range, |chunk| {
+        if overlap > 0 && !carry.is_empty() {
+            // The carried tail of the previous chunks is shorter than a
+            // whole run, so only runs straddling the boundary can be found
+            // in the joint window.
+            let joint_start = pos - carry.len() as u64;
+            let head = chunk.len().min(overlap);
+            carry.extend_from_slice(&chunk[..head]);
+            found = f(joint_start, &carry);
+            carry.truncate(carry.len() - head);
+            if found.is_some() {
+                return Err(Error::new_other(Stop));
+            }
+        }
+        found = f(pos, chunk);
+        if found.is_some() {
+            return Err(Error::new_other(Stop));
+        }
+        if overlap > 0 {
+            if chunk.len() >= overlap {
+                carry.clear();
+                carry.extend_from_slice(&chunk[chunk.len() - overlap..]);
+            } else {
+                carry.extend_from_slice(chunk);
+                let excess = carry.len().saturating_sub(overlap);
+                carry.drain(..excess);
+            }
+        }
+        pos += chunk.len() as u64;
+        Ok(())
+    });
+    match result {
+        Ok(()) => Ok(found),
+        Err(_) if found.is_some() => Ok(found),
+        Err(e) => Err(e),
+    }
+}
+
+/// Behaves like `scan`, but traverses the range backward with
+/// `Cache::traverse_chunks_rev`, passing windows in descending order of
+/// their start offset.
+pub(crate) fn scan_rev<C, T, F>(
+    cache: &C,
+    range: Range<u64>,
+    overlap: usize,
+    f: F,
+) -> Result<Option<T>>
+where
+    C: Cache + ?Sized,
+    F: FnMut(u64, &[u8]) -> Option<T>,
+{
+    let mut f = f;
+    let mut found = None;
+    let mut carry: Vec<u8> = Vec::with_capacity(overlap * 2);
+    let mut end = range.end;
+    let result = cache.traverse_chunks_rev(range, |chunk| {
+        let pos = end - chunk.len() as u64;
+        if overlap > 0 && !carry.is_empty() {
+            // The carried head of the following chunks is shorter than a
+            // whole run, so only runs straddling the boundary can be found
+            // in the joint window.
+            let tail = chunk.len().min(overlap);
+            let mut joint = Vec::with_capacity(tail + carry.len());
+            joint.extend_from_slice(&chunk[chunk.len() - tail..]);
+            joint.extend_from_slice(&carry);
+            found = f(end - tail as u64, &joint);
+            if found.is_some() {
+                return Err(Error::new_other(Stop));
+            }
+        }
+        found = f(pos, chunk);
+        if found.is_some() {
+            return Err(Error::new_other(Stop));
+        }
+        if overlap > 0 {
+            if chunk.len() >= overlap {
+                carry.clear();
+                carry.extend_from_slice(&chunk[..overlap]);
+            } else {
+                carry.splice(0..0, chunk.iter().cloned());
+                carry.truncate(overlap);
+            }
+        }
+        end = pos;
+        Ok(())
+    });
+    match result {
+        Ok(()) => Ok(found),
+        Err(_) if found.is_some() => Ok(found),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the offset of the first occurrence of the finder's pattern in
+/// `range` of the cache.
+pub(crate) fn find<C: Cache + ?Sized>(
+    cache: &C,
+    finder: &Finder,
+    range: Range<u64>,
+) -> Result<Option<u64>> {
+    let needle_len = finder.needle().len();
+    if needle_len == 0 {
+        return Ok(Some(range.start));
+    }
+    if range.end - range.start < needle_len as u64 {
+        return Ok(None);
+    }
+    scan(cache, range, needle_len - 1, |offset, window| {
+        finder.find(window).map(|idx| offset + idx as u64)
+    })
+}
+
+/// Returns the offset of the last occurrence of the finder's pattern in
+/// `range` of the cache.
+pub(crate) fn rfind<C: Cache + ?Sized>(
+    cache: &C,
+    finder: &FinderRev,
+    range: Range<u64>,
+) -> Result<Option<u64>> {
+    let needle_len = finder.needle().len();
+    if needle_len == 0 {
+        return Ok(Some(range.end));
+    }
+    if range.end - range.start < needle_len as u64 {
+        return Ok(None);
+    }
+    scan_rev(cache, range, needle_len - 1, |offset, window| {
+        finder.rfind(window).map(|idx| offset + idx as u64)
+    })
+}
+
+/// An iterator over the offsets of the non-overlapping occurrences of a
+/// pattern in a cache.
+///
+/// This type is returned by `Cache::find_iter`. Each call to `next` resumes
+/// the search after the previous match, so matches are found lazily. If an
+/// error occurs, it is returned and the iteration ends.
+pub struct FindIter<'a, C: Cache + ?Sized> {
+    cache: &'a C,
+    finder: Finder<'a>,
+    range: Range<u64>,
+    done: bool,
+}
+
+impl<'a, C: Cache + ?Sized> FindIter<'a, C> {
+    pub(crate) fn new(cache: &'a C, pattern: &'a [u8], range: Range<u64>) -> Self {
+        FindIter {
+            cache,
+            finder: Finder::new(pattern),
+            range,
+            done: false,
+        }
+    }
+}
+
+impl<'a, C: Cache + ?Sized> Iterator for FindIter<'a, C> {
+    type Item = Result<u64>;
+
+    fn next(&mut self) -> Option<Result<u64>> {
+        if self.done || self.range.start > self.range.end {
+            return None;
+        }
+        match find(self.cache, &self.finder, self.range.clone()) {
+            Ok(Some(offset)) => {
+                let len = self.finder.needle().len().max(1) as u64;
+                self.range.start = offset + len;
+                Some(Ok(offset))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use super::{bounds, Cache, Result};
+use std::collections::VecDeque;
+use std::ops::{Range, RangeBounds};
+
+/// The number of bytes traversed between checks for completed strings, so
+/// that strings are found lazily.
+const STEP: u64 = 64 * 1024;
+
+/// The default maximum number of characters in a returned run.
+const DEFAULT_MAX_LEN: usize = 4096;
+
+/// A text encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// 7 bit ASCII.
+    Ascii,
+
+    /// UTF-8.
+    Utf8,
+
+    /// UTF-16 with little-endian code units.
+    Utf16Le,
+
+    /// UTF-16 with big-endian code units.
+    Utf16Be,
+
+    /// ISO 8859-1, where each byte is the character with the same code
+    /// point.
+    Latin1,
+
+    /// Windows-1252, which extends ISO 8859-1 with printable characters
+    /// from 0x80 to 0x9F.
+    Windows1252,
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Encoding::Ascii => "ASCII",
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "ISO-8859-1",
+            Encoding::Windows1252 => "Windows-1252",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn is_printable_ascii(c: char) -> bool {
+    c == '\t' || (' '..='~').contains(&c)
+}
+
+/// A run of printable characters being extracted.
+struct Run {
+    start: u64,
+    text: String,
+    chars: usize,
+    continued: bool,
+}
+
+/// Extracts the runs of one encoding, or of both ASCII and UTF-8, which
+/// share a scanner so that each run is only reported once.
+struct Scanner {
+    kind: Kind,
+    run: Option<Run>,
+    partial: [u8; 4],
+    partial_len: usize,
+    partial_start: u64,
+    high_surrogate: Option<(u64, u16)>,
+    continued: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Bytes { ascii: bool, utf8: bool },
+    Utf16 { big_endian: bool, odd: bool },
+}
+
+/// The limits on the runs that are reported.
+#[derive(Clone, Copy)]
+struct Limits {
+    min_len: usize,
+    max_len: usize,
+}
+
+impl Scanner {
+    fn new(kind: Kind) -> Self {
+        Scanner {
+            kind,
+            run: None,
+            partial: [0; 4],
+            partial_len: 0,
+            partial_start: 0,
+            high_surrogate: None,
+            continued: false,
+        }
+    }
+
+    /// Returns the offset of the earliest run this scanner may still
+    /// report, which is the start of the open run, or of a character that
+    /// is not complete yet.
+    fn pending_start(&self) -> Option<u64> {
+        let partial = Some(self.partial_start).filter(|_| self.partial_len > 0);
+        let surrogate = self.high_surrogate.map(|(start, _)| start);
+        let open = self.run.as_ref().map(|run| run.start);
+        [open, partial, surrogate].iter().flatten().min().copied()
+    }
+
+    fn is_printable(&self, c: char) -> bool {
+        match self.kind {
+            Kind::Bytes { utf8, .. } => {
+                is_printable_ascii(c) || (utf8 && !c.is_ascii() && !c.is_control())
+            }
+            Kind::Utf16 { .. } => {
+                is_printable_ascii(c) || (('\u{A0}'..'\u{800}').contains(&c) && !c.is_control())
+            }
+        }
+    }
+
+    /// Extends the current run with a character starting at `offset`, or
+    /// ends it if the character is not printable or missing.
+    fn accept(&mut self, offset: u64, c: Option<char>, limits: Limits, found: &mut Found) {
+        match c {
+            Some(c) if self.is_printable(c) => {
+                if self.run.is_none() {
+                    self.run = Some(Run {
+                        start: offset,
+                        text: String::new(),
+                        chars: 0,
+                        continued: std::mem::replace(&mut self.continued, false),
+                    });
+                }
+                let run = self.run.as_mut().unwrap();
+                run.text.push(c);
+                run.chars += 1;
+                if run.chars >= limits.max_len {
+                    // The run is reported in pieces, so that the text held
+                    // stays bounded.
+                    self.end(limits, found);
+                    self.continued = true;
+                }
+            }
+            _ => self.end(limits, found),
+        }
+    }
+
+    /// Ends the current run, and reports it if it is long enough or
+    /// continues a run that was.
+    fn end(&mut self, limits: Limits, found: &mut Found) {
+        self.continued = false;
+        if let Some(run) = self.run.take() {
+            if run.chars >= limits.min_len || run.continued {
+                let encoding = match self.kind {
+                    Kind::Bytes { ascii: true, .. } if run.text.is_ascii() => Encoding::Ascii,
+                    Kind::Bytes { .. } => Encoding::Utf8,
+                    Kind::Utf16 {
+                        big_endian: false, ..
+                    } => Encoding::Utf16Le,
+                    Kind::Utf16 {
+                        big_endian: true, ..
+                    } => Encoding::Utf16Be,
+                };
+                found.insert(run.start, encoding, run.text);
+            }
+        }
+    }
+
+    fn feed(&mut self, offset: u64, byte: u8, limits: Limits, found: &mut Found) {
+        match self.kind {
+            Kind::Bytes { utf8: false, .. } => self.accept(
+                offset,
+                Some(byte as char).filter(char::is_ascii),
+                limits,
+                found,
+            ),
+            Kind::Bytes { utf8: true, .. } => self.feed_utf8(offset, byte, limits, found),
+            Kind::Utf16 { big_endian, odd } => {
+                // Only code units at even offsets, or at odd offsets for
+                // an odd scanner, are considered.
+                if (offset & 1 == 1) == odd {
+                    self.partial[0] = byte;
+                    self.partial_len = 1;
+                    self.partial_start = offset;
+                    return;
+                }
+                if self.partial_len == 0 {
+                    return;
+                }
+                self.partial_len = 0;
+                let pair = [self.partial[0], byte];
+                let unit = if big_endian {
+                    u16::from_be_bytes(pair)
+                } else {
+                    u16::from_le_bytes(pair)
+                };
+                self.feed_utf16(offset - 1, unit, limits, found);
+            }
+        }
+    }
+
+    fn feed_utf8(&mut self, offset: u64, byte: u8, limits: Limits, found: &mut Found) {
+        if self.partial_len > 0 {
+            if byte & 0xC0 == 0x80 {
+                self.partial[self.partial_len] = byte;
+                self.partial_len += 1;
+                let needed = match self.partial[0] {
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    _ => 4,
+                };
+                if self.partial_len < needed {
+                    return;
+                }
+                let c = std::str::from_utf8(&self.partial[..needed])
+                    .ok()
+                    .and_then(|s| s.chars().next());
+                self.partial_len = 0;
+                self.accept(self.partial_start, c, limits, found);
+                return;
+            }
+            // The sequence was cut short, so the byte starts afresh.
+            self.partial_len = 0;
+            self.end(limits, found);
+        }
+        match byte {
+            0x00..=0x7F => self.accept(offset, Some(byte as char), limits, found),
+            0xC2..=0xF4 => {
+                self.partial[0] = byte;
+                self.partial_len = 1;
+                self.partial_start = offset;
+            }
+            _ => self.end(limits, found),
+        }
+    }
+
+    fn feed_utf16(&mut self, offset: u64, unit: u16, limits: Limits, found: &mut Found) {
+        match (self.high_surrogate.take(), unit) {
+            (None, 0xD800..=0xDBFF) => self.high_surrogate = Some((offset, unit)),
+            (Some((start, high)), 0xDC00..=0xDFFF) => {
+                let code = 0x10000 + (((high as u32) - 0xD800) << 10) + (unit as u32 - 0xDC00);
+                self.accept(start, std::char::from_u32(code), limits, found);
+            }
+            (Some(_), _) => {
+                self.end(limits, found);
+                self.feed_utf16(offset, unit, limits, found);
+            }
+            (None, _) => self.accept(offset, std::char::from_u32(unit as u32), limits, found),
+        }
+    }
+
+    /// Ends any run at the end of the range.
+    fn finish(&mut self, limits: Limits, found: &mut Found) {
+        self.partial_len = 0;
+        self.high_surrogate = None;
+        self.end(limits, found);
+    }
+}
+
+/// The completed strings not yet returned, in ascending order of offset.
+struct Found(VecDeque<(u64, Encoding, String)>);
+
+impl Found {
+    fn insert(&mut self, offset: u64, encoding: Encoding, text: String) {
+        let idx = self.0.partition_point(|found| found.0 <= offset);
+        self.0.insert(idx, (offset, encoding, text));
+    }
+}
+
+/// An iterator over the runs of printable characters in a range of a cache,
+/// like the `strings` utility.
+///
+/// Each item is the offset of the first byte of a run, its encoding, and its
+/// text. Runs are found in ASCII, UTF-8, UTF-16LE and UTF-16BE by default,
+/// or in the encodings passed to `Strings::with_encodings`, and are
+/// reported when they are at least the minimum number of characters long.
+/// Runs are returned in ascending order of offset, including runs that
+/// straddle chunk or page boundaries, and the range is traversed lazily.
+///
+/// Printable characters are tab and the visible ASCII characters, and for
+/// UTF-8 also any other character that is not a control character. A UTF-8
+/// run made only of ASCII characters is reported as ASCII when ASCII is
+/// searched for. UTF-16 runs must start at even offsets unless
+/// `Strings::with_unaligned_utf16` is used, and are limited to characters
+/// below U+0800, which covers Latin, Greek, Cyrillic, Hebrew and Arabic
+/// scripts, since almost any pair of bytes would otherwise be a printable
+/// character.
+///
+/// Runs longer than the maximum length, 4096 characters unless set with
+/// `Strings::with_max_len`, are returned as consecutive pieces of at most
+/// that many characters, so that the memory used stays bounded. The last
+/// piece may be shorter than the minimum length.
+///
+/// If an error occurs, it is returned and the iteration ends.
+pub struct Strings<'a, C: Cache + ?Sized> {
+    cache: &'a C,
+    range: Range<u64>,
+    limits: Limits,
+    unaligned_utf16: bool,
+    scanners: Vec<Scanner>,
+    found: Found,
+    done: bool,
+}
+
+impl<'a, C: Cache + ?Sized> Strings<'a, C> {
+    /// Creates an iterator over the runs of at least `min_len` printable
+    /// characters within the passed range of the cache, in all supported
+    /// encodings.
+    pub fn new<R: RangeBounds<u64>>(cache: &'a C, range: R, min_len: usize) -> Self {
+        Strings {
+            cache,
+            range: bounds(&range, cache.len()),
+            limits: Limits {
+                min_len: min_len.max(1),
+                max_len: DEFAULT_MAX_LEN.max(min_len),
+            },
+            unaligned_utf16: false,
+            scanners: Vec::new(),
+            found: Found(VecDeque::new()),
+            done: false,
+        }
+        .with_encodings(&[
+            Encoding::Ascii,
+            Encoding::Utf8,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+        ])
+    }
+
+    /// Restricts the iterator to the passed encodings. This must be called
+    /// before the first call to `next`. Latin-1 and Windows-1252 are not
+    /// searched, since almost every byte is a printable character in them.
+    pub fn with_encodings(mut self, encodings: &[Encoding]) -> Self {
+        let ascii = encodings.contains(&Encoding::Ascii);
+        let utf8 = encodings.contains(&Encoding::Utf8);
+        self.scanners.clear();
+        if ascii || utf8 {
+            self.scanners
+                .push(Scanner::new(Kind::Bytes { ascii, utf8 }));
+        }
+        for (encoding, big_endian) in [(Encoding::Utf16Le, false), (Encoding::Utf16Be, true)].iter()
+        {
+            if encodings.contains(encoding) {
+                self.scanners.push(Scanner::new(Kind::Utf16 {
+                    big_endian: *big_endian,
+                    odd: false,
+                }));
+            }
+        }
+        let unaligned = self.unaligned_utf16;
+        self.with_unaligned_utf16(unaligned)
+    }
+
+    /// Sets whether UTF-16 runs are also searched for at odd offsets, as in
+    /// packed structures. This doubles the work for UTF-16 and finds more
+    /// false positives. This must be called before the first call to
+    /// `next`.
+    pub fn with_unaligned_utf16(mut self, unaligned: bool) -> Self {
+        self.unaligned_utf16 = unaligned;
+        self.scanners
+            .retain(|scanner| !matches!(scanner.kind, Kind::Utf16 { odd: true, .. }));
+        if unaligned {
+            let odd: Vec<_> = self
+                .scanners
+                .iter()
+                .filter_map(|scanner| match scanner.kind {
+                    Kind::Utf16 { big_endian, .. } => Some(Scanner::new(Kind::Utf16 {
+                        big_endian,
+                        odd: true,
+                    })),
+                    Kind::Bytes { .. } => None,
+                })
+                .collect();
+            self.scanners.extend(odd);
+        }
+        self
+    }
+
+    /// Sets the maximum number of characters in a returned run, above
+    /// which runs are split into pieces. It is raised to the minimum length
+    /// if lower. This must be called before the first call to `next`.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.limits.max_len = max_len.max(self.limits.min_len);
+        self
+    }
+
+    /// Returns the offset before which no further runs can start, other
+    /// than runs starting at the same offset as a completed run.
+    fn settled(&self) -> u64 {
+        self.scanners
+            .iter()
+            .filter_map(Scanner::pending_start)
+            .min()
+            .unwrap_or(self.range.start)
+    }
+
+    /// Traverses the next step of the range.
+    fn step(&mut self) -> Result<()> {
+        let end = self.range.start.saturating_add(STEP).min(self.range.end);
+        let mut pos = self.range.start;
+        let scanners = &mut self.scanners;
+        let found = &mut self.found;
+        let limits = self.limits;
+        self.cache.traverse_chunks(pos..end, |chunk| {
+            for byte in chunk {
+                for scanner in scanners.iter_mut() {
+                    scanner.feed(pos, *byte, limits, found);
+                }
+                pos += 1;
+            }
+            Ok(())
+        })?;
+        self.range.start = end;
+        Ok(())
+    }
+}
+
+impl<'a, C: Cache + ?Sized> Iterator for Strings<'a, C> {
+    type Item = Result<(u64, Encoding, String)>;
+
+    fn next(&mut self) -> Option<Result<(u64, Encoding, String)>> {
+        loop {
+            let ready = match self.found.0.front() {
+                Some(first) => self.done || first.0 <= self.settled(),
+                None => false,
+            };
+            if ready {
+                return Some(Ok(self.found.0.pop_front().unwrap()));
+            }
+            if self.done {
+                return None;
+            }
+            if self.range.start >= self.range.end {
+                for scanner in self.scanners.iter_mut() {
+                    scanner.finish(self.limits, &mut self.found);
+                }
+                self.done = true;
+                continue;
+            }
+            if let Err(e) = self.step() {
+                self.done = true;
+                self.found.0.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+}
@@ -1,23 +1,104 @@
-use super::Cache;
+use super::fingerprint::FingerprintFn;
+use super::{bounds, Cache, Fingerprint, Fingerprinted, Operation, ResultExt};
 use std::collections::HashMap;
-use std::io::{Read, Seek, SeekFrom};
-use std::ops::{Bound, RangeBounds};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::{Range, RangeBounds};
 use std::sync::Mutex;
 
 use super::{Error, Result};
 
 struct Frame {
     data: Vec<u8>,
+    len: usize,
     page: u64,
+    dirty: bool,
     next: usize,
     prev: usize,
 }
 
-const NULL: usize = std::usize::MAX;
+const NAME: &str = "SwapCache";
+const NULL: usize = usize::MAX;
+const NO_PAGE: u64 = u64::MAX;
+
+/// Reads from the source until the buffer is full or the end of the source
+/// is reached, and returns the number of bytes read. Sources may return
+/// fewer bytes than requested from a single read, such as pipes, FUSE, or
+/// network file systems, so a single read is not sufficient to fill a page.
+fn fill<T: Read>(source: &mut T, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match source.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(len) => total += len,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+fn write_at<T: Write + Seek>(source: &mut T, pos: u64, data: &[u8]) -> std::io::Result<()> {
+    source.seek(SeekFrom::Start(pos))?;
+    source.write_all(data)
+}
+
+fn flush_source<T: Write>(source: &mut T) -> std::io::Result<()> {
+    source.flush()
+}
+
+/// Returns the length of the source and its fingerprint. Without a
+/// fingerprint function, the fingerprint only records the length.
+fn measure<T: Read + Seek>(
+    source: &mut T,
+    fingerprinter: Option<FingerprintFn<T>>,
+) -> Result<(u64, Fingerprint)> {
+    let len = source
+        .seek(SeekFrom::End(0))
+        .context(NAME, Operation::Seek, None)?;
+    let fingerprint = match fingerprinter {
+        Some(fingerprinter) => fingerprinter(source).context(NAME, Operation::Metadata, None)?,
+        None => Fingerprint::new(len),
+    };
+    Ok((len, fingerprint))
+}
+
+/// How data written to a `SwapCache` reaches its source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    /// Written data is kept in dirty frames, and written to the source when
+    /// the frame is evicted, or when the cache is flushed or unwrapped.
+    #[default]
+    WriteBack,
+
+    /// Written data is stored in the cached frames and written to the source
+    /// immediately.
+    WriteThrough,
+}
+
+/// The write operations of a source, captured when the source is first
+/// written through the cache, so that dirty frames can be written back from
+/// code that only knows the source as `Read + Seek`.
+struct Writer<T> {
+    write: fn(&mut T, u64, &[u8]) -> std::io::Result<()>,
+    flush: fn(&mut T) -> std::io::Result<()>,
+}
+
+impl<T> Clone for Writer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Writer<T> {}
 
 struct SwapCacheImpl<T: Read + Seek> {
     page_sz: u64,
-    source: T,
+    /// The source, which is only taken when the cache is unwrapped.
+    source: Option<T>,
+    writer: Option<Writer<T>>,
+    fingerprint: Fingerprint,
+    fingerprinter: Option<FingerprintFn<T>>,
+    policy: WritePolicy,
     frames: Vec<Frame>,
     map: HashMap<u64, usize>,
     front: usize,
@@ -25,60 +106,55 @@ struct SwapCacheImpl<T: Read + Seek> {
 }
 
 impl<T: Read + Seek> SwapCacheImpl<T> {
-    fn new(source: T, page_size: usize, frame_count: usize) -> Result<Self> {
+    fn new(
+        source: T,
+        fingerprint: Fingerprint,
+        fingerprinter: Option<FingerprintFn<T>>,
+        page_size: usize,
+        frame_count: usize,
+    ) -> Result<Self> {
         let mut source = source;
+        let len = fingerprint.len();
         let mut frames: Vec<Frame> = Vec::new();
         let mut map: HashMap<u64, usize> = HashMap::new();
-        let last = frame_count - 1;
-        source.seek(SeekFrom::Start(0))?;
-        frames.reserve_exact(frame_count as usize);
-        map.reserve(frame_count as usize);
-        if frame_count == 1 {
-            map.insert(0, 0);
-            let mut data = vec![0; page_size as usize];
-            source.read(&mut data)?;
+        let page_count = len.div_ceil(page_size as u64);
+        source
+            .seek(SeekFrom::Start(0))
+            .context(NAME, Operation::Seek, Some(0..0))?;
+        frames.reserve_exact(frame_count);
+        map.reserve(frame_count);
+        for i in 0..frame_count {
+            let mut data = vec![0; page_size];
+            let (page, len) = if (i as u64) < page_count {
+                map.insert(i as u64, i);
+                let start = i as u64 * page_size as u64;
+                let range = start..start + page_size as u64;
+                let len =
+                    fill(&mut source, &mut data).context(NAME, Operation::Read, Some(range))?;
+                (i as u64, len)
+            } else {
+                (NO_PAGE, 0)
+            };
             frames.push(Frame {
                 data,
-                page: 0,
-                next: NULL,
-                prev: NULL,
-            })
-        } else {
-            for i in 0..frame_count {
-                map.insert(i as u64, i);
-                let mut data = vec![0; page_size as usize];
-                source.read(&mut data)?;
-                if i == 0 {
-                    frames.push(Frame {
-                        data,
-                        page: 0,
-                        next: NULL,
-                        prev: 1,
-                    });
-                } else if i == last {
-                    frames.push(Frame {
-                        data,
-                        page: i as u64,
-                        next: last,
-                        prev: NULL,
-                    });
-                } else {
-                    frames.push(Frame {
-                        data,
-                        page: i as u64,
-                        next: i - 1,
-                        prev: i + 1,
-                    });
-                }
-            }
+                len,
+                page,
+                dirty: false,
+                next: if i + 1 < frame_count { i + 1 } else { NULL },
+                prev: if i > 0 { i - 1 } else { NULL },
+            });
         }
         Ok(SwapCacheImpl {
             page_sz: page_size as u64,
-            source,
+            source: Some(source),
+            writer: None,
+            fingerprint,
+            fingerprinter,
+            policy: WritePolicy::WriteBack,
             frames,
             map,
-            front: last,
-            back: 0,
+            front: 0,
+            back: frame_count - 1,
         })
     }
 
@@ -98,56 +174,197 @@ impl<T: Read + Seek> SwapCacheImpl<T> {
         f(self.get_frame_mut(fidx))
     }
 
+    fn source(&mut self) -> &mut T {
+        self.source
+            .as_mut()
+            .expect("source is only taken when unwrapping")
+    }
+
+    /// Records the fingerprint of the source after the cache wrote bytes up
+    /// to `end` to it, so that `Cache::validate` does not report the cache's
+    /// own writes as a modification of the source.
+    fn refresh_fingerprint(&mut self, end: u64) -> Result<()> {
+        self.fingerprint = match self.fingerprinter {
+            Some(fingerprinter) => {
+                fingerprinter(self.source()).context(NAME, Operation::Metadata, None)?
+            }
+            None => Fingerprint::new(self.fingerprint.len().max(end)),
+        };
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.map.clear();
+        for frame in self.frames.iter_mut() {
+            frame.page = NO_PAGE;
+            frame.len = 0;
+            frame.dirty = false;
+        }
+    }
+
+    /// Writes the frame back to the source if it is dirty.
+    fn write_back(&mut self, fidx: usize) -> Result<()> {
+        let writer = match self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let frame = &mut self.frames[fidx];
+        if frame.dirty {
+            let start = frame.page * self.page_sz;
+            let end = start + frame.len as u64;
+            let source = self
+                .source
+                .as_mut()
+                .expect("source is only taken when unwrapping");
+            (writer.write)(source, start, &frame.data[..frame.len]).context(
+                NAME,
+                Operation::Write,
+                Some(start..end),
+            )?;
+            frame.dirty = false;
+            self.refresh_fingerprint(end)?;
+        }
+        Ok(())
+    }
+
+    /// Writes every dirty frame back to the source in page order, and then
+    /// flushes the source.
+    fn flush(&mut self) -> Result<()> {
+        let writer = match self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let mut dirty: Vec<usize> = (0..self.frames.len())
+            .filter(|fidx| self.frames[*fidx].dirty)
+            .collect();
+        dirty.sort_by_key(|fidx| self.frames[*fidx].page);
+        for fidx in dirty {
+            self.write_back(fidx)?;
+        }
+        (writer.flush)(self.source()).context(NAME, Operation::Sync, None)
+    }
+
     fn load_page(&mut self, page: u64) -> Result<usize> {
-        match self.map.remove(&self.frames[self.front].page) {
-            Some(_) => {}
-            None => unreachable!(),
+        let fidx = self.front;
+        self.write_back(fidx)?;
+        let old_page = self.frames[fidx].page;
+        if old_page != NO_PAGE {
+            self.map.remove(&old_page);
         }
-        self.source.seek(SeekFrom::Start(page * self.page_sz))?;
-        self.source.read(&mut self.frames[self.front].data)?;
-        self.frames[self.front].page = page;
-        self.map.insert(page, self.front);
-        Ok(self.front)
+        self.map_frame_mut(fidx, |frame| {
+            frame.page = NO_PAGE;
+            frame.len = 0;
+        });
+        let start = page * self.page_sz;
+        self.source().seek(SeekFrom::Start(start)).context(
+            NAME,
+            Operation::Seek,
+            Some(start..start),
+        )?;
+        let source = self
+            .source
+            .as_mut()
+            .expect("source is only taken when unwrapping");
+        let len = fill(source, &mut self.frames[fidx].data).context(
+            NAME,
+            Operation::Read,
+            Some(start..start + self.page_sz),
+        )?;
+        self.map_frame_mut(fidx, |frame| {
+            frame.page = page;
+            frame.len = len;
+        });
+        self.map.insert(page, fidx);
+        Ok(fidx)
     }
 
     fn promote_frame(&mut self, fidx: usize) {
-        if self.back != self.front {
+        if fidx != self.back {
             let (next_idx, prev_idx) = self.map_frame(fidx, |frame| (frame.next, frame.prev));
-            if next_idx != NULL {
-                if prev_idx != NULL {
-                    self.get_frame_mut(prev_idx).next = next_idx;
-                    self.get_frame_mut(next_idx).prev = prev_idx;
-                } else {
-                    self.front = next_idx;
-                    self.get_frame_mut(next_idx).prev = NULL;
-                }
-                self.get_frame_mut(self.back).next = fidx;
-                let back_idx = self.back;
-                self.map_frame_mut(fidx, |frame| {
-                    frame.prev = back_idx;
-                    frame.next = NULL;
-                });
+            if prev_idx != NULL {
+                self.get_frame_mut(prev_idx).next = next_idx;
+            } else {
+                self.front = next_idx;
             }
+            self.get_frame_mut(next_idx).prev = prev_idx;
+            self.get_frame_mut(self.back).next = fidx;
+            let back_idx = self.back;
+            self.map_frame_mut(fidx, |frame| {
+                frame.prev = back_idx;
+                frame.next = NULL;
+            });
+            self.back = fidx;
         }
     }
 
-    fn get_chunk(&mut self, pos: u64) -> Result<&[u8]> {
-        let page = pos / self.page_sz;
-
+    /// Returns the index of the frame holding `page`, swapping the page in
+    /// if needed, and marks it as the most recently used frame.
+    fn get_page(&mut self, page: u64) -> Result<usize> {
         let fidx = match self.map.get(&page) {
             Some(fidx) => *fidx,
-            None => NULL,
+            None => self.load_page(page)?,
         };
+        self.promote_frame(fidx);
+        Ok(fidx)
+    }
 
-        let fidx = if fidx == NULL {
-            self.load_page(page)?
-        } else {
-            fidx
-        };
+    /// Returns the valid bytes of the page containing `pos`, starting at
+    /// `pos`. The returned chunk is empty if `pos` is at or beyond the end
+    /// of the data that could be read from the source.
+    fn get_chunk(&mut self, pos: u64) -> Result<&[u8]> {
+        let page = pos / self.page_sz;
+        let fidx = self.get_page(page)?;
+        let frame = self.get_frame(fidx);
+        let offset = ((pos - (page * self.page_sz)) as usize).min(frame.len);
+        Ok(&frame.data[offset..frame.len])
+    }
 
-        self.promote_frame(fidx);
+    /// Copies `data` into the frames of the pages it covers, starting at
+    /// `pos`. The frames are marked dirty, unless the write policy is
+    /// write-through, in which case the data is also written to the source.
+    fn write(&mut self, pos: u64, data: &[u8]) -> Result<()> {
+        let write_through = self.policy == WritePolicy::WriteThrough;
+        let mut at = pos;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let page = at / self.page_sz;
+            let fidx = self.get_page(page)?;
+            let offset = (at - page * self.page_sz) as usize;
+            let frame = self.get_frame_mut(fidx);
+            let len = rest.len().min(frame.data.len() - offset);
+            if frame.len < offset {
+                for byte in &mut frame.data[frame.len..offset] {
+                    *byte = 0;
+                }
+            }
+            frame.data[offset..offset + len].copy_from_slice(&rest[..len]);
+            frame.len = frame.len.max(offset + len);
+            frame.dirty = frame.dirty || !write_through;
+            at += len as u64;
+            rest = &rest[len..];
+        }
+        if write_through {
+            if let Some(writer) = self.writer {
+                let end = pos + data.len() as u64;
+                (writer.write)(self.source(), pos, data).context(
+                    NAME,
+                    Operation::Write,
+                    Some(pos..end),
+                )?;
+                self.refresh_fingerprint(end)?;
+            }
+        }
+        Ok(())
+    }
+}
 
-        Ok(&self.get_frame(fidx).data[(pos - (page * self.page_sz)) as usize..])
+impl<T: Read + Seek> Drop for SwapCacheImpl<T> {
+    /// Writes dirty frames back to the source. Errors are ignored, so the
+    /// cache should be flushed with `SwapCache::flush` to detect them.
+    fn drop(&mut self) {
+        if self.source.is_some() {
+            let _ = self.flush();
+        }
     }
 }
 
@@ -159,6 +376,21 @@ impl<T: Read + Seek> SwapCacheImpl<T> {
 /// will be replaced by the new page. Because interior mutability is
 /// required, the primary functionality of `SwapCache` is wrapped with
 /// a mutex, which also makes it thread safe.
+///
+/// When the source also implements `Write`, `SwapCache` can be written to
+/// with `SwapCache::write`. With the default `WritePolicy::WriteBack`,
+/// written pages are kept in memory as dirty frames and written to the
+/// source when they are evicted, when `SwapCache::flush` is called, when
+/// the source is taken back with `Cache::into_inner` or
+/// `Cache::replace_source`, or when the cache is dropped. Errors writing
+/// back dirty frames on drop are ignored, so the cache should be flushed
+/// first to detect them. With `WritePolicy::WriteThrough`, every write
+/// reaches the source immediately.
+///
+/// The fingerprint of the source is refreshed after every write the cache
+/// makes to it, so `Cache::validate` does not report the cache's own writes
+/// as modifications. If the source was modified by someone else, dirty
+/// frames are discarded along with the rest of the cached pages.
 pub struct SwapCache<T: Read + Seek> {
     sz: u64,
     cache_sz: usize,
@@ -167,15 +399,32 @@ pub struct SwapCache<T: Read + Seek> {
 
 impl<T: Read + Seek> SwapCache<T> {
     /// Creates a new `SwapCache` containing the passed source, and with pages
-    /// of size `page_size` bytes, and `frame_count` frames.
+    /// of size `page_size` bytes, and `frame_count` frames. The fingerprint
+    /// of the source only records its length until the first call to
+    /// `Cache::validate`. See `SwapCache::new_fingerprinted`.
     pub fn new(source: T, page_size: usize, frame_count: usize) -> Result<Self> {
+        Self::with_fingerprinter(source, page_size, frame_count, None)
+    }
+
+    pub(crate) fn with_fingerprinter(
+        source: T,
+        page_size: usize,
+        frame_count: usize,
+        fingerprinter: Option<FingerprintFn<T>>,
+    ) -> Result<Self> {
         let mut source = source;
-        let len = source.seek(SeekFrom::End(0))?;
+        let (len, fingerprint) = measure(&mut source, fingerprinter)?;
         if page_size != 0 && frame_count != 0 {
             Ok(SwapCache {
                 sz: len,
                 cache_sz: page_size * frame_count,
-                swap: Mutex::new(SwapCacheImpl::new(source, page_size, frame_count)?),
+                swap: Mutex::new(SwapCacheImpl::new(
+                    source,
+                    fingerprint,
+                    fingerprinter,
+                    page_size,
+                    frame_count,
+                )?),
             })
         } else if page_size == 0 {
             Err(Error::new_zero_cache(
@@ -187,6 +436,65 @@ impl<T: Read + Seek> SwapCache<T> {
             ))
         }
     }
+
+    /// Returns the policy used for writes to the cache.
+    pub fn write_policy(&self) -> WritePolicy {
+        match self.swap.lock() {
+            Ok(swap) => swap.policy,
+            Err(poisoned) => poisoned.into_inner().policy,
+        }
+    }
+}
+
+impl<T: Fingerprinted> SwapCache<T> {
+    /// Creates a new `SwapCache` like `SwapCache::new`, and records the full
+    /// fingerprint of the source, so that the first call to
+    /// `Cache::validate` detects any modification made after construction.
+    /// Sources passed to `Cache::replace_source` are fingerprinted the same
+    /// way.
+    pub fn new_fingerprinted(source: T, page_size: usize, frame_count: usize) -> Result<Self> {
+        Self::with_fingerprinter(source, page_size, frame_count, Some(T::fingerprint))
+    }
+}
+
+impl<T: Read + Write + Seek> SwapCache<T> {
+    /// Writes `data` to the cache at `offset`. Writing past the current end
+    /// of the cache extends it, but `offset` must not be beyond the end, or
+    /// an out of range error is returned. Depending on the write policy, the
+    /// data reaches the source immediately, or when the written pages are
+    /// written back.
+    pub fn write(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        if offset > self.sz {
+            return Err(Error::OutOfRange {
+                offset,
+                len: self.sz,
+            });
+        }
+        let swap = self.swap.get_mut()?;
+        swap.writer.get_or_insert(Writer {
+            write: write_at::<T>,
+            flush: flush_source::<T>,
+        });
+        swap.write(offset, data)?;
+        self.sz = self.sz.max(offset + data.len() as u64);
+        Ok(())
+    }
+
+    /// Writes all dirty frames back to the source, and flushes the source.
+    pub fn flush(&mut self) -> Result<()> {
+        self.swap.get_mut()?.flush()
+    }
+
+    /// Sets the policy used for subsequent writes to the cache. Switching to
+    /// `WritePolicy::WriteThrough` writes back all dirty frames first.
+    pub fn set_write_policy(&mut self, policy: WritePolicy) -> Result<()> {
+        let swap = self.swap.get_mut()?;
+        if policy == WritePolicy::WriteThrough {
+            swap.flush()?;
+        }
+        swap.policy = policy;
+        Ok(())
+    }
 }
 
 impl<T: Read + Seek> Cache for SwapCache<T> {
@@ -194,8 +502,26 @@ impl<T: Read + Seek> Cache for SwapCache<T> {
 
     fn into_inner(self) -> Result<T> {
         let mut swap = Mutex::into_inner(self.swap)?;
-        swap.source.seek(SeekFrom::Start(0))?;
-        Ok(swap.source)
+        swap.flush()?;
+        let mut source = swap.source.take().expect("source is only taken once");
+        source
+            .seek(SeekFrom::Start(0))
+            .context(NAME, Operation::Seek, Some(0..0))?;
+        Ok(source)
+    }
+
+    fn replace_source(&mut self, source: T) -> Result<T> {
+        let mut source = source;
+        let swap = self.swap.get_mut()?;
+        let (len, fingerprint) = measure(&mut source, swap.fingerprinter)?;
+        swap.flush()?;
+        swap.invalidate();
+        let mut old = std::mem::replace(swap.source(), source);
+        swap.fingerprint = fingerprint;
+        self.sz = len;
+        old.seek(SeekFrom::Start(0))
+            .context(NAME, Operation::Seek, Some(0..0))?;
+        Ok(old)
     }
 
     fn len(&self) -> u64 {
@@ -206,60 +532,87 @@ impl<T: Read + Seek> Cache for SwapCache<T> {
         self.cache_sz
     }
 
+    fn fingerprint(&self) -> Fingerprint {
+        match self.swap.lock() {
+            Ok(swap) => swap.fingerprint,
+            Err(poisoned) => poisoned.into_inner().fingerprint,
+        }
+    }
+
+    /// Dirty frames are not written back first, so the source is compared
+    /// as the cache last left it. If the source was modified, dirty frames
+    /// are discarded along with the rest of the cached pages.
+    fn validate(&mut self) -> Result<bool>
+    where
+        T: Fingerprinted,
+    {
+        let swap = self.swap.get_mut()?;
+        swap.fingerprinter = Some(T::fingerprint);
+        let current = swap
+            .source()
+            .fingerprint()
+            .context(NAME, Operation::Metadata, None)?;
+        if swap.fingerprint.removed_in(&current) {
+            return Err(Error::SourceRemoved);
+        }
+        let changed = !swap.fingerprint.matches(&current);
+        if changed {
+            swap.invalidate();
+            self.sz = current.len();
+        }
+        swap.fingerprint = current;
+        Ok(changed)
+    }
+
     fn traverse_chunks<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
         &self,
         range: R,
         f: F,
     ) -> Result<()> {
         let len = self.sz;
-        let start = match range.start_bound() {
-            Bound::Included(start) => {
-                if *start >= len {
-                    return Ok(());
-                } else {
-                    *start
-                }
-            }
-            Bound::Excluded(start) => {
-                let start = *start + 1;
-                if start > len {
-                    return Ok(());
-                } else {
-                    start
-                }
-            }
-            Bound::Unbounded => 0,
-        };
-        let end = match range.end_bound() {
-            Bound::Included(end) => {
-                if *end >= len {
-                    len
-                } else {
-                    *end + 1
-                }
-            }
-            Bound::Excluded(end) => {
-                if *end > len {
-                    len
-                } else {
-                    *end
-                }
-            }
-            Bound::Unbounded => len,
-        };
-        if start < len {
+        let Range { start, end } = bounds(&range, len);
+        if start < end {
             let mut f = f;
             let mut guard = self.swap.lock()?;
             let mut pos = start;
-            loop {
+            while pos < end {
                 let chunk = (*guard).get_chunk(pos)?;
-                let new_pos = pos + chunk.len() as u64;
-                if new_pos > end {
-                    return f(&chunk[..(new_pos - pos) as usize]);
-                } else {
-                    f(chunk)?;
+                if chunk.is_empty() {
+                    break;
+                }
+                let chunk = &chunk[..(chunk.len() as u64).min(end - pos) as usize];
+                f(chunk)?;
+                pos += chunk.len() as u64;
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks the pages covering the range from last to first, so that a
+    /// backward scan swaps each page in once and keeps the pages nearest
+    /// the scan position cached.
+    fn traverse_chunks_rev<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        let len = self.sz;
+        let Range { start, end } = bounds(&range, len);
+        if start < end {
+            let mut f = f;
+            let mut guard = self.swap.lock()?;
+            let page_sz = guard.page_sz;
+            let mut pos = end;
+            while pos > start {
+                let page_start = (pos - 1) / page_sz * page_sz;
+                let chunk = (*guard).get_chunk(page_start)?;
+                // Bytes beyond the data that could be read are skipped.
+                let from = (start.max(page_start) - page_start) as usize;
+                let to = ((pos - page_start) as usize).min(chunk.len());
+                if from < to {
+                    f(&chunk[from..to])?;
                 }
-                pos = new_pos;
+                pos = page_start;
             }
         }
         Ok(())
@@ -0,0 +1,1124 @@
+use super::cursor::cstr_len;
+use super::template_parser;
+use super::{Cache, Error, ReadAt, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// The deepest nesting of structs a template may reach during evaluation,
+/// which also bounds recursive struct definitions.
+const MAX_DEPTH: usize = 64;
+
+/// The longest `cstr` field, not counting its terminator, so that a missing
+/// terminator does not read the rest of the cache into memory.
+const MAX_CSTR_LEN: usize = 1 << 20;
+
+/// The byte order of a multi-byte value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endian {
+    /// Least significant byte first.
+    Little,
+
+    /// Most significant byte first.
+    Big,
+}
+
+/// A primitive numeric type of a template field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    /// An unsigned 8 bit integer.
+    U8,
+    /// A signed 8 bit integer.
+    I8,
+    /// An unsigned 16 bit integer.
+    U16,
+    /// A signed 16 bit integer.
+    I16,
+    /// An unsigned 32 bit integer.
+    U32,
+    /// A signed 32 bit integer.
+    I32,
+    /// An unsigned 64 bit integer.
+    U64,
+    /// A signed 64 bit integer.
+    I64,
+    /// A 32 bit float.
+    F32,
+    /// A 64 bit float.
+    F64,
+}
+
+impl Primitive {
+    /// Returns the size of the type in bytes.
+    pub fn size(self) -> u64 {
+        match self {
+            Primitive::U8 | Primitive::I8 => 1,
+            Primitive::U16 | Primitive::I16 => 2,
+            Primitive::U32 | Primitive::I32 | Primitive::F32 => 4,
+            Primitive::U64 | Primitive::I64 | Primitive::F64 => 8,
+        }
+    }
+
+    /// Returns the name of the type in the template text format.
+    pub fn name(self) -> &'static str {
+        match self {
+            Primitive::U8 => "u8",
+            Primitive::I8 => "i8",
+            Primitive::U16 => "u16",
+            Primitive::I16 => "i16",
+            Primitive::U32 => "u32",
+            Primitive::I32 => "i32",
+            Primitive::U64 => "u64",
+            Primitive::I64 => "i64",
+            Primitive::F32 => "f32",
+            Primitive::F64 => "f64",
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Primitive::F32 | Primitive::F64)
+    }
+
+    fn is_signed(self) -> bool {
+        matches!(
+            self,
+            Primitive::I8 | Primitive::I16 | Primitive::I32 | Primitive::I64
+        )
+    }
+}
+
+/// An expression over the fields of a template, used for array lengths and
+/// conditions.
+///
+/// Expressions are written in the C-like syntax of the template text
+/// format, such as `count * 2` or `version >= 2 && flags & 1`. Names refer
+/// to integer or enum fields laid out before the expression in the current
+/// struct or any enclosing struct, and `a.b` refers to field `b` of the
+/// struct field `a`. `Enum::Variant` is the value of an enum variant.
+/// Arithmetic is performed on 128 bit signed integers, and comparisons
+/// evaluate to 1 or 0. Parentheses and operators may nest at most 64 deep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr(pub(crate) ExprNode);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ExprNode {
+    Int(i128),
+    Path(Vec<String>),
+    Variant(String, String),
+    Unary(UnaryOp, Box<ExprNode>),
+    Binary(BinaryOp, Box<ExprNode>, Box<ExprNode>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UnaryOp {
+    Not,
+    Neg,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Expr {
+    /// Parses an expression. Returns `Error::Template` if the text is not a
+    /// valid expression.
+    pub fn parse(text: &str) -> Result<Self> {
+        template_parser::parse_expr(text).map(Expr)
+    }
+
+    /// Creates an expression with a constant value.
+    pub fn constant(value: u64) -> Self {
+        Expr(ExprNode::Int(value as i128))
+    }
+
+    /// Creates an expression with the value of a field, where `path` is a
+    /// field name or a dot separated path such as `header.count`.
+    pub fn field(path: &str) -> Self {
+        Expr(ExprNode::Path(path.split('.').map(String::from).collect()))
+    }
+}
+
+/// The type of a field in a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    /// A number of a primitive type, in the passed byte order.
+    Primitive(Primitive, Endian),
+
+    /// Raw bytes, whose length is the value of the expression.
+    Bytes(Expr),
+
+    /// A NUL-terminated string. The terminator is part of the field.
+    CStr,
+
+    /// An instance of the named struct.
+    Struct(String),
+
+    /// A value of the named enum.
+    Enum(String),
+
+    /// An array of elements of a type, whose length is the value of the
+    /// expression.
+    Array(Box<FieldType>, Expr),
+}
+
+/// The definition of a field of a struct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FieldDef {
+    pub(crate) name: String,
+    pub(crate) ty: FieldType,
+    pub(crate) condition: Option<Expr>,
+}
+
+/// The definition of a struct in a template: a sequence of fields laid out
+/// one after another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructDef {
+    name: String,
+    fields: Vec<FieldDef>,
+}
+
+impl StructDef {
+    /// Creates an empty struct with the passed name.
+    pub fn new(name: &str) -> Self {
+        StructDef {
+            name: name.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Appends a field to the struct.
+    pub fn field(mut self, name: &str, ty: FieldType) -> Self {
+        self.fields.push(FieldDef {
+            name: name.to_string(),
+            ty,
+            condition: None,
+        });
+        self
+    }
+
+    /// Appends a field to the struct that is only present when the
+    /// condition evaluates to a value other than zero.
+    pub fn field_if(mut self, name: &str, ty: FieldType, condition: Expr) -> Self {
+        self.fields.push(FieldDef {
+            name: name.to_string(),
+            ty,
+            condition: Some(condition),
+        });
+        self
+    }
+
+    /// Returns the name of the struct.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn push(&mut self, field: FieldDef) {
+        self.fields.push(field);
+    }
+}
+
+/// The definition of an enum in a template: an integer with names for some
+/// of its values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumDef {
+    name: String,
+    repr: Primitive,
+    endian: Endian,
+    variants: Vec<(String, u64)>,
+}
+
+impl EnumDef {
+    /// Creates an enum with the passed name and no variants, stored as the
+    /// passed integer type in the passed byte order. The value is read as
+    /// an unsigned integer even if the type is signed.
+    pub fn new(name: &str, repr: Primitive, endian: Endian) -> Self {
+        EnumDef {
+            name: name.to_string(),
+            repr,
+            endian,
+            variants: Vec::new(),
+        }
+    }
+
+    /// Adds a named variant to the enum.
+    pub fn variant(mut self, name: &str, value: u64) -> Self {
+        self.variants.push((name.to_string(), value));
+        self
+    }
+
+    /// Returns the name of the enum.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name of the variant with the passed value, if any.
+    pub fn variant_name(&self, value: u64) -> Option<&str> {
+        self.variants
+            .iter()
+            .find(|(_, v)| *v == value)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn variant_value(&self, name: &str) -> Option<u64> {
+        self.variants
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// A description of the layout of binary data, similar to the templates of
+/// hex editors.
+///
+/// A template is a set of struct and enum definitions and a root struct.
+/// Templates are built in Rust with `StructDef` and `EnumDef`, or parsed
+/// from text with `Template::parse`:
+///
+/// ```text
+/// endian big;
+///
+/// enum Kind : u8 { Data = 1, Index = 2 }
+///
+/// struct Entry {
+///     Kind kind;
+///     u32 len;
+///     bytes payload[len];
+///     if (kind == Kind::Index) u16le refs[len / 4];
+/// }
+///
+/// struct File {
+///     bytes magic[4];
+///     u16 count;
+///     Entry entries[count];
+///     cstr comment;
+/// }
+///
+/// root File;
+/// ```
+///
+/// Primitive types are `u8`, `i8`, `u16`, `i16`, `u32`, `i32`, `u64`,
+/// `i64`, `f32` and `f64`, optionally suffixed with `le` or `be` to override
+/// the byte order set by the last `endian` directive, which is little-endian
+/// by default. `bytes name[len]` is a run of raw bytes, `cstr` is a
+/// NUL-terminated string of at most 1 MiB, and any type followed by `[len]`
+/// is an array. Fields prefixed with `if (condition)` are only present when
+/// the condition is not zero. See `Expr` for the expression syntax. Enums
+/// must be defined before the structs that use them. The `root` directive
+/// sets the root struct like `Template::set_root`, and without it the root
+/// is chosen as described for `Template::add_struct`. Comments start with
+/// `//`.
+///
+/// `Template::evaluate` lays the root struct over a cache, producing a tree
+/// of `Field` values that is evaluated lazily as it is navigated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Template {
+    structs: HashMap<String, StructDef>,
+    enums: HashMap<String, EnumDef>,
+    root: Option<FieldType>,
+    root_is_set: bool,
+}
+
+impl Template {
+    /// Creates an empty template.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a template from the text format described in the type
+    /// documentation. Returns `Error::Template` if the text is not a valid
+    /// template.
+    pub fn parse(text: &str) -> Result<Self> {
+        let template = template_parser::parse_template(text)?;
+        template.check()?;
+        Ok(template)
+    }
+
+    /// Adds a struct definition, replacing any struct with the same name.
+    /// The last struct added is the root, unless the root is set with
+    /// `Template::set_root`, whether before or after the struct is added.
+    pub fn add_struct(&mut self, def: StructDef) {
+        if !self.root_is_set {
+            self.root = Some(FieldType::Struct(def.name.clone()));
+        }
+        self.structs.insert(def.name.clone(), def);
+    }
+
+    /// Adds an enum definition, replacing any enum with the same name.
+    pub fn add_enum(&mut self, def: EnumDef) {
+        self.enums.insert(def.name.clone(), def);
+    }
+
+    /// Sets the struct that is laid over the data by
+    /// `Template::evaluate`.
+    pub fn set_root(&mut self, name: &str) {
+        self.root = Some(FieldType::Struct(name.to_string()));
+        self.root_is_set = true;
+    }
+
+    /// Returns the name of the root struct, if any.
+    pub fn root(&self) -> Option<&str> {
+        match self.root {
+            Some(FieldType::Struct(ref name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the struct with the passed name, if any.
+    pub fn get_struct(&self, name: &str) -> Option<&StructDef> {
+        self.structs.get(name)
+    }
+
+    /// Returns the enum with the passed name, if any.
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDef> {
+        self.enums.get(name)
+    }
+
+    pub(crate) fn has_enum(&self, name: &str) -> bool {
+        self.enums.contains_key(name)
+    }
+
+    /// Lays the root struct over the cache at the passed offset, and returns
+    /// the root field. The fields of the root are only laid out when
+    /// `Field::children` or a similar method is called, and values are only
+    /// read by `Field::value`. Returns `Error::Template` if the template
+    /// refers to an undefined struct or enum or has no root.
+    pub fn evaluate<'a, C: Cache + ?Sized>(
+        &'a self,
+        cache: &'a C,
+        offset: u64,
+    ) -> Result<Field<'a, C>> {
+        self.check()?;
+        let ty = self
+            .root
+            .as_ref()
+            .ok_or_else(|| Error::Template("template has no root struct".into()))?;
+        let eval = Evaluator {
+            template: self,
+            cache,
+        };
+        let scope = Rc::new(Scope::root());
+        let size = eval.size_of(ty, offset, &scope)?;
+        eval.check_bounds(offset, size)?;
+        Ok(Field {
+            eval,
+            name: self.root().unwrap_or_default().to_string(),
+            offset,
+            size,
+            ty,
+            scope,
+        })
+    }
+
+    /// Checks that every struct and enum referred to is defined.
+    fn check(&self) -> Result<()> {
+        if let Some(ref root) = self.root {
+            self.check_type(root)?;
+        }
+        for def in self.structs.values() {
+            for field in def.fields.iter() {
+                self.check_type(&field.ty)?;
+            }
+        }
+        for def in self.enums.values() {
+            if def.repr.is_float() {
+                return Err(Error::Template(format!(
+                    "enum `{}` must be stored as an integer",
+                    def.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_type(&self, ty: &FieldType) -> Result<()> {
+        match ty {
+            FieldType::Struct(name) if !self.structs.contains_key(name) => {
+                Err(Error::Template(format!("struct `{}` is not defined", name)))
+            }
+            FieldType::Enum(name) if !self.enums.contains_key(name) => {
+                Err(Error::Template(format!("enum `{}` is not defined", name)))
+            }
+            FieldType::Array(element, _) => self.check_type(element),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A field laid out before the expression being evaluated.
+#[derive(Clone)]
+struct Entry<'a> {
+    name: &'a str,
+    offset: u64,
+    size: u64,
+    ty: &'a FieldType,
+}
+
+/// The fields of a struct laid out so far, and the struct enclosing it.
+struct Scope<'a> {
+    parent: Option<Rc<Scope<'a>>>,
+    entries: RefCell<Vec<Entry<'a>>>,
+    depth: usize,
+}
+
+impl<'a> Scope<'a> {
+    fn root() -> Self {
+        Scope {
+            parent: None,
+            entries: RefCell::new(Vec::new()),
+            depth: 0,
+        }
+    }
+
+    fn child(parent: &Rc<Scope<'a>>) -> Result<Self> {
+        if parent.depth >= MAX_DEPTH {
+            return Err(Error::Template(format!(
+                "template nests more than {} structs deep",
+                MAX_DEPTH
+            )));
+        }
+        Ok(Scope {
+            parent: Some(parent.clone()),
+            entries: RefCell::new(Vec::new()),
+            depth: parent.depth + 1,
+        })
+    }
+
+    /// Finds the most recent field with the passed name in this scope or
+    /// an enclosing one, and returns it with the scope it belongs to.
+    fn lookup(self: &Rc<Self>, name: &str) -> Option<(Entry<'a>, Rc<Scope<'a>>)> {
+        let mut scope = self.clone();
+        loop {
+            let found = scope
+                .entries
+                .borrow()
+                .iter()
+                .rev()
+                .find(|entry| entry.name == name)
+                .cloned();
+            if let Some(entry) = found {
+                return Some((entry, scope));
+            }
+            scope = scope.parent.clone()?;
+        }
+    }
+}
+
+/// The template and cache of an evaluation.
+struct Evaluator<'a, C: Cache + ?Sized> {
+    template: &'a Template,
+    cache: &'a C,
+}
+
+impl<'a, C: Cache + ?Sized> Clone for Evaluator<'a, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, C: Cache + ?Sized> Copy for Evaluator<'a, C> {}
+
+impl<'a, C: Cache + ?Sized> Evaluator<'a, C> {
+    fn check_bounds(&self, offset: u64, size: u64) -> Result<()> {
+        let len = self.cache.len();
+        if offset.saturating_add(size) > len {
+            return Err(Error::OutOfRange {
+                offset: len.max(offset),
+                len,
+            });
+        }
+        Ok(())
+    }
+
+    fn struct_def(&self, name: &str) -> &'a StructDef {
+        // Templates are checked before evaluation.
+        &self.template.structs[name]
+    }
+
+    fn enum_def(&self, name: &str) -> &'a EnumDef {
+        &self.template.enums[name]
+    }
+
+    /// Returns the size of a type that does not depend on the data, if any.
+    fn static_size(&self, ty: &FieldType, depth: usize) -> Option<u64> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match ty {
+            FieldType::Primitive(primitive, _) => Some(primitive.size()),
+            FieldType::Enum(name) => Some(self.enum_def(name).repr.size()),
+            FieldType::Bytes(Expr(ExprNode::Int(len))) => u64::try_from(*len).ok(),
+            FieldType::Array(element, Expr(ExprNode::Int(count))) => self
+                .static_size(element, depth + 1)?
+                .checked_mul(u64::try_from(*count).ok()?),
+            FieldType::Struct(name) => {
+                let mut size = 0u64;
+                for field in self.struct_def(name).fields.iter() {
+                    if field.condition.is_some() {
+                        return None;
+                    }
+                    size = size.checked_add(self.static_size(&field.ty, depth + 1)?)?;
+                }
+                Some(size)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the size of a field of the passed type at the passed offset,
+    /// where `scope` holds the fields laid out before it.
+    fn size_of(&self, ty: &'a FieldType, offset: u64, scope: &Rc<Scope<'a>>) -> Result<u64> {
+        if let Some(size) = self.static_size(ty, scope.depth) {
+            return Ok(size);
+        }
+        match ty {
+            FieldType::Bytes(len) => self.count(len, scope),
+            FieldType::CStr => Ok(cstr_len(self.cache, offset, MAX_CSTR_LEN)? as u64 + 1),
+            FieldType::Struct(name) => {
+                let inner = self.layout(self.struct_def(name), offset, scope)?;
+                let entries = inner.entries.borrow();
+                Ok(entries
+                    .last()
+                    .map_or(0, |entry| entry.offset + entry.size - offset))
+            }
+            FieldType::Array(element, count) => {
+                let count = self.element_count(element, count, scope)?;
+                if let Some(size) = self.static_size(element, scope.depth) {
+                    return size
+                        .checked_mul(count)
+                        .ok_or_else(|| Error::Template("array is larger than 2^64 bytes".into()));
+                }
+                // Elements of variable size are assumed to be at least one
+                // byte long, which bounds the walk below.
+                if count > self.cache.len().saturating_sub(offset) {
+                    return Err(Error::OutOfRange {
+                        offset: self.cache.len().max(offset),
+                        len: self.cache.len(),
+                    });
+                }
+                let mut pos = offset;
+                for _ in 0..count {
+                    let size = self.size_of(element, pos, scope)?;
+                    self.check_bounds(pos, size)?;
+                    pos += size;
+                }
+                Ok(pos - offset)
+            }
+            _ => unreachable!("primitive and enum sizes are static"),
+        }
+    }
+
+    /// Lays out the fields of a struct at the passed offset, and returns
+    /// the scope holding them.
+    fn layout(
+        &self,
+        def: &'a StructDef,
+        offset: u64,
+        parent: &Rc<Scope<'a>>,
+    ) -> Result<Rc<Scope<'a>>> {
+        let scope = Rc::new(Scope::child(parent)?);
+        let mut pos = offset;
+        for field in def.fields.iter() {
+            if let Some(ref condition) = field.condition {
+                if self.eval(&condition.0, &scope)? == 0 {
+                    continue;
+                }
+            }
+            let size = self.size_of(&field.ty, pos, &scope)?;
+            self.check_bounds(pos, size)?;
+            scope.entries.borrow_mut().push(Entry {
+                name: &field.name,
+                offset: pos,
+                size,
+                ty: &field.ty,
+            });
+            pos += size;
+        }
+        Ok(scope)
+    }
+
+    /// Evaluates an expression used as a length or count.
+    fn count(&self, expr: &Expr, scope: &Rc<Scope<'a>>) -> Result<u64> {
+        let value = self.eval(&expr.0, scope)?;
+        u64::try_from(value)
+            .map_err(|_| Error::Template(format!("invalid length or count {}", value)))
+    }
+
+    /// Evaluates the count of an array of the passed element type. Elements
+    /// of no size are rejected, since any count of them would fit in the
+    /// cache and walking them might never end.
+    fn element_count(
+        &self,
+        element: &FieldType,
+        expr: &Expr,
+        scope: &Rc<Scope<'a>>,
+    ) -> Result<u64> {
+        let count = self.count(expr, scope)?;
+        if count > 0 && self.static_size(element, scope.depth) == Some(0) {
+            return Err(Error::Template("array elements must not be empty".into()));
+        }
+        Ok(count)
+    }
+
+    fn eval(&self, expr: &ExprNode, scope: &Rc<Scope<'a>>) -> Result<i128> {
+        let value = match expr {
+            ExprNode::Int(value) => *value,
+            ExprNode::Path(path) => self.resolve(path, scope)?,
+            ExprNode::Variant(name, variant) => {
+                let value = self
+                    .template
+                    .enums
+                    .get(name)
+                    .and_then(|def| def.variant_value(variant))
+                    .ok_or_else(|| {
+                        Error::Template(format!("`{}::{}` is not defined", name, variant))
+                    })?;
+                value as i128
+            }
+            ExprNode::Unary(op, operand) => {
+                let value = self.eval(operand, scope)?;
+                match op {
+                    UnaryOp::Not => (value == 0) as i128,
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::BitNot => !value,
+                }
+            }
+            ExprNode::Binary(BinaryOp::And, lhs, rhs) => {
+                (self.eval(lhs, scope)? != 0 && self.eval(rhs, scope)? != 0) as i128
+            }
+            ExprNode::Binary(BinaryOp::Or, lhs, rhs) => {
+                (self.eval(lhs, scope)? != 0 || self.eval(rhs, scope)? != 0) as i128
+            }
+            ExprNode::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs, scope)?;
+                let rhs = self.eval(rhs, scope)?;
+                match op {
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::Eq => (lhs == rhs) as i128,
+                    BinaryOp::Ne => (lhs != rhs) as i128,
+                    BinaryOp::Lt => (lhs < rhs) as i128,
+                    BinaryOp::Le => (lhs <= rhs) as i128,
+                    BinaryOp::Gt => (lhs > rhs) as i128,
+                    BinaryOp::Ge => (lhs >= rhs) as i128,
+                    BinaryOp::Shl | BinaryOp::Shr => {
+                        let shift = u32::try_from(rhs)
+                            .ok()
+                            .filter(|shift| *shift < 128)
+                            .ok_or_else(|| Error::Template(format!("invalid shift by {}", rhs)))?;
+                        if *op == BinaryOp::Shl {
+                            lhs.wrapping_shl(shift)
+                        } else {
+                            lhs >> shift
+                        }
+                    }
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div | BinaryOp::Rem if rhs == 0 => {
+                        return Err(Error::Template("division by zero".into()))
+                    }
+                    BinaryOp::Div => lhs.wrapping_div(rhs),
+                    BinaryOp::Rem => lhs.wrapping_rem(rhs),
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    /// Returns the value of the integer or enum field at the passed path.
+    fn resolve(&self, path: &[String], scope: &Rc<Scope<'a>>) -> Result<i128> {
+        let not_found = || Error::Template(format!("field `{}` is not defined", path.join(".")));
+        let (mut entry, mut scope) = scope.lookup(&path[0]).ok_or_else(not_found)?;
+        for name in path[1..].iter() {
+            let def = match entry.ty {
+                FieldType::Struct(ref def) => self.struct_def(def),
+                _ => return Err(not_found()),
+            };
+            scope = self.layout(def, entry.offset, &scope)?;
+            entry = scope
+                .entries
+                .borrow()
+                .iter()
+                .find(|entry| entry.name == name)
+                .cloned()
+                .ok_or_else(not_found)?;
+        }
+        match self.read(entry.ty, entry.offset)? {
+            Some(Value::Unsigned(value)) | Some(Value::Enum { value, .. }) => Ok(value as i128),
+            Some(Value::Signed(value)) => Ok(value as i128),
+            _ => Err(Error::Template(format!(
+                "field `{}` is not an integer",
+                path.join(".")
+            ))),
+        }
+    }
+
+    /// Reads the value of a field of the passed type and size.
+    fn read_sized(&self, ty: &FieldType, offset: u64, size: u64) -> Result<Option<Value>> {
+        match ty {
+            FieldType::Bytes(_) => Ok(Some(Value::Bytes(
+                self.cache.bytes_at(offset, size as usize)?,
+            ))),
+            FieldType::CStr => Ok(Some(Value::Bytes(
+                self.cache.bytes_at(offset, size as usize - 1)?,
+            ))),
+            _ => self.read(ty, offset),
+        }
+    }
+
+    /// Reads the value of a field of a fixed size type.
+    fn read(&self, ty: &FieldType, offset: u64) -> Result<Option<Value>> {
+        let (primitive, endian) = match ty {
+            FieldType::Primitive(primitive, endian) => (*primitive, *endian),
+            FieldType::Enum(name) => {
+                let def = self.enum_def(name);
+                let value = self.read_unsigned(def.repr, def.endian, offset)?;
+                return Ok(Some(Value::Enum {
+                    value,
+                    variant: def.variant_name(value).map(String::from),
+                }));
+            }
+            _ => return Ok(None),
+        };
+        let cache = self.cache;
+        let big = endian == Endian::Big;
+        let value = match primitive {
+            Primitive::F32 => Value::Float(if big {
+                cache.f32_be_at(offset)?
+            } else {
+                cache.f32_le_at(offset)?
+            } as f64),
+            Primitive::F64 => Value::Float(if big {
+                cache.f64_be_at(offset)?
+            } else {
+                cache.f64_le_at(offset)?
+            }),
+            _ => {
+                let value = self.read_unsigned(primitive, endian, offset)?;
+                if primitive.is_signed() {
+                    let shift = 64 - 8 * primitive.size() as u32;
+                    Value::Signed(((value << shift) as i64) >> shift)
+                } else {
+                    Value::Unsigned(value)
+                }
+            }
+        };
+        Ok(Some(value))
+    }
+
+    fn read_unsigned(&self, primitive: Primitive, endian: Endian, offset: u64) -> Result<u64> {
+        let cache = self.cache;
+        let big = endian == Endian::Big;
+        Ok(match primitive.size() {
+            1 => cache.u8_at(offset)? as u64,
+            2 if big => cache.u16_be_at(offset)? as u64,
+            2 => cache.u16_le_at(offset)? as u64,
+            4 if big => cache.u32_be_at(offset)? as u64,
+            4 => cache.u32_le_at(offset)? as u64,
+            _ if big => cache.u64_be_at(offset)?,
+            _ => cache.u64_le_at(offset)?,
+        })
+    }
+}
+
+/// The value of a field of a primitive, bytes, string or enum type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// The value of an unsigned integer field.
+    Unsigned(u64),
+
+    /// The value of a signed integer field.
+    Signed(i64),
+
+    /// The value of a float field.
+    Float(f64),
+
+    /// The contents of a bytes field, or of a string field without its
+    /// terminator.
+    Bytes(Vec<u8>),
+
+    /// The value of an enum field, and the name of its variant, if any.
+    Enum {
+        /// The value of the field.
+        value: u64,
+        /// The name of the variant with the value, if any.
+        variant: Option<String>,
+    },
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Unsigned(value) => write!(f, "{}", value),
+            Value::Signed(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Bytes(bytes) => {
+                for (idx, byte) in bytes.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{:02X}", byte)?;
+                }
+                Ok(())
+            }
+            Value::Enum {
+                variant: Some(variant),
+                ..
+            } => write!(f, "{}", variant),
+            Value::Enum { value, .. } => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A field of a template laid over a cache.
+///
+/// Fields form a tree rooted at the field returned by `Template::evaluate`.
+/// The offset and size of a field are known when it is created, but the
+/// fields of a struct or array are only laid out when they are requested,
+/// and values are only read by `Field::value`, so only the parts of a large
+/// structure that are navigated to are evaluated. Fields do not cache their
+/// children, so navigating to the same field again evaluates it again.
+pub struct Field<'a, C: Cache + ?Sized> {
+    eval: Evaluator<'a, C>,
+    name: String,
+    offset: u64,
+    size: u64,
+    ty: &'a FieldType,
+    scope: Rc<Scope<'a>>,
+}
+
+impl<'a, C: Cache + ?Sized> Clone for Field<'a, C> {
+    fn clone(&self) -> Self {
+        Field {
+            eval: self.eval,
+            name: self.name.clone(),
+            offset: self.offset,
+            size: self.size,
+            ty: self.ty,
+            scope: self.scope.clone(),
+        }
+    }
+}
+
+impl<'a, C: Cache + ?Sized> std::fmt::Debug for Field<'a, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Field")
+            .field("name", &self.name)
+            .field("type", &self.type_name())
+            .field("offset", &self.offset)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl<'a, C: Cache + ?Sized> Field<'a, C> {
+    /// Returns the name of the field. Array elements are named by their
+    /// index, such as `[3]`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the offset of the first byte of the field.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size of the field in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the range of bytes covered by the field.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.size
+    }
+
+    /// Returns the type of the field.
+    pub fn field_type(&self) -> &'a FieldType {
+        self.ty
+    }
+
+    /// Returns the name of the type of the field, as it would be written in
+    /// the template text format.
+    pub fn type_name(&self) -> String {
+        fn name(ty: &FieldType) -> String {
+            match ty {
+                FieldType::Primitive(primitive, Endian::Little) if primitive.size() > 1 => {
+                    format!("{}le", primitive.name())
+                }
+                FieldType::Primitive(primitive, Endian::Big) if primitive.size() > 1 => {
+                    format!("{}be", primitive.name())
+                }
+                FieldType::Primitive(primitive, _) => primitive.name().to_string(),
+                FieldType::Bytes(_) => "bytes".to_string(),
+                FieldType::CStr => "cstr".to_string(),
+                FieldType::Struct(name) | FieldType::Enum(name) => name.clone(),
+                FieldType::Array(element, _) => format!("{}[]", name(element)),
+            }
+        }
+        name(self.ty)
+    }
+
+    /// Returns true if the field is a struct, false otherwise.
+    pub fn is_struct(&self) -> bool {
+        matches!(self.ty, FieldType::Struct(_))
+    }
+
+    /// Returns true if the field is an array, false otherwise.
+    pub fn is_array(&self) -> bool {
+        matches!(self.ty, FieldType::Array(..))
+    }
+
+    /// Reads the value of the field. Returns `None` for structs and arrays,
+    /// whose values are their children.
+    pub fn value(&self) -> Result<Option<Value>> {
+        self.eval.read_sized(self.ty, self.offset, self.size)
+    }
+
+    /// Lays out and returns the fields of a struct, or the elements of an
+    /// array, in ascending order of offset. Fields whose condition is false
+    /// are omitted. Returns no fields for other types.
+    pub fn children(&self) -> Result<Vec<Field<'a, C>>> {
+        match self.ty {
+            FieldType::Struct(name) => {
+                let scope =
+                    self.eval
+                        .layout(self.eval.struct_def(name), self.offset, &self.scope)?;
+                let entries = scope.entries.borrow();
+                Ok(entries
+                    .iter()
+                    .map(|entry| Field {
+                        eval: self.eval,
+                        name: entry.name.to_string(),
+                        offset: entry.offset,
+                        size: entry.size,
+                        ty: entry.ty,
+                        scope: scope.clone(),
+                    })
+                    .collect())
+            }
+            FieldType::Array(element, count) => {
+                let count = self.eval.element_count(element, count, &self.scope)?;
+                let mut elements = Vec::new();
+                let mut pos = self.offset;
+                for idx in 0..count {
+                    let size = self.eval.size_of(element, pos, &self.scope)?;
+                    elements.push(self.element_field(idx, pos, size));
+                    pos += size;
+                }
+                Ok(elements)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the field of a struct with the passed name, or `None` if
+    /// the field is not a struct, or has no such field or the field's
+    /// condition is false.
+    pub fn child(&self, name: &str) -> Result<Option<Field<'a, C>>> {
+        if !self.is_struct() {
+            return Ok(None);
+        }
+        Ok(self
+            .children()?
+            .into_iter()
+            .find(|field| field.name == name))
+    }
+
+    /// Returns the number of elements of an array, or `None` if the field
+    /// is not an array.
+    pub fn len(&self) -> Result<Option<u64>> {
+        match self.ty {
+            FieldType::Array(_, count) => self.eval.count(count, &self.scope).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the element of an array at the passed index, or `None` if
+    /// the field is not an array or the index is out of bounds. Elements of
+    /// a fixed size are found without laying out the elements before them.
+    pub fn element(&self, idx: u64) -> Result<Option<Field<'a, C>>> {
+        let (element, count) = match self.ty {
+            FieldType::Array(element, count) => (element, self.eval.count(count, &self.scope)?),
+            _ => return Ok(None),
+        };
+        if idx >= count {
+            return Ok(None);
+        }
+        if let Some(size) = self.eval.static_size(element, self.scope.depth) {
+            return Ok(Some(self.element_field(
+                idx,
+                self.offset + idx * size,
+                size,
+            )));
+        }
+        Ok(self.children()?.into_iter().nth(idx as usize))
+    }
+
+    /// Returns the descendant at the passed path of field names and array
+    /// indices, such as `entries[2].kind`, or `None` if there is none.
+    pub fn get(&self, path: &str) -> Result<Option<Field<'a, C>>> {
+        let mut field = self.clone();
+        for part in path.split('.') {
+            let (name, indices) = match part.find('[') {
+                Some(idx) => part.split_at(idx),
+                None => (part, ""),
+            };
+            if !name.is_empty() {
+                field = match field.child(name)? {
+                    Some(child) => child,
+                    None => return Ok(None),
+                };
+            }
+            for index in indices.split('[').skip(1) {
+                let index = index
+                    .strip_suffix(']')
+                    .and_then(|index| index.parse().ok())
+                    .ok_or_else(|| Error::Template(format!("invalid field path `{}`", path)))?;
+                field = match field.element(index)? {
+                    Some(element) => element,
+                    None => return Ok(None),
+                };
+            }
+        }
+        Ok(Some(field))
+    }
+
+    fn element_field(&self, idx: u64, offset: u64, size: u64) -> Field<'a, C> {
+        let element = match self.ty {
+            FieldType::Array(element, _) => element,
+            _ => unreachable!(),
+        };
+        Field {
+            eval: self.eval,
+            name: format!("[{}]", idx),
+            offset,
+            size,
+            ty: element,
+            scope: self.scope.clone(),
+        }
+    }
+}
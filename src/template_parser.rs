@@ -0,0 +1,434 @@
+use super::template::{
+    BinaryOp, Endian, EnumDef, Expr, ExprNode, FieldDef, FieldType, Primitive, StructDef, Template,
+    UnaryOp,
+};
+use super::{Error, Result};
+
+/// The punctuation of the template text format, with longer tokens before
+/// their prefixes.
+const PUNCTS: &[&str] = &[
+    "::", "==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "{", "}", "[", "]", "(", ")", ";", ":",
+    ",", "=", ".", "+", "-", "*", "/", "%", "&", "|", "^", "!", "~", "<", ">",
+];
+
+/// The deepest nesting of parentheses, unary operators and binary operators
+/// in an expression, which bounds the recursion of the parser and of the
+/// evaluator. Each parenthesis recurses through every precedence level, so
+/// this is kept low enough for small thread stacks.
+const MAX_DEPTH: usize = 64;
+
+/// The binary operators of expressions, from the lowest precedence to the
+/// highest.
+const LEVELS: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Int(i128),
+    Punct(&'static str),
+    End,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Int(value) => write!(f, "`{}`", value),
+            Token::Punct(punct) => write!(f, "`{}`", punct),
+            Token::End => write!(f, "end of text"),
+        }
+    }
+}
+
+/// Splits text into tokens, each with the line it is on.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut rest = text;
+    loop {
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        rest = trimmed;
+        if let Some(after) = rest.strip_prefix('\n') {
+            line += 1;
+            rest = after;
+            continue;
+        }
+        if rest.starts_with("//") {
+            rest = &rest[rest.find('\n').unwrap_or(rest.len())..];
+            continue;
+        }
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => break,
+        };
+        let len = if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push((Token::Ident(rest[..len].to_string()), line));
+            len
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let literal = rest[..len].replace('_', "");
+            let value = match literal
+                .strip_prefix("0x")
+                .or_else(|| literal.strip_prefix("0X"))
+            {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => literal.parse(),
+            }
+            .map_err(|_| {
+                Error::Template(format!(
+                    "invalid integer `{}` at line {}",
+                    &rest[..len],
+                    line
+                ))
+            })?;
+            tokens.push((Token::Int(value as i128), line));
+            len
+        } else {
+            let punct = PUNCTS
+                .iter()
+                .find(|punct| rest.starts_with(*punct))
+                .ok_or_else(|| {
+                    Error::Template(format!("unexpected character '{}' at line {}", c, line))
+                })?;
+            tokens.push((Token::Punct(punct), line));
+            punct.len()
+        };
+        rest = &rest[len..];
+    }
+    tokens.push((Token::End, line));
+    Ok(tokens)
+}
+
+/// Parses a primitive type name, such as `u32` or `i16be`, with its byte
+/// order if it has a suffix.
+fn primitive(name: &str) -> Option<(Primitive, Option<Endian>)> {
+    let (base, endian) = if let Some(base) = name.strip_suffix("le") {
+        (base, Some(Endian::Little))
+    } else if let Some(base) = name.strip_suffix("be") {
+        (base, Some(Endian::Big))
+    } else {
+        (name, None)
+    };
+    let primitive = match base {
+        "u8" => Primitive::U8,
+        "i8" => Primitive::I8,
+        "u16" => Primitive::U16,
+        "i16" => Primitive::I16,
+        "u32" => Primitive::U32,
+        "i32" => Primitive::I32,
+        "u64" => Primitive::U64,
+        "i64" => Primitive::I64,
+        "f32" => Primitive::F32,
+        "f64" => Primitive::F64,
+        _ => return None,
+    };
+    Some((primitive, endian))
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self> {
+        Ok(Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        let (token, line) = &self.tokens[self.pos];
+        Error::Template(format!("{}, found {} at line {}", msg, token, line))
+    }
+
+    /// Enters one more level of expression nesting, or fails if it is too
+    /// deep.
+    fn nest(&mut self) -> Result<()> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::Template(format!(
+                "expression nests more than {} deep at line {}",
+                MAX_DEPTH, self.tokens[self.pos].1
+            )));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Consumes the passed punctuation if it is next.
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Token::Punct(next) if *next == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", punct)))
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> Result<String> {
+        match self.peek() {
+            Token::Ident(name) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error(&format!("expected {}", what))),
+        }
+    }
+
+    fn int(&mut self) -> Result<u64> {
+        match self.peek() {
+            Token::Int(value) => {
+                let value = *value as u64;
+                self.pos += 1;
+                Ok(value)
+            }
+            _ => Err(self.error("expected an integer")),
+        }
+    }
+
+    fn template(&mut self) -> Result<Template> {
+        let mut template = Template::new();
+        let mut endian = Endian::Little;
+        loop {
+            let keyword = match self.peek() {
+                Token::End => break,
+                Token::Ident(keyword) => keyword.clone(),
+                _ => return Err(self.error("expected `struct`, `enum`, `endian` or `root`")),
+            };
+            self.pos += 1;
+            match keyword.as_str() {
+                "endian" => {
+                    endian = match self.ident("`little` or `big`")?.as_str() {
+                        "little" => Endian::Little,
+                        "big" => Endian::Big,
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.error("expected `little` or `big`"));
+                        }
+                    };
+                    self.expect(";")?;
+                }
+                "root" => {
+                    let root = self.ident("a struct name")?;
+                    template.set_root(&root);
+                    self.expect(";")?;
+                }
+                "enum" => {
+                    let def = self.enum_def(endian)?;
+                    template.add_enum(def);
+                }
+                "struct" => {
+                    let def = self.struct_def(&template, endian)?;
+                    template.add_struct(def);
+                }
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected `struct`, `enum`, `endian` or `root`"));
+                }
+            }
+        }
+        Ok(template)
+    }
+
+    fn enum_def(&mut self, endian: Endian) -> Result<EnumDef> {
+        let name = self.ident("an enum name")?;
+        self.expect(":")?;
+        let (repr, repr_endian) = match self.peek() {
+            Token::Ident(repr) => primitive(repr),
+            _ => None,
+        }
+        .ok_or_else(|| self.error("expected an integer type"))?;
+        self.pos += 1;
+        let mut def = EnumDef::new(&name, repr, repr_endian.unwrap_or(endian));
+        self.expect("{")?;
+        while !self.eat("}") {
+            let variant = self.ident("a variant name")?;
+            self.expect("=")?;
+            def = def.variant(&variant, self.int()?);
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+        self.eat(";");
+        Ok(def)
+    }
+
+    fn struct_def(&mut self, template: &Template, endian: Endian) -> Result<StructDef> {
+        let mut def = StructDef::new(&self.ident("a struct name")?);
+        self.expect("{")?;
+        while !self.eat("}") {
+            let condition = if *self.peek() == Token::Ident("if".into()) {
+                self.pos += 1;
+                self.expect("(")?;
+                let condition = self.expr()?;
+                self.expect(")")?;
+                Some(Expr(condition))
+            } else {
+                None
+            };
+            let type_name = self.ident("a type name")?;
+            let name = self.ident("a field name")?;
+            let mut ty = match (type_name.as_str(), primitive(&type_name)) {
+                ("bytes", _) => {
+                    self.expect("[")?;
+                    let len = self.expr()?;
+                    self.expect("]")?;
+                    FieldType::Bytes(Expr(len))
+                }
+                ("cstr", _) => FieldType::CStr,
+                (_, Some((primitive, field_endian))) => {
+                    FieldType::Primitive(primitive, field_endian.unwrap_or(endian))
+                }
+                (name, None) if template.has_enum(name) => FieldType::Enum(type_name),
+                _ => FieldType::Struct(type_name),
+            };
+            if self.eat("[") {
+                let count = self.expr()?;
+                self.expect("]")?;
+                ty = FieldType::Array(Box::new(ty), Expr(count));
+            }
+            self.expect(";")?;
+            def.push(FieldDef {
+                name,
+                ty,
+                condition,
+            });
+        }
+        self.eat(";");
+        Ok(def)
+    }
+
+    fn expr(&mut self) -> Result<ExprNode> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<ExprNode> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        let depth = self.depth;
+        loop {
+            let op = match self.peek() {
+                Token::Punct(punct) => LEVELS[level]
+                    .iter()
+                    .find(|(name, _)| name == punct)
+                    .map(|(_, op)| *op),
+                _ => None,
+            };
+            let op = match op {
+                Some(op) => op,
+                None => {
+                    self.depth = depth;
+                    return Ok(lhs);
+                }
+            };
+            // Each operator nests the expression so far one level deeper.
+            self.nest()?;
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = ExprNode::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<ExprNode> {
+        let op = match self.peek() {
+            Token::Punct("!") => UnaryOp::Not,
+            Token::Punct("-") => UnaryOp::Neg,
+            Token::Punct("~") => UnaryOp::BitNot,
+            _ => return self.primary(),
+        };
+        self.nest()?;
+        self.pos += 1;
+        let operand = self.unary()?;
+        self.depth -= 1;
+        Ok(ExprNode::Unary(op, Box::new(operand)))
+    }
+
+    fn primary(&mut self) -> Result<ExprNode> {
+        match self.peek().clone() {
+            Token::Int(value) => {
+                self.pos += 1;
+                Ok(ExprNode::Int(value))
+            }
+            Token::Ident(name) => {
+                self.pos += 1;
+                if self.eat("::") {
+                    let variant = self.ident("a variant name")?;
+                    return Ok(ExprNode::Variant(name, variant));
+                }
+                let mut path = vec![name];
+                while self.eat(".") {
+                    path.push(self.ident("a field name")?);
+                }
+                Ok(ExprNode::Path(path))
+            }
+            Token::Punct("(") => {
+                self.nest()?;
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            _ => Err(self.error("expected an expression")),
+        }
+    }
+}
+
+/// Parses a template from its text format.
+pub(crate) fn parse_template(text: &str) -> Result<Template> {
+    Parser::new(text)?.template()
+}
+
+/// Parses an expression from its text format.
+pub(crate) fn parse_expr(text: &str) -> Result<ExprNode> {
+    let mut parser = Parser::new(text)?;
+    let expr = parser.expr()?;
+    if *parser.peek() != Token::End {
+        return Err(parser.error("expected the end of the expression"));
+    }
+    Ok(expr)
+}
@@ -1,9 +1,9 @@
 use super::*;
 use std::fs::File;
 
-use tempfile::tempfile;
+use tempfile::{tempfile, NamedTempFile};
 
-const ADV_HUCK_FINN: &'static [u8] = include_bytes!("adventures-of-huckleberry-finn.txt");
+const ADV_HUCK_FINN: &[u8] = include_bytes!("adventures-of-huckleberry-finn.txt");
 
 const SWAP_TEST_PAGE_SZ: usize = 50;
 const SWAP_TEST_FRAMES: usize = 50;
@@ -127,3 +127,2273 @@ fn auto_cache_swap_general_test_1() {
 fn layered_cache_general_test_1() {
     general_test_1(test_layered_cache());
 }
+
+fn new_named_test_file() -> NamedTempFile {
+    use std::io::Write;
+    let mut file = NamedTempFile::new()
+        .expect("Failed to create temp file. This is an OS failure, not a crate bug.");
+    file.write_all(ADV_HUCK_FINN)
+        .expect("Failed to write to temp file. This is an OS failure, not a crate bug.");
+    file
+}
+
+fn validate_test<C: Cache<Source = File>, F: Fn(File) -> C>(new_cache: F) {
+    let named = new_named_test_file();
+    let mut cache = new_cache(named.reopen().unwrap());
+    assert!(!cache.validate().unwrap());
+
+    let modified = &ADV_HUCK_FINN[..ADV_HUCK_FINN.len() / 2];
+    std::fs::write(named.path(), modified).unwrap();
+    assert!(cache.validate().unwrap());
+    assert_eq!(cache.len(), modified.len() as u64);
+    let mut buf = vec![0; modified.len()];
+    assert_eq!(cache.read(0, &mut buf).unwrap(), modified.len());
+    assert_eq!(buf, modified);
+    assert!(!cache.validate().unwrap());
+
+    let path = named.into_temp_path();
+    path.close().unwrap();
+    assert!(cache.validate().unwrap_err().is_source_removed_error());
+    assert_eq!(cache.len(), modified.len() as u64);
+}
+
+#[test]
+fn full_cache_validate_test() {
+    validate_test(|file| FullCache::new(file).unwrap());
+}
+
+#[test]
+fn swap_cache_validate_test() {
+    validate_test(|file| SwapCache::new(file, SWAP_TEST_PAGE_SZ, SWAP_TEST_FRAMES).unwrap());
+}
+
+#[test]
+fn auto_cache_validate_test() {
+    validate_test(|file| AutoCache::new(file, SWAP_TEST_PAGE_SZ * SWAP_TEST_FRAMES).unwrap());
+}
+
+fn fingerprinted_test<C: Cache<Source = File>, F: Fn(File) -> C>(new_cache: F) {
+    use std::time::{Duration, SystemTime};
+    let named = new_named_test_file();
+    let mut cache = new_cache(named.reopen().unwrap());
+    assert!(cache.fingerprint().modified().is_some());
+
+    // A modification that keeps the length is detected by the first call.
+    let mut modified = ADV_HUCK_FINN.to_vec();
+    modified[0] = b'#';
+    std::fs::write(named.path(), &modified).unwrap();
+    let earlier = SystemTime::now() - Duration::from_secs(3600);
+    named.as_file().set_modified(earlier).unwrap();
+    assert!(cache.validate().unwrap());
+    let mut buf = vec![0; 1];
+    cache.read(0, &mut buf).unwrap();
+    assert_eq!(buf, b"#");
+
+    cache.replace_source(named.reopen().unwrap()).unwrap();
+    assert_eq!(cache.fingerprint().modified(), Some(earlier));
+    assert!(!cache.validate().unwrap());
+}
+
+#[test]
+fn full_cache_fingerprinted_test() {
+    fingerprinted_test(|file| FullCache::new_fingerprinted(file).unwrap());
+}
+
+#[test]
+fn swap_cache_fingerprinted_test() {
+    fingerprinted_test(|file| {
+        SwapCache::new_fingerprinted(file, SWAP_TEST_PAGE_SZ, SWAP_TEST_FRAMES).unwrap()
+    });
+}
+
+#[test]
+fn auto_cache_fingerprinted_test() {
+    fingerprinted_test(|file| {
+        AutoCache::new_fingerprinted(file, SWAP_TEST_PAGE_SZ * SWAP_TEST_FRAMES).unwrap()
+    });
+}
+
+#[cfg(all(feature = "watch", target_os = "linux"))]
+#[test]
+fn watched_cache_test() {
+    let named = new_named_test_file();
+    let path = named.into_temp_path();
+    let cache = WatchedCache::open(&path, |file| {
+        SwapCache::new(file, SWAP_TEST_PAGE_SZ, SWAP_TEST_FRAMES)
+    })
+    .unwrap();
+    let changes = cache.subscribe().unwrap();
+    assert_eq!(cache.poll().unwrap(), None);
+
+    let modified = &ADV_HUCK_FINN[..ADV_HUCK_FINN.len() / 2];
+    std::fs::write(&path, modified).unwrap();
+    let mut buf = vec![0; modified.len()];
+    assert_eq!(cache.read(0, &mut buf).unwrap(), modified.len());
+    assert_eq!(buf, modified);
+    assert_eq!(cache.len(), modified.len() as u64);
+    assert_eq!(changes.try_recv().unwrap(), Change::Modified);
+
+    // The length and fingerprint can be read while the cache is traversed.
+    cache
+        .traverse_chunks(..10, |_| {
+            assert_eq!(cache.len(), modified.len() as u64);
+            assert_eq!(cache.fingerprint().len(), modified.len() as u64);
+            assert!(cache.cache_size() > 0);
+            Ok(())
+        })
+        .unwrap();
+
+    let replacement = &ADV_HUCK_FINN[ADV_HUCK_FINN.len() / 2..];
+    let new_path = path.with_extension("new");
+    std::fs::write(&new_path, replacement).unwrap();
+    std::fs::rename(&new_path, &path).unwrap();
+    assert_eq!(cache.poll().unwrap(), Some(Change::Replaced));
+    assert_eq!(changes.try_recv().unwrap(), Change::Replaced);
+    let mut buf = vec![0; replacement.len()];
+    assert_eq!(cache.read(0, &mut buf).unwrap(), replacement.len());
+    assert_eq!(buf, replacement);
+
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(cache.poll().unwrap(), Some(Change::Removed));
+    assert_eq!(changes.try_recv().unwrap(), Change::Removed);
+    assert_eq!(cache.len(), replacement.len() as u64);
+    assert!(changes.try_recv().is_err());
+}
+
+/// A source that returns at most a few bytes from each read, and is
+/// interrupted before every other read.
+struct ShortReader {
+    inner: std::io::Cursor<&'static [u8]>,
+    interrupt: bool,
+}
+
+impl ShortReader {
+    fn new() -> Self {
+        ShortReader {
+            inner: std::io::Cursor::new(ADV_HUCK_FINN),
+            interrupt: false,
+        }
+    }
+}
+
+impl std::io::Read for ShortReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.interrupt = !self.interrupt;
+        if self.interrupt {
+            return Err(std::io::Error::from(std::io::ErrorKind::Interrupted));
+        }
+        let len = buf.len().min(7);
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+impl std::io::Seek for ShortReader {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+fn general_test_2<C: Cache>(cache: C) {
+    let len = ADV_HUCK_FINN.len();
+    for &(offset, size) in &[(0, 10), (45, 10), (49, 51), (1234, 777), (len - 5, 5)] {
+        let mut buf = vec![0; size];
+        assert_eq!(cache.read(offset as u64, &mut buf).unwrap(), size);
+        assert_eq!(buf, &ADV_HUCK_FINN[offset..offset + size]);
+    }
+
+    let mut buf = vec![0; 100];
+    assert_eq!(cache.read(len as u64 - 30, &mut buf).unwrap(), 30);
+    assert_eq!(&buf[..30], &ADV_HUCK_FINN[len - 30..]);
+    assert_eq!(cache.read(len as u64, &mut buf).unwrap(), 0);
+
+    let mut total = 0;
+    cache
+        .traverse_chunks(len as u64 - 75.., |chunk| {
+            assert_eq!(
+                chunk,
+                &ADV_HUCK_FINN[len - 75 + total..len - 75 + total + chunk.len()]
+            );
+            total += chunk.len();
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(total, 75);
+}
+
+#[test]
+fn full_cache_general_test_2() {
+    general_test_2(test_full_cache());
+}
+
+#[test]
+fn swap_cache_general_test_2() {
+    general_test_2(test_swap_cache());
+}
+
+#[test]
+fn auto_cache_full_general_test_2() {
+    general_test_2(test_auto_cache_full());
+}
+
+#[test]
+fn auto_cache_swap_general_test_2() {
+    general_test_2(test_auto_cache_swap());
+}
+
+#[test]
+fn layered_cache_general_test_2() {
+    general_test_2(test_layered_cache());
+}
+
+#[test]
+fn swap_cache_short_read_test() {
+    let cache = SwapCache::new(ShortReader::new(), SWAP_TEST_PAGE_SZ, SWAP_TEST_FRAMES).unwrap();
+    general_test_2(cache);
+    let cache = SwapCache::new(ShortReader::new(), SWAP_TEST_PAGE_SZ, SWAP_TEST_FRAMES).unwrap();
+    general_test_1(cache);
+}
+
+#[test]
+fn swap_cache_truncated_source_test() {
+    let named = new_named_test_file();
+    let cache = SwapCache::new(named.reopen().unwrap(), SWAP_TEST_PAGE_SZ, 2).unwrap();
+    named.as_file().set_len(1000).unwrap();
+    let mut buf = vec![0; 2000];
+    assert_eq!(cache.read(0, &mut buf).unwrap(), 1000);
+    assert_eq!(&buf[..1000], &ADV_HUCK_FINN[..1000]);
+}
+
+/// A source that fails every read past a given offset.
+struct FailingReader {
+    inner: std::io::Cursor<&'static [u8]>,
+    fail_at: u64,
+}
+
+impl std::io::Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.inner.position() >= self.fail_at {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "read failed",
+            ));
+        }
+        let len = buf
+            .len()
+            .min((self.fail_at - self.inner.position()) as usize);
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+impl std::io::Seek for FailingReader {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn swap_cache_error_context_test() {
+    let source = FailingReader {
+        inner: std::io::Cursor::new(ADV_HUCK_FINN),
+        fail_at: 1000,
+    };
+    let cache = SwapCache::new(source, SWAP_TEST_PAGE_SZ, 2).unwrap();
+    let mut buf = vec![0; 10];
+    let err = cache.read(1010, &mut buf).unwrap_err();
+    assert!(err.is_io_error());
+    let context = err.context().unwrap();
+    assert_eq!(context.cache(), "SwapCache");
+    assert_eq!(context.operation(), Operation::Read);
+    assert_eq!(context.range(), Some(1000..1050));
+    assert_eq!(err.to_string(), "SwapCache failed to read bytes 1000..1050");
+    assert_eq!(
+        std::error::Error::source(&err).unwrap().to_string(),
+        "read failed"
+    );
+
+    let mut reader = CacheReader::new(cache);
+    reader.seek(std::io::SeekFrom::Start(1010)).unwrap();
+    let err = reader.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    assert_eq!(err.to_string(), "SwapCache failed to read bytes 1000..1050");
+    assert_eq!(
+        std::error::Error::source(&err).unwrap().to_string(),
+        "read failed"
+    );
+}
+
+/// A source that cannot seek.
+struct UnseekableReader;
+
+impl std::io::Read for UnseekableReader {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl std::io::Seek for UnseekableReader {
+    fn seek(&mut self, _: std::io::SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "seek failed",
+        ))
+    }
+}
+
+#[test]
+fn auto_cache_error_context_test() {
+    let err = AutoCache::new(UnseekableReader, 1000).err().unwrap();
+    let context = err.context().unwrap();
+    assert_eq!(context.cache(), "AutoCache");
+    assert_eq!(context.operation(), Operation::Seek);
+    assert_eq!(context.range(), None);
+}
+
+fn traverse_with_test<C: Cache>(cache: C) {
+    let token = CancellationToken::new();
+    let mut reports = Vec::new();
+    let mut data = Vec::new();
+    cache
+        .traverse_chunks_with(
+            100..1100,
+            &token,
+            |done, total| reports.push((done, total)),
+            |chunk| {
+                data.extend_from_slice(chunk);
+                Ok(())
+            },
+        )
+        .unwrap();
+    assert_eq!(data, &ADV_HUCK_FINN[100..1100]);
+    assert_eq!(reports.last(), Some(&(1000, 1000)));
+    assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+
+    token.cancel();
+    let err = cache
+        .traverse_chunks_with(.., &token, |_, _| {}, |_| Ok(()))
+        .unwrap_err();
+    assert!(err.is_cancelled_error());
+}
+
+#[test]
+fn full_cache_traverse_with_test() {
+    traverse_with_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_traverse_with_test() {
+    traverse_with_test(test_swap_cache());
+
+    let cache = test_swap_cache();
+    let token = CancellationToken::new();
+    let mut seen = 0;
+    let err = cache
+        .traverse_chunks_with(
+            ..,
+            &token,
+            |_, _| {},
+            |chunk| {
+                seen += chunk.len();
+                if seen >= 200 {
+                    token.cancel();
+                }
+                Ok(())
+            },
+        )
+        .unwrap_err();
+    assert!(err.is_cancelled_error());
+    assert!((seen as u64) < cache.len());
+}
+
+#[test]
+fn swap_cache_write_test() {
+    let mut expected = ADV_HUCK_FINN.to_vec();
+    let mut cache = SwapCache::new(std::io::Cursor::new(expected.clone()), 50, 4).unwrap();
+    assert_eq!(cache.write_policy(), WritePolicy::WriteBack);
+    for (i, offset) in [10u64, 4000, 120, 9990, 45].iter().enumerate() {
+        let data = vec![b'0' + i as u8; 70];
+        cache.write(*offset, &data).unwrap();
+        let start = *offset as usize;
+        expected[start..start + data.len()].copy_from_slice(&data);
+    }
+    let len = expected.len() as u64;
+    cache.write(len, b"THE END").unwrap();
+    expected.extend_from_slice(b"THE END");
+    assert!(cache
+        .write(len + 100, b"x")
+        .unwrap_err()
+        .is_out_of_range_error());
+
+    let mut buf = vec![0; expected.len()];
+    assert_eq!(cache.read(0, &mut buf).unwrap(), expected.len());
+    assert_eq!(buf, expected);
+    assert_eq!(cache.into_inner().unwrap().into_inner(), expected);
+
+    let mut cache = SwapCache::new(std::io::Cursor::new(expected.clone()), 50, 4).unwrap();
+    cache.write(60, b"pending").unwrap();
+    cache.set_write_policy(WritePolicy::WriteThrough).unwrap();
+    cache.write(500, b"through").unwrap();
+    expected[60..67].copy_from_slice(b"pending");
+    expected[500..507].copy_from_slice(b"through");
+    let source = cache
+        .replace_source(std::io::Cursor::new(Vec::new()))
+        .unwrap();
+    assert_eq!(source.into_inner(), expected);
+}
+
+#[test]
+fn swap_cache_write_validate_test() {
+    use std::fs::OpenOptions;
+    use std::time::{Duration, SystemTime};
+    let named = new_named_test_file();
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(named.path())
+        .unwrap();
+    let mut cache = SwapCache::new_fingerprinted(file, 50, 4).unwrap();
+    let mut expected = ADV_HUCK_FINN.to_vec();
+
+    // The cache's own writes are not reported as modifications.
+    cache.write(10, b"back").unwrap();
+    cache.write(9000, b"evicted").unwrap();
+    cache.write(20000, b"evicted").unwrap();
+    assert!(!cache.validate().unwrap());
+    cache.flush().unwrap();
+    assert!(!cache.validate().unwrap());
+    cache.set_write_policy(WritePolicy::WriteThrough).unwrap();
+    cache.write(4000, b"through").unwrap();
+    assert!(!cache.validate().unwrap());
+    expected[10..14].copy_from_slice(b"back");
+    expected[9000..9007].copy_from_slice(b"evicted");
+    expected[20000..20007].copy_from_slice(b"evicted");
+    expected[4000..4007].copy_from_slice(b"through");
+    assert_eq!(std::fs::read(named.path()).unwrap(), expected);
+
+    // Dirty frames are written back on drop.
+    cache.set_write_policy(WritePolicy::WriteBack).unwrap();
+    cache.write(100, b"dropped").unwrap();
+    drop(cache);
+    expected[100..107].copy_from_slice(b"dropped");
+    assert_eq!(std::fs::read(named.path()).unwrap(), expected);
+
+    // A modification by someone else discards dirty frames unwritten.
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(named.path())
+        .unwrap();
+    let mut cache = SwapCache::new_fingerprinted(file, 50, 4).unwrap();
+    cache.write(0, b"lost").unwrap();
+    let earlier = SystemTime::now() - Duration::from_secs(3600);
+    named.as_file().set_modified(earlier).unwrap();
+    assert!(cache.validate().unwrap());
+    drop(cache);
+    assert_eq!(std::fs::read(named.path()).unwrap(), expected);
+}
+
+fn naive_find_all(data: &[u8], pattern: &[u8], range: std::ops::Range<usize>) -> Vec<u64> {
+    let mut found = Vec::new();
+    let mut pos = range.start;
+    while pos + pattern.len() <= range.end {
+        if &data[pos..pos + pattern.len()] == pattern {
+            found.push(pos as u64);
+            pos += pattern.len().max(1);
+        } else {
+            pos += 1;
+        }
+    }
+    found
+}
+
+fn find_test<C: Cache>(cache: C) {
+    let data = ADV_HUCK_FINN;
+    for (start, len) in [(3usize, 2usize), (49, 2), (99, 51), (1234, 7), (5000, 120)].iter() {
+        let pattern = &data[*start..*start + *len];
+        let range = 40..9000;
+        let expected = naive_find_all(data, pattern, range.clone());
+        let found: Vec<u64> = cache
+            .find_iter(pattern, range.start as u64..range.end as u64)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(found, expected);
+        assert_eq!(
+            cache.find(pattern, range.start as u64..).unwrap(),
+            expected.first().cloned()
+        );
+    }
+    let all: Vec<u64> = cache.find_iter(b"Huck", ..).map(|r| r.unwrap()).collect();
+    assert_eq!(all, naive_find_all(data, b"Huck", 0..data.len()));
+    assert_eq!(cache.find(b"not in the book at all", ..).unwrap(), None);
+    assert_eq!(cache.find(b"", 10..20).unwrap(), Some(10));
+    assert_eq!(cache.find_iter(b"", 10..13).count(), 4);
+    assert_eq!(cache.find(&data[100..110], 100..109).unwrap(), None);
+}
+
+#[test]
+fn full_cache_find_test() {
+    find_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_find_test() {
+    find_test(test_swap_cache());
+    find_test(SwapCache::new(new_test_file(), 7, 3).unwrap());
+}
+
+#[test]
+fn edit_cache_find_test() {
+    let mut cache = EditCache::new(test_full_cache());
+    cache.delete(2000..2100);
+    cache.insert(2000, &ADV_HUCK_FINN[2000..2100]).unwrap();
+    cache.overwrite(3000, &ADV_HUCK_FINN[3000..3001]).unwrap();
+    find_test(cache);
+}
+
+fn hex_pattern_test<C: Cache>(cache: C) {
+    let data = ADV_HUCK_FINN;
+    let pattern = HexPattern::parse("48 75 ?? 6B").unwrap();
+    let expected: Vec<u64> = (0..data.len() - 3)
+        .filter(|idx| data[*idx] == b'H' && data[idx + 1] == b'u' && data[idx + 3] == b'k')
+        .map(|idx| idx as u64)
+        .collect();
+    assert!(!expected.is_empty());
+    let found: Vec<u64> = pattern.find_iter(&cache, ..).map(|r| r.unwrap()).collect();
+    assert_eq!(found, expected);
+
+    // Lower case letters, a space, then any letter, with no anchor byte.
+    let pattern: HexPattern = "6? 2? 40/C0".parse().unwrap();
+    let expected: Vec<u64> = (0..data.len() as u64 - 2)
+        .filter(|idx| pattern.matches(&data[*idx as usize..]))
+        .fold(Vec::new(), |mut found, idx| {
+            if !matches!(found.last(), Some(last) if idx < last + 3) {
+                found.push(idx);
+            }
+            found
+        });
+    let found: Vec<u64> = pattern.find_iter(&cache, ..).map(|r| r.unwrap()).collect();
+    assert_eq!(found, expected);
+
+    let exact = HexPattern::from_bytes(&data[4990..5010]);
+    assert_eq!(
+        exact.find(&cache, 100..).unwrap(),
+        cache.find(&data[4990..5010], 100..).unwrap()
+    );
+}
+
+#[test]
+fn hex_pattern_parse_test() {
+    let pattern = HexPattern::parse("48 8B ?? ?? 00 F?").unwrap();
+    assert_eq!(pattern.values(), &[0x48, 0x8B, 0, 0, 0, 0xF0]);
+    assert_eq!(pattern.masks(), &[0xFF, 0xFF, 0, 0, 0xFF, 0xF0]);
+    assert_eq!(pattern.to_string(), "48 8B ?? ?? 00 F?");
+    assert_eq!(HexPattern::parse("488b????00f?").unwrap(), pattern);
+    let masked = HexPattern::parse("41/DF ?1").unwrap();
+    assert_eq!(masked.to_string(), "41/DF ?1");
+    assert!(masked.matches(b"a1"));
+    assert!(masked.matches(b"AQ"));
+    assert!(!masked.matches(b"B1"));
+    assert!(HexPattern::parse("4").unwrap_err().is_pattern_error());
+    assert!(HexPattern::parse("4G").unwrap_err().is_pattern_error());
+    assert!(HexPattern::parse("41/F").unwrap_err().is_pattern_error());
+    assert!(HexPattern::with_masks(&[1, 2], &[0xFF]).is_err());
+
+    // Candidates at overlapping anchors are all checked.
+    let overlapping = HexPattern::parse("AA AA ?? 01").unwrap();
+    let cache = FullCache::new(std::io::Cursor::new(vec![0xAA, 0xAA, 0xAA, 0, 1])).unwrap();
+    assert_eq!(overlapping.find(&cache, ..).unwrap(), Some(1));
+}
+
+#[test]
+fn full_cache_hex_pattern_test() {
+    hex_pattern_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_hex_pattern_test() {
+    hex_pattern_test(SwapCache::new(new_test_file(), 5, 3).unwrap());
+}
+
+#[cfg(feature = "regex")]
+fn regex_test<C: Cache>(cache: C) {
+    let data = ADV_HUCK_FINN;
+    for pattern in [
+        r"Huck\w*",
+        r"\bTom\b",
+        r"[0-9]+",
+        r"(?m)^CHAPTER [IVXL]+\.\r$",
+    ]
+    .iter()
+    {
+        let search = RegexSearch::new(pattern, 20).unwrap();
+        let expected: Vec<(u64, u64)> = search
+            .regex()
+            .find_iter(data)
+            .map(|m| (m.start() as u64, m.len() as u64))
+            .collect();
+        assert!(!expected.is_empty());
+        let found: Vec<(u64, u64)> = search
+            .find_iter(&cache, ..)
+            .map(|m| m.map(|m| (m.offset(), m.len())).unwrap())
+            .collect();
+        assert_eq!(found, expected);
+    }
+    let search = RegexSearch::new(r"Tom", 3).unwrap();
+    let first = search.find(&cache, 1000..).unwrap().unwrap();
+    assert_eq!(
+        first.range(),
+        cache.find(b"Tom", 1000..).unwrap().unwrap()..first.offset() + 3
+    );
+    assert!(RegexSearch::new("(", 10).unwrap_err().is_pattern_error());
+    assert!(RegexSearch::new("a", 0).unwrap_err().is_pattern_error());
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn full_cache_regex_test() {
+    regex_test(test_full_cache());
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn swap_cache_regex_test() {
+    regex_test(SwapCache::new(new_test_file(), 5, 3).unwrap());
+}
+
+fn reverse_test<C: Cache>(cache: C) {
+    let data = ADV_HUCK_FINN;
+    for range in [0..data.len(), 123..4567, 4000..4001, 10..10].iter() {
+        let mut chunks = Vec::new();
+        cache
+            .traverse_chunks_rev(range.start as u64..range.end as u64, |chunk| {
+                chunks.push(chunk.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        let content: Vec<u8> = chunks.into_iter().rev().flatten().collect();
+        assert!(content == data[range.clone()]);
+    }
+    for (start, len) in [(3usize, 2usize), (49, 2), (99, 51), (1234, 7)].iter() {
+        let pattern = &data[*start..*start + *len];
+        let last = (0..=9000 - pattern.len())
+            .rev()
+            .find(|idx| &data[*idx..*idx + pattern.len()] == pattern)
+            .map(|idx| idx as u64);
+        assert_eq!(cache.rfind(pattern, ..9000).unwrap(), last);
+    }
+    let tail = &data[data.len() - 30..];
+    assert_eq!(cache.rfind(tail, ..).unwrap(), Some(data.len() as u64 - 30));
+    assert_eq!(cache.rfind(b"Huck", ..10).unwrap(), None);
+    assert_eq!(cache.rfind(b"", 10..20).unwrap(), Some(20));
+}
+
+#[test]
+fn full_cache_reverse_test() {
+    reverse_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_reverse_test() {
+    reverse_test(test_swap_cache());
+    reverse_test(SwapCache::new(new_test_file(), 7, 3).unwrap());
+}
+
+/// A cache that only implements the required methods, so that the provided
+/// methods are tested.
+struct ForwardCache(SwapCache<File>);
+
+impl Cache for ForwardCache {
+    type Source = File;
+
+    fn into_inner(self) -> Result<File> {
+        self.0.into_inner()
+    }
+
+    fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    fn cache_size(&self) -> usize {
+        self.0.cache_size()
+    }
+
+    fn traverse_chunks<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        self.0.traverse_chunks(range, f)
+    }
+}
+
+#[test]
+fn provided_reverse_test() {
+    let cache = ForwardCache(SwapCache::new(new_test_file(), 7, 3).unwrap());
+    let mut lens = Vec::new();
+    cache
+        .traverse_chunks_rev(.., |chunk| {
+            lens.push(chunk.len());
+            Ok(())
+        })
+        .unwrap();
+    assert!(lens.len() > 1);
+    assert_eq!(lens.iter().sum::<usize>(), ADV_HUCK_FINN.len());
+    reverse_test(cache);
+}
+
+#[test]
+fn edit_cache_reverse_test() {
+    let mut cache = EditCache::new(SwapCache::new(new_test_file(), 7, 3).unwrap());
+    cache.delete(2000..2100);
+    cache.insert(2000, &ADV_HUCK_FINN[2000..2100]).unwrap();
+    cache.overwrite(3000, &ADV_HUCK_FINN[3000..3001]).unwrap();
+    reverse_test(cache);
+}
+
+#[test]
+fn checksum_vectors_test() {
+    let vectors: &[(&[u8], Algorithm, &str)] = &[
+        (b"123456789", Algorithm::Crc32, "cbf43926"),
+        (b"Wikipedia", Algorithm::Adler32, "11e60398"),
+        (b"", Algorithm::Md5, "d41d8cd98f00b204e9800998ecf8427e"),
+        (b"abc", Algorithm::Md5, "900150983cd24fb0d6963f7d28e17f72"),
+        (
+            b"abc",
+            Algorithm::Sha1,
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+        ),
+        (
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+            Algorithm::Sha256,
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        ),
+        (
+            b"12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+            Algorithm::Md5,
+            "57edf4a22be3c955ac49da2e2107b67a",
+        ),
+        (
+            b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+            Algorithm::Sha1,
+            "a49b2446a02c645bf419f995b67091253a04a259",
+        ),
+        (
+            b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+            Algorithm::Sha256,
+            "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1",
+        ),
+        (b"", Algorithm::XxHash64, "ef46db3751d8e999"),
+        (b"abc", Algorithm::XxHash64, "44bc2cf5ad770999"),
+        (
+            b"Nobody inspects the spammish repetition",
+            Algorithm::XxHash64,
+            "fbcea83c8a378bf1",
+        ),
+    ];
+    for (input, algorithm, expected) in vectors.iter() {
+        let cache = FullCache::new(std::io::Cursor::new(input.to_vec())).unwrap();
+        let checksum = cache.digest(.., *algorithm).unwrap();
+        assert_eq!(checksum.to_string(), *expected);
+        assert_eq!(checksum.algorithm(), *algorithm);
+        assert_eq!(checksum.as_bytes().len(), algorithm.output_len());
+    }
+
+    // One million repetitions of "a", traversed in many chunks.
+    let source = std::io::Cursor::new(vec![b'a'; 1_000_000]);
+    let cache = SwapCache::new(source, 4096, 4).unwrap();
+    let vectors = [
+        (Algorithm::Md5, "7707d6ae4e027c70eea2a935c2296f21"),
+        (Algorithm::Sha1, "34aa973cd4c4daa4f61eeb2bdbad27316534016f"),
+        (
+            Algorithm::Sha256,
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+        ),
+    ];
+    for (algorithm, expected) in vectors.iter() {
+        let checksum = cache.digest(.., *algorithm).unwrap();
+        assert_eq!(checksum.to_string(), *expected);
+    }
+}
+
+fn digest_test<C: Cache>(cache: C) {
+    let algorithms = [
+        Algorithm::Crc32,
+        Algorithm::Adler32,
+        Algorithm::Md5,
+        Algorithm::Sha1,
+        Algorithm::Sha256,
+        Algorithm::XxHash64,
+    ];
+    for range in [0..ADV_HUCK_FINN.len(), 123..9000, 55..56, 10..10].iter() {
+        for algorithm in algorithms.iter() {
+            let mut hasher = Hasher::new(*algorithm);
+            hasher.update(&ADV_HUCK_FINN[range.clone()]);
+            let range = range.start as u64..range.end as u64;
+            assert_eq!(cache.digest(range, *algorithm).unwrap(), hasher.finish());
+        }
+    }
+}
+
+#[test]
+fn full_cache_digest_test() {
+    digest_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_digest_test() {
+    digest_test(test_swap_cache());
+    digest_test(SwapCache::new(new_test_file(), 7, 3).unwrap());
+}
+
+fn analysis_test<C: Cache>(cache: C) {
+    let data = ADV_HUCK_FINN;
+    let histogram = Histogram::compute(&cache, 100..5000).unwrap();
+    assert_eq!(histogram.total(), 4900);
+    for byte in [b' ', b'e', b'\n', 0].iter() {
+        let expected = data[100..5000].iter().filter(|b| *b == byte).count();
+        assert_eq!(histogram.count(*byte), expected as u64);
+    }
+
+    let token = CancellationToken::new();
+    let mut blocks = Vec::new();
+    Histogram::for_blocks(
+        &cache,
+        10..,
+        4096,
+        &token,
+        |_, _| {},
+        |range, histogram| {
+            assert_eq!(histogram.total(), range.end - range.start);
+            blocks.push(range);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(blocks.first(), Some(&(10..4106)));
+    assert_eq!(blocks.last().unwrap().end, data.len() as u64);
+    assert!(blocks.windows(2).all(|pair| pair[0].end == pair[1].start));
+
+    let mut last_progress = 0;
+    let series =
+        EntropySeries::compute(&cache, .., 1000, &token, |done, _| last_progress = done).unwrap();
+    assert_eq!(last_progress, data.len() as u64);
+    assert_eq!(series.len(), data.len().div_ceil(1000));
+    for (idx, value) in series.values().iter().enumerate() {
+        let range = series.block_range(idx).unwrap();
+        let mut histogram = Histogram::new();
+        histogram.update(&data[range.start as usize..range.end as usize]);
+        assert_eq!(*value, histogram.entropy() as f32);
+        assert!(*value > 3.0 && *value < 6.0);
+    }
+    assert_eq!(series.block_at(2500), Some(2));
+    assert_eq!(series.block_at(data.len() as u64), None);
+    assert_eq!(series.block_range(series.len()), None);
+
+    let err = EntropySeries::compute(&cache, .., 0, &token, |_, _| {}).unwrap_err();
+    assert!(err.is_invalid_argument_error());
+
+    token.cancel();
+    let err = EntropySeries::compute(&cache, .., 1000, &token, |_, _| {}).unwrap_err();
+    assert!(err.is_cancelled_error());
+}
+
+#[test]
+fn full_cache_analysis_test() {
+    analysis_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_analysis_test() {
+    analysis_test(SwapCache::new(new_test_file(), 7, 3).unwrap());
+}
+
+#[test]
+fn entropy_test() {
+    let mut histogram = Histogram::new();
+    assert_eq!(histogram.entropy(), 0.0);
+    histogram.update(&[7; 100]);
+    assert_eq!(histogram.entropy(), 0.0);
+    histogram.update(&[8; 100]);
+    assert!((histogram.entropy() - 1.0).abs() < 1e-9);
+    let all: Vec<u8> = (0..=255).collect();
+    let mut uniform = Histogram::new();
+    uniform.update(&all);
+    assert!((uniform.entropy() - 8.0).abs() < 1e-9);
+    uniform.merge(&histogram);
+    assert_eq!(uniform.total(), 456);
+    assert_eq!(uniform.count(7), 101);
+}
+
+/// Checks that the ranges of a diff cover both inputs in order, and that the
+/// ranges reported equal are. Returns the number of bytes reported changed,
+/// inserted and deleted.
+fn check_diff(a: &[u8], b: &[u8], ranges: &[DiffRange]) -> (u64, u64, u64) {
+    let (mut pos_a, mut pos_b) = (0, 0);
+    let mut totals = (0, 0, 0);
+    for range in ranges {
+        let (ra, rb) = (range.a_range(), range.b_range());
+        assert_eq!((ra.start, rb.start), (pos_a, pos_b));
+        let (len_a, len_b) = (ra.end - ra.start, rb.end - rb.start);
+        match range {
+            DiffRange::Equal { .. } => {
+                assert!(
+                    a[ra.start as usize..ra.end as usize] == b[rb.start as usize..rb.end as usize]
+                )
+            }
+            DiffRange::Changed { .. } => totals.0 += len_a.max(len_b),
+            DiffRange::Inserted { .. } => {
+                assert_eq!(len_a, 0);
+                totals.1 += len_b;
+            }
+            DiffRange::Deleted { .. } => {
+                assert_eq!(len_b, 0);
+                totals.2 += len_a;
+            }
+        }
+        pos_a = ra.end;
+        pos_b = rb.end;
+    }
+    assert_eq!((pos_a, pos_b), (a.len() as u64, b.len() as u64));
+    totals
+}
+
+fn diff_all<A: Cache, B: Cache>(a: &A, b: &B) -> Vec<DiffRange> {
+    diff(a, b).collect::<Result<Vec<_>>>().unwrap()
+}
+
+#[test]
+fn diff_test() {
+    use std::io::Cursor;
+    let data = ADV_HUCK_FINN;
+    let old = SwapCache::new(Cursor::new(data.to_vec()), 7, 3).unwrap();
+    let ranges = diff_all(&old, &test_full_cache());
+    assert_eq!(
+        ranges,
+        vec![DiffRange::Equal {
+            a: 0..data.len() as u64,
+            b: 0..data.len() as u64
+        }]
+    );
+
+    // Caches of the same length are compared position by position.
+    let mut changed = data.to_vec();
+    changed[100..103].copy_from_slice(&[0, 1, 2]);
+    changed[70000] = 0;
+    let new = FullCache::new(Cursor::new(changed.clone())).unwrap();
+    let ranges = diff_all(&old, &new);
+    assert_eq!(ranges.len(), 5);
+    assert_eq!(
+        ranges[1],
+        DiffRange::Changed {
+            a: 100..103,
+            b: 100..103
+        }
+    );
+    assert_eq!(
+        ranges[3],
+        DiffRange::Changed {
+            a: 70000..70001,
+            b: 70000..70001
+        }
+    );
+    assert_eq!(check_diff(data, &changed, &ranges), (4, 0, 0));
+
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let mut edited = data.to_vec();
+    edited[30000..30003].copy_from_slice(&[0, 1, 2]);
+    edited.drain(20000..20010);
+    let inserted: Vec<u8> = (0..1000).map(|_| 128 + rng.next(128) as u8).collect();
+    edited.splice(5000..5000, inserted);
+    let new = SwapCache::new(Cursor::new(edited.clone()), 7, 3).unwrap();
+    let ranges = diff_all(&old, &new);
+    assert_eq!(check_diff(data, &edited, &ranges), (3, 1000, 10));
+    assert_eq!(
+        ranges[1],
+        DiffRange::Inserted {
+            a: 5000..5000,
+            b: 5000..6000
+        }
+    );
+    let ranges = diff_all(&new, &old);
+    assert_eq!(check_diff(&edited, data, &ranges), (3, 10, 1000));
+
+    // Insertions larger than the resynchronization window.
+    let mut edited = data.to_vec();
+    let inserted: Vec<u8> = (0..100_000).map(|_| 128 + rng.next(128) as u8).collect();
+    edited.splice(40000..40000, inserted);
+    let new = FullCache::new(Cursor::new(edited.clone())).unwrap();
+    let ranges = diff_all(&old, &new);
+    assert_eq!(check_diff(data, &edited, &ranges), (0, 100_000, 0));
+
+    // Caches of the same length fall back to resynchronization when a run
+    // of changed bytes is longer than the window.
+    let mut edited = data.to_vec();
+    let inserted: Vec<u8> = (0..100_000).map(|_| 128 + rng.next(128) as u8).collect();
+    edited.splice(40000..40000, inserted);
+    edited.drain(300_000..400_000);
+    let new = FullCache::new(Cursor::new(edited.clone())).unwrap();
+    let ranges = diff_all(&old, &new);
+    assert_eq!(check_diff(data, &edited, &ranges), (0, 100_000, 100_000));
+    let mut edited = data.to_vec();
+    for byte in &mut edited[40000..240_000] {
+        *byte ^= 1 + rng.next(255) as u8;
+    }
+    let new = FullCache::new(Cursor::new(edited.clone())).unwrap();
+    let ranges = diff_all(&old, &new);
+    assert_eq!(check_diff(data, &edited, &ranges), (200_000, 0, 0));
+
+    let empty = FullCache::new(Cursor::new(Vec::new())).unwrap();
+    let ranges = diff_all(&empty, &old);
+    assert_eq!(
+        ranges,
+        vec![DiffRange::Inserted {
+            a: 0..0,
+            b: 0..data.len() as u64
+        }]
+    );
+}
+
+fn collect_strings<C: Cache>(cache: &C) -> Vec<(u64, Encoding, String)> {
+    Strings::new(cache, .., 4).collect::<Result<_>>().unwrap()
+}
+
+#[test]
+fn strings_test() {
+    use std::io::Cursor;
+    let mut data = vec![0u8, 1];
+    data.extend_from_slice(b"Hello, world");
+    data.extend_from_slice(&[0xFF, 0xFE]);
+    data.extend_from_slice("h\u{e9}llo w\u{f6}rld".as_bytes());
+    data.extend_from_slice(&[0, 0x80, 0]);
+    let wide = data.len() as u64;
+    for c in "Wide \u{3a9}\u{1f600}".encode_utf16() {
+        data.extend_from_slice(&c.to_le_bytes());
+    }
+    data.extend_from_slice(&[0, 0xD8, 0, 0]);
+    let big = data.len() as u64;
+    for c in "Big end".encode_utf16() {
+        data.extend_from_slice(&c.to_be_bytes());
+    }
+    data.extend_from_slice(b"\x00\x00\x01abc\x01");
+    let expected = vec![
+        (2, Encoding::Ascii, "Hello, world".to_string()),
+        (16, Encoding::Utf8, "h\u{e9}llo w\u{f6}rld".to_string()),
+        (wide, Encoding::Utf16Le, "Wide \u{3a9}".to_string()),
+        (big, Encoding::Utf16Be, "Big end".to_string()),
+    ];
+    let full = FullCache::new(Cursor::new(data.clone())).unwrap();
+    let swap = SwapCache::new(Cursor::new(data.clone()), 7, 3).unwrap();
+    assert_eq!(collect_strings(&full), expected);
+    assert_eq!(collect_strings(&swap), expected);
+
+    let ascii: Vec<_> = Strings::new(&swap, .., 3)
+        .with_encodings(&[Encoding::Ascii])
+        .collect::<Result<_>>()
+        .unwrap();
+    let texts: Vec<&str> = ascii.iter().map(|found| found.2.as_str()).collect();
+    assert_eq!(texts, vec!["Hello, world", "llo w", "rld", "abc"]);
+    assert_eq!(ascii[1].0, 19);
+
+    // The lines of the book, separated by CRLF, are stitched across pages.
+    let cache = SwapCache::new(new_test_file(), 7, 3).unwrap();
+    let found: Vec<_> = Strings::new(&cache, 1000..50000, 8)
+        .collect::<Result<_>>()
+        .unwrap();
+    let text = std::str::from_utf8(&ADV_HUCK_FINN[1000..50000]).unwrap();
+    let lines: Vec<&str> = text
+        .split(['\r', '\n'])
+        .filter(|line| line.chars().count() >= 8)
+        .collect();
+    assert_eq!(found.len(), lines.len());
+    for ((offset, encoding, text), line) in found.iter().zip(lines) {
+        let expected = if line.is_ascii() {
+            Encoding::Ascii
+        } else {
+            Encoding::Utf8
+        };
+        assert_eq!(*encoding, expected);
+        assert_eq!(text, line);
+        let offset = *offset as usize;
+        assert!(&ADV_HUCK_FINN[offset..offset + line.len()] == line.as_bytes());
+    }
+}
+
+#[test]
+fn strings_limits_test() {
+    use std::io::Cursor;
+    let mut data = vec![0u8];
+    for c in "Packed".encode_utf16() {
+        data.extend_from_slice(&c.to_le_bytes());
+    }
+    data.extend_from_slice(&[0, 0, 0]);
+    let long = data.len() as u64;
+    data.extend_from_slice(&[b'x'; 10000]);
+    data.push(0);
+    let cache = SwapCache::new(Cursor::new(data), 7, 3).unwrap();
+
+    // Read at even offsets, the same bytes are big-endian text.
+    let aligned: Vec<_> = Strings::new(&cache, ..long, 4)
+        .with_encodings(&[Encoding::Utf16Le])
+        .collect::<Result<_>>()
+        .unwrap();
+    assert!(aligned.is_empty());
+    let unaligned: Vec<_> = Strings::new(&cache, ..long, 4)
+        .with_unaligned_utf16(true)
+        .with_encodings(&[Encoding::Utf16Le])
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(
+        unaligned,
+        vec![(1, Encoding::Utf16Le, "Packed".to_string())]
+    );
+
+    // Long runs are returned in pieces of at most the maximum length.
+    let pieces: Vec<_> = Strings::new(&cache, long.., 4)
+        .with_encodings(&[Encoding::Ascii])
+        .with_max_len(3000)
+        .collect::<Result<_>>()
+        .unwrap();
+    let lens: Vec<_> = pieces
+        .iter()
+        .map(|(offset, _, text)| (*offset - long, text.len()))
+        .collect();
+    assert_eq!(
+        lens,
+        vec![(0, 3000), (3000, 3000), (6000, 3000), (9000, 1000)]
+    );
+    let pieces: Vec<_> = Strings::new(&cache, long.., 4)
+        .with_encodings(&[Encoding::Ascii])
+        .with_max_len(5000)
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(pieces.len(), 2);
+}
+
+#[test]
+fn strings_step_boundary_test() {
+    use std::io::Cursor;
+    // Strings are found in steps of 64 KiB.
+    let step = 64 * 1024;
+    let mut mixed = b"abc\x01".to_vec();
+    mixed.extend_from_slice("\u{1f600}h\u{e9}llo\x01".as_bytes());
+    for c in "W\u{1f600}ide".encode_utf16() {
+        mixed.extend_from_slice(&c.to_le_bytes());
+    }
+    mixed.push(0);
+    for c in "\u{1f600}Big".encode_utf16() {
+        mixed.extend_from_slice(&c.to_be_bytes());
+    }
+    mixed.extend_from_slice(b"\x01xyz\x01");
+    for shift in 0..mixed.len() {
+        let mut data = vec![0; step - shift];
+        data.extend_from_slice(&mixed);
+        data.extend_from_slice(&[0; 16]);
+        let cache = FullCache::new(Cursor::new(data)).unwrap();
+        let whole: Vec<_> = Strings::new(&cache, .., 3)
+            .with_unaligned_utf16(true)
+            .collect::<Result<_>>()
+            .unwrap();
+        // Starting near the data, the whole run of strings is in one step.
+        let near: Vec<_> = Strings::new(&cache, step as u64 - 100.., 3)
+            .with_unaligned_utf16(true)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert!(whole.len() >= 4);
+        assert!(whole.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(whole, near, "shift {}", shift);
+    }
+}
+
+fn cursor_test<C: Cache>(cache: C) {
+    let mut cursor = CacheCursor::new(&cache);
+    assert_eq!(cursor.read_u8().unwrap(), 0xAB);
+    assert_eq!(cursor.read_i8().unwrap(), -2);
+    assert_eq!(cursor.read_u16_le().unwrap(), 0x0201);
+    assert_eq!(cursor.read_u16_be().unwrap(), 0x0102);
+    assert_eq!(cursor.read_u32_le().unwrap(), 0xDEAD_BEEF);
+    assert_eq!(cursor.read_i64_be().unwrap(), -42);
+    assert_eq!(cursor.read_f32_le().unwrap(), 1.5);
+    assert_eq!(cursor.read_f64_be().unwrap(), -0.25);
+    assert_eq!(cursor.read_uleb128().unwrap(), 624_485);
+    assert_eq!(cursor.read_sleb128().unwrap(), -123_456);
+    assert_eq!(cursor.read_uleb128().unwrap(), u64::MAX);
+    assert_eq!(cursor.read_sleb128().unwrap(), i64::MIN);
+    assert_eq!(cursor.read_cstr().unwrap(), b"hello");
+    assert_eq!(&cursor.read_array::<3>().unwrap(), b"xyz");
+    assert_eq!(cursor.position(), 65);
+
+    // Failed reads leave the cursor in place.
+    let err = cursor.read_uleb128().unwrap_err();
+    assert!(err.is_malformed_error());
+    assert_eq!(cursor.position(), 65);
+    cursor.skip(10);
+    assert_eq!(cursor.remaining(), 3);
+    let err = cursor.read_u32_be().unwrap_err();
+    match err {
+        Error::OutOfRange { offset, len } => assert_eq!((offset, len), (78, 78)),
+        e => panic!("unexpected error: {}", e),
+    }
+    assert_eq!(cursor.read_bytes(3).unwrap(), b"end");
+    assert!(cursor.is_at_end());
+    assert!(cursor.read_u8().is_err());
+
+    assert_eq!(cache.u32_be_at(4).unwrap(), 0x0102_EFBE);
+    assert_eq!(cache.i16_le_at(0).unwrap(), -341);
+    assert_eq!(cache.cstr_at(58).unwrap(), b"llo");
+    assert!(cache.cstr_at(62).unwrap_err().is_out_of_range_error());
+    assert!(CacheCursor::at(&cache, 66).read_cstr().is_err());
+    assert!(cache
+        .bytes_at(0, usize::MAX)
+        .unwrap_err()
+        .is_out_of_range_error());
+    assert!(CacheCursor::at(&cache, 70).read_bytes(9).is_err());
+    assert_eq!(cache.cstr_at_bounded(58, 3).unwrap(), b"llo");
+    assert!(cache
+        .cstr_at_bounded(56, 3)
+        .unwrap_err()
+        .is_malformed_error());
+    assert!(cache
+        .cstr_at_bounded(62, 100)
+        .unwrap_err()
+        .is_out_of_range_error());
+    let mut cursor = CacheCursor::at(&cache, 56);
+    assert!(cursor.read_cstr_bounded(4).is_err());
+    assert_eq!(cursor.position(), 56);
+    assert_eq!(cursor.read_cstr_bounded(5).unwrap(), b"hello");
+    assert_eq!(cursor.position(), 62);
+}
+
+fn cursor_test_data() -> Vec<u8> {
+    let mut data = vec![0xAB, 0xFE, 0x01, 0x02, 0x01, 0x02];
+    data.extend_from_slice(&0xDEAD_BEEFu32.to_le_bytes());
+    data.extend_from_slice(&(-42i64).to_be_bytes());
+    data.extend_from_slice(&1.5f32.to_le_bytes());
+    data.extend_from_slice(&(-0.25f64).to_be_bytes());
+    data.extend_from_slice(&[0xE5, 0x8E, 0x26]);
+    data.extend_from_slice(&[0xC0, 0xBB, 0x78]);
+    data.extend_from_slice(&[0xFF; 9]);
+    data.push(0x01);
+    data.extend_from_slice(&[0x80; 9]);
+    data.push(0x7F);
+    data.extend_from_slice(b"hello\0xyz");
+    data.extend_from_slice(&[0x80; 10]);
+    data.extend_from_slice(b"end");
+    data
+}
+
+#[test]
+fn full_cache_cursor_test() {
+    let data = cursor_test_data();
+    cursor_test(FullCache::new(std::io::Cursor::new(data)).unwrap());
+}
+
+#[test]
+fn swap_cache_cursor_test() {
+    let data = cursor_test_data();
+    cursor_test(SwapCache::new(std::io::Cursor::new(data), 3, 2).unwrap());
+}
+
+/// Reads `bits` bits at bit offset `pos` of the data one bit at a time.
+fn naive_bits(data: &[u8], pos: u64, bits: u32, order: BitOrder) -> u64 {
+    (0..bits as u64).fold(0, |value, idx| {
+        let bit_pos = pos + idx;
+        let byte = data[(bit_pos / 8) as usize];
+        match order {
+            BitOrder::MsbFirst => (value << 1) | ((byte >> (7 - bit_pos % 8)) & 1) as u64,
+            BitOrder::LsbFirst => value | (((byte >> (bit_pos % 8)) & 1) as u64) << idx,
+        }
+    })
+}
+
+fn bit_reader_test<C: Cache>(cache: C) {
+    let data = ADV_HUCK_FINN;
+    let mut rng = XorShift(0x1234_5678_9abc_def1);
+    for order in [BitOrder::MsbFirst, BitOrder::LsbFirst].iter() {
+        let mut reader = BitReader::new(&cache, *order);
+        for _ in 0..2000 {
+            let bits = rng.next(65) as u32;
+            let expected = naive_bits(data, reader.position(), bits, *order);
+            assert_eq!(reader.read_bits(bits).unwrap(), expected);
+            reader.skip(rng.next(100));
+        }
+        let pos = rng.next(data.len() as u64 * 8 - 64);
+        reader.seek(pos);
+        assert_eq!(
+            reader.peek_bits(64).unwrap(),
+            naive_bits(data, pos, 64, *order)
+        );
+        assert_eq!(reader.position(), pos);
+        reader.align_to_byte();
+        assert!(reader.is_byte_aligned());
+        assert_eq!(reader.position(), pos.div_ceil(8) * 8);
+    }
+
+    let mut reader = BitReader::new(&cache, BitOrder::MsbFirst);
+    reader.seek(data.len() as u64 * 8 - 3);
+    assert_eq!(reader.remaining(), 3);
+    assert!(reader.read_bits(4).unwrap_err().is_out_of_range_error());
+    assert_eq!(reader.remaining(), 3);
+    assert_eq!(
+        reader.read_signed_bits(3).unwrap(),
+        ((data[data.len() - 1] as i64) << 61) >> 61
+    );
+    assert!(reader.read_bit().is_err());
+    assert_eq!(reader.read_bits(0).unwrap(), 0);
+}
+
+#[test]
+fn full_cache_bit_reader_test() {
+    bit_reader_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_bit_reader_test() {
+    bit_reader_test(SwapCache::new(new_test_file(), 7, 3).unwrap());
+}
+
+const TEST_TEMPLATE: &str = "
+    endian big;
+
+    enum Kind : u8 { Data = 1, Index = 2 }
+
+    struct Entry {
+        Kind kind;
+        u32 len;
+        bytes payload[len];
+        if (kind == Kind::Index) u16le refs[len / 4];
+    }
+
+    // The root struct.
+    struct File {
+        bytes magic[4];
+        u16 count;
+        Entry entries[count];
+        cstr comment;
+        i16le delta;
+        f32 ratio;
+        if (count == 2 || entries.bogus) bytes tail[(1 + 2 * 3 == 7) << 1];
+    }
+";
+
+fn template_test_data() -> Vec<u8> {
+    let mut data = b"HXCV\x00\x02".to_vec();
+    data.extend_from_slice(&[1, 0, 0, 0, 3]);
+    data.extend_from_slice(b"abc");
+    data.extend_from_slice(&[2, 0, 0, 0, 8]);
+    data.extend_from_slice(b"12345678");
+    data.extend_from_slice(&[0x02, 0x01, 0x04, 0x03]);
+    data.extend_from_slice(b"hi\0");
+    data.extend_from_slice(&(-5i16).to_le_bytes());
+    data.extend_from_slice(&1.5f32.to_be_bytes());
+    data.extend_from_slice(&[0xAA, 0xBB]);
+    data
+}
+
+fn template_test<C: Cache>(cache: C) {
+    let data = template_test_data();
+    let template = Template::parse(TEST_TEMPLATE).unwrap();
+    assert_eq!(template.root(), Some("File"));
+
+    let root = template.evaluate(&cache, 0).unwrap();
+    assert_eq!(root.range(), 0..data.len() as u64);
+    assert!(root.is_struct());
+    assert_eq!(root.value().unwrap(), None);
+    let names: Vec<String> = root
+        .children()
+        .unwrap()
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect();
+    assert_eq!(
+        names,
+        ["magic", "count", "entries", "comment", "delta", "ratio", "tail"]
+    );
+
+    let entries = root.child("entries").unwrap().unwrap();
+    assert!(entries.is_array());
+    assert_eq!(entries.type_name(), "Entry[]");
+    assert_eq!(entries.len().unwrap(), Some(2));
+    assert_eq!(entries.range(), 6..31);
+    assert_eq!(
+        entries.element(2).unwrap().map(|field| field.offset()),
+        None
+    );
+    let first = entries.element(0).unwrap().unwrap();
+    assert_eq!(first.name(), "[0]");
+    assert_eq!(first.size(), 8);
+    assert_eq!(first.child("refs").unwrap().map(|field| field.size()), None);
+    assert_eq!(
+        first.child("payload").unwrap().unwrap().value().unwrap(),
+        Some(Value::Bytes(b"abc".to_vec()))
+    );
+
+    let kind = root.get("entries[1].kind").unwrap().unwrap();
+    assert_eq!(kind.offset(), 14);
+    assert_eq!(kind.type_name(), "Kind");
+    let kind = kind.value().unwrap().unwrap();
+    assert_eq!(kind.to_string(), "Index");
+    assert_eq!(
+        kind,
+        Value::Enum {
+            value: 2,
+            variant: Some("Index".to_string())
+        }
+    );
+    let refs = root.get("entries[1].refs").unwrap().unwrap();
+    assert_eq!(refs.type_name(), "u16le[]");
+    assert_eq!(refs.range(), 27..31);
+    assert_eq!(
+        root.get("entries[1].refs[1]")
+            .unwrap()
+            .unwrap()
+            .value()
+            .unwrap(),
+        Some(Value::Unsigned(0x0304))
+    );
+    assert!(root.get("entries[9]").unwrap().is_none());
+    assert!(root.get("missing.field").unwrap().is_none());
+    assert!(root.get("entries[x]").unwrap_err().is_template_error());
+
+    let get = |path: &str| root.get(path).unwrap().unwrap().value().unwrap().unwrap();
+    assert_eq!(get("comment"), Value::Bytes(b"hi".to_vec()));
+    assert_eq!(root.get("comment").unwrap().unwrap().size(), 3);
+    assert_eq!(get("delta"), Value::Signed(-5));
+    assert_eq!(get("ratio"), Value::Float(1.5));
+    assert_eq!(get("tail").to_string(), "AA BB");
+
+    // Laid over the wrong offset, the count of entries exceeds the data.
+    let err = template.evaluate(&cache, 10).unwrap_err();
+    assert!(err.is_out_of_range_error());
+}
+
+#[test]
+fn full_cache_template_test() {
+    let data = template_test_data();
+    template_test(FullCache::new(std::io::Cursor::new(data)).unwrap());
+}
+
+#[test]
+fn swap_cache_template_test() {
+    let data = template_test_data();
+    template_test(SwapCache::new(std::io::Cursor::new(data), 3, 2).unwrap());
+}
+
+#[test]
+fn template_builder_test() {
+    let mut built = Template::new();
+    built.add_enum(
+        EnumDef::new("Kind", Primitive::U8, Endian::Big)
+            .variant("Data", 1)
+            .variant("Index", 2),
+    );
+    built.add_struct(
+        StructDef::new("Entry")
+            .field("kind", FieldType::Enum("Kind".into()))
+            .field("len", FieldType::Primitive(Primitive::U32, Endian::Big))
+            .field("payload", FieldType::Bytes(Expr::field("len")))
+            .field_if(
+                "refs",
+                FieldType::Array(
+                    Box::new(FieldType::Primitive(Primitive::U16, Endian::Little)),
+                    Expr::parse("len / 4").unwrap(),
+                ),
+                Expr::parse("kind == Kind::Index").unwrap(),
+            ),
+    );
+    built.add_struct(
+        StructDef::new("File")
+            .field("magic", FieldType::Bytes(Expr::constant(4)))
+            .field("count", FieldType::Primitive(Primitive::U16, Endian::Big))
+            .field(
+                "entries",
+                FieldType::Array(
+                    Box::new(FieldType::Struct("Entry".into())),
+                    Expr::field("count"),
+                ),
+            )
+            .field("comment", FieldType::CStr)
+            .field(
+                "delta",
+                FieldType::Primitive(Primitive::I16, Endian::Little),
+            )
+            .field("ratio", FieldType::Primitive(Primitive::F32, Endian::Big))
+            .field_if(
+                "tail",
+                FieldType::Bytes(Expr::parse("(1 + 2 * 3 == 7) << 1").unwrap()),
+                Expr::parse("count == 2 || entries.bogus").unwrap(),
+            ),
+    );
+    // Without a root set, built and parsed templates use the last struct.
+    assert_eq!(built.root(), Some("File"));
+    assert_eq!(built, Template::parse(TEST_TEMPLATE).unwrap());
+    built.set_root("File");
+    let rooted = format!("root File;\n{}", TEST_TEMPLATE);
+    assert_eq!(built, Template::parse(&rooted).unwrap());
+
+    // A root set before the structs are added is kept.
+    let mut early = Template::new();
+    early.set_root("A");
+    early.add_struct(StructDef::new("A"));
+    early.add_struct(StructDef::new("B"));
+    assert_eq!(early.root(), Some("A"));
+    let parsed = Template::parse("root A; struct A { } struct B { }").unwrap();
+    assert_eq!(early, parsed);
+
+    // A recursive struct ends when its condition is false.
+    let list = Template::parse("struct Node { u8 more; if (more) Node next; }").unwrap();
+    let cache = FullCache::new(std::io::Cursor::new(vec![1, 1, 1, 0, 9])).unwrap();
+    let root = list.evaluate(&cache, 0).unwrap();
+    assert_eq!(root.size(), 4);
+    let last = root.get("next.next.next").unwrap().unwrap();
+    assert_eq!(last.offset(), 3);
+    assert!(last.child("next").unwrap().is_none());
+
+    let endless = Template::parse("struct Loop { Loop inner; }").unwrap();
+    assert!(endless.evaluate(&cache, 0).unwrap_err().is_template_error());
+
+    for text in [
+        "struct A { Missing m; }",
+        "struct A { u8 a }",
+        "struct A { u8 a[1 +]; }",
+        "enum E : f32 { A = 1 }",
+        "struct A { u8 $; }",
+        "root B; struct A { u8 a; }",
+    ]
+    .iter()
+    {
+        assert!(
+            Template::parse(text).unwrap_err().is_template_error(),
+            "{}",
+            text
+        );
+    }
+    let undefined = Template::parse("struct A { u8 a[b]; }").unwrap();
+    assert!(undefined
+        .evaluate(&cache, 0)
+        .unwrap_err()
+        .is_template_error());
+    let zero = Template::parse("struct A { u8 a[1 / (2 - 2)]; }").unwrap();
+    assert!(zero.evaluate(&cache, 0).unwrap_err().is_template_error());
+
+    // Arrays of empty elements are rejected rather than walked.
+    let ones = FullCache::new(std::io::Cursor::new(vec![0xFF; 8])).unwrap();
+    let empty = Template::parse("struct E { } struct R { u32 n; E items[n]; }").unwrap();
+    assert!(empty.evaluate(&ones, 0).unwrap_err().is_template_error());
+    let empty = Template::parse("struct E { } struct R { E items[1000000000000]; }").unwrap();
+    let items = empty
+        .evaluate(&ones, 0)
+        .unwrap()
+        .get("items")
+        .unwrap()
+        .unwrap();
+    assert!(items.children().unwrap_err().is_template_error());
+    let none = Template::parse("struct E { } struct R { E items[0]; }").unwrap();
+    let items = none
+        .evaluate(&ones, 0)
+        .unwrap()
+        .get("items")
+        .unwrap()
+        .unwrap();
+    assert!(items.children().unwrap().is_empty());
+
+    // Deeply nested expressions are rejected by the parser.
+    let deep = format!(
+        "struct A {{ u8 a[{}1{}]; }}",
+        "(".repeat(1000),
+        ")".repeat(1000)
+    );
+    assert!(Template::parse(&deep).unwrap_err().is_template_error());
+    let long = format!("struct A {{ u8 a[1{}]; }}", " + 1".repeat(1000));
+    assert!(Template::parse(&long).unwrap_err().is_template_error());
+    let negated = format!("struct A {{ u8 a[{}1]; }}", "-".repeat(1000));
+    assert!(Template::parse(&negated).unwrap_err().is_template_error());
+    let nested = format!(
+        "struct A {{ u8 a[{}1{}]; }}",
+        "(".repeat(20),
+        ")".repeat(20)
+    );
+    assert!(Template::parse(&nested).is_ok());
+    assert!(Template::new()
+        .evaluate(&cache, 0)
+        .unwrap_err()
+        .is_template_error());
+}
+
+fn inspect_test<C: Cache>(cache: C) {
+    let inspection = inspect(&cache, 0).unwrap();
+    assert_eq!(inspection.bytes(), &(0..16).collect::<Vec<u8>>()[..]);
+    assert_eq!(inspection.u32(Endian::Little), Some(0x0302_0100));
+    assert_eq!(inspection.u64(Endian::Big), Some(0x0001_0203_0405_0607));
+    assert_eq!(
+        inspection.guid(Endian::Little).unwrap(),
+        "{03020100-0504-0706-0809-0A0B0C0D0E0F}"
+    );
+    assert_eq!(
+        inspection.guid(Endian::Big).unwrap(),
+        "{00010203-0405-0607-0809-0A0B0C0D0E0F}"
+    );
+    assert_eq!(inspection.utf8_char(), Some(('\0', 1)));
+
+    let inspection = inspect(&cache, 16).unwrap();
+    assert_eq!(inspection.offset(), 16);
+    assert_eq!(inspection.i8(), -1);
+    assert_eq!(inspection.i16(Endian::Big), Some(-2));
+    assert_eq!(inspection.utf8_char(), None);
+
+    let at = |offset: u64| inspect(&cache, offset).unwrap();
+    assert_eq!(at(18).f64(Endian::Little), Some(-0.25));
+    assert_eq!(at(26).f32(Endian::Big), Some(1.5));
+    assert_eq!(at(30).utf8_char(), Some(('€', 3)));
+    assert_eq!(at(33).utf16_char(Endian::Little), Some(('😀', 4)));
+    assert_eq!(at(35).utf16_char(Endian::Little), None);
+    assert_eq!(at(37).utf16_char(Endian::Big), Some(('é', 2)));
+    assert_eq!(
+        at(39).unix_time32(Endian::Little).unwrap().to_string(),
+        "2021-03-04 12:34:56"
+    );
+    assert_eq!(
+        at(43).unix_time64(Endian::Big).unwrap().to_string(),
+        "1969-12-31 23:59:59"
+    );
+    let filetime = at(51).filetime(Endian::Little).unwrap();
+    assert_eq!(filetime.to_string(), "1970-01-01 00:00:01.000000500");
+    assert_eq!(
+        at(59).dos_time(Endian::Little).unwrap(),
+        DateTime {
+            year: 2021,
+            month: 3,
+            day: 4,
+            hour: 12,
+            minute: 34,
+            second: 56,
+            nanosecond: 0,
+        }
+    );
+    assert_eq!(at(16).dos_time(Endian::Little), None);
+
+    // Fewer bytes are available at the end of the cache.
+    let last = at(cache.len() - 3);
+    assert_eq!(last.bytes().len(), 3);
+    assert_eq!(last.u16(Endian::Little), Some(0x01AA));
+    assert_eq!(last.u32(Endian::Little), None);
+    assert_eq!(last.guid(Endian::Big), None);
+    assert!(inspect(&cache, cache.len())
+        .unwrap_err()
+        .is_out_of_range_error());
+}
+
+fn inspect_test_data() -> Vec<u8> {
+    let mut data: Vec<u8> = (0..16).collect();
+    data.extend_from_slice(&[0xFF, 0xFE]);
+    data.extend_from_slice(&(-0.25f64).to_le_bytes());
+    data.extend_from_slice(&1.5f32.to_be_bytes());
+    data.extend_from_slice("€".as_bytes());
+    data.extend_from_slice(&[0x3D, 0xD8, 0x00, 0xDE]);
+    data.extend_from_slice(&[0x00, 0xE9]);
+    data.extend_from_slice(&1_614_861_296i32.to_le_bytes());
+    data.extend_from_slice(&(-1i64).to_be_bytes());
+    data.extend_from_slice(&116_444_736_010_000_005u64.to_le_bytes());
+    let time: u16 = (12 << 11) | (34 << 5) | 28;
+    let date: u16 = (41 << 9) | (3 << 5) | 4;
+    data.extend_from_slice(&time.to_le_bytes());
+    data.extend_from_slice(&date.to_le_bytes());
+    data.extend_from_slice(&[0xAA, 0x01, 0x02]);
+    data
+}
+
+#[test]
+fn full_cache_inspect_test() {
+    let data = inspect_test_data();
+    inspect_test(FullCache::new(std::io::Cursor::new(data)).unwrap());
+}
+
+#[test]
+fn swap_cache_inspect_test() {
+    let data = inspect_test_data();
+    inspect_test(SwapCache::new(std::io::Cursor::new(data), 3, 2).unwrap());
+}
+
+fn line_index_test<C: Cache>(cache: C) {
+    let data = ADV_HUCK_FINN;
+    let mut starts = vec![0u64];
+    starts.extend(
+        data.iter()
+            .enumerate()
+            .filter(|(_, byte)| **byte == b'\n')
+            .map(|(idx, _)| idx as u64 + 1),
+    );
+    if starts.last() == Some(&(data.len() as u64)) {
+        starts.pop();
+    }
+
+    let mut index = LineIndex::with_interval(7);
+    let mut rng = XorShift(0x0bad_cafe_f00d_d00d);
+    for round in 0..3 {
+        for _ in 0..50 {
+            let line = rng.next(starts.len() as u64 + 2);
+            assert_eq!(
+                index.line_to_offset(&cache, line).unwrap(),
+                starts.get(line as usize).cloned()
+            );
+            let offset = rng.next(data.len() as u64);
+            let expected = starts.partition_point(|start| *start <= offset) as u64 - 1;
+            assert_eq!(index.offset_to_line(&cache, offset).unwrap(), expected);
+        }
+        assert_eq!(index.line_count(&cache).unwrap(), starts.len() as u64);
+        // Each round queries a more complete index.
+        if round == 0 {
+            assert!(!index.update(&cache, 10_000).unwrap());
+            assert_eq!(index.indexed_len(), 10_000);
+        } else {
+            let token = CancellationToken::new();
+            let mut last = 0;
+            index.build(&cache, &token, |done, _| last = done).unwrap();
+            assert_eq!(last > 0, round == 1);
+            assert!(index.is_complete(&cache));
+        }
+    }
+
+    let lines: Vec<(u64, Range<u64>)> = index
+        .lines(&cache, 100..105)
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(lines.len(), 5);
+    for (line, range) in lines.iter() {
+        assert_eq!(range.start, starts[*line as usize]);
+        assert_eq!(range.end + 1, starts[*line as usize + 1]);
+    }
+    assert_eq!(index.lines(&cache, ..).count(), starts.len(),);
+    let tail = starts.len() as u64 - 1;
+    let last = index.lines(&cache, tail..).next().unwrap().unwrap();
+    assert_eq!(last.1.start, starts[tail as usize]);
+    assert!(index.lines(&cache, tail + 1..).next().is_none());
+    assert!(index
+        .offset_to_line(&cache, data.len() as u64)
+        .unwrap_err()
+        .is_out_of_range_error());
+
+    let token = CancellationToken::new();
+    token.cancel();
+    index.clear();
+    assert!(index
+        .build(&cache, &token, |_, _| ())
+        .unwrap_err()
+        .is_cancelled_error());
+    assert_eq!(index.indexed_len(), 0);
+}
+
+#[test]
+fn full_cache_line_index_test() {
+    line_index_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_line_index_test() {
+    line_index_test(test_swap_cache());
+}
+
+#[test]
+fn line_index_edge_test() {
+    for (text, count) in [("", 0), ("\n", 1), ("a", 1), ("a\n", 1), ("a\n\nb", 3)].iter() {
+        let cache = FullCache::new(std::io::Cursor::new(text.as_bytes().to_vec())).unwrap();
+        let mut index = LineIndex::with_interval(1);
+        index.update(&cache, u64::MAX).unwrap();
+        assert_eq!(index.line_count(&cache).unwrap(), *count, "{:?}", text);
+        assert_eq!(index.lines(&cache, ..).count() as u64, *count, "{:?}", text);
+        assert_eq!(index.line_to_offset(&cache, *count).unwrap(), None);
+    }
+}
+
+/// Decodes the data with `DecodeChars` over caches with different page
+/// sizes, checks that `decode` agrees, and returns the characters.
+fn decode_all(data: &[u8], encoding: Encoding) -> Vec<(u64, DecodedChar)> {
+    let full = FullCache::new(std::io::Cursor::new(data.to_vec())).unwrap();
+    let expected: Vec<(u64, DecodedChar)> = DecodeChars::new(&full, .., encoding)
+        .collect::<Result<_>>()
+        .unwrap();
+    for page_size in [1, 3, 7].iter() {
+        let swap = SwapCache::new(std::io::Cursor::new(data.to_vec()), *page_size, 2).unwrap();
+        let chars: Vec<(u64, DecodedChar)> = DecodeChars::new(&swap, .., encoding)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(chars, expected, "{} with pages of {}", encoding, page_size);
+
+        let mut pieces = Vec::new();
+        decode(&swap, .., encoding, |offset, chunk| {
+            let piece = match chunk {
+                TextChunk::Text(text) => Ok(text.to_string()),
+                TextChunk::Malformed(bytes) => Err(bytes.to_vec()),
+            };
+            pieces.push((offset, piece));
+            Ok(())
+        })
+        .unwrap();
+        let mut chars = expected.iter();
+        for (offset, piece) in pieces.iter() {
+            match piece {
+                Ok(text) => {
+                    for (idx, c) in text.chars().enumerate() {
+                        let (at, decoded) = chars.next().unwrap();
+                        assert!(idx > 0 || at == offset);
+                        assert_eq!(*decoded, DecodedChar::Char(c));
+                    }
+                }
+                Err(bytes) => {
+                    let (at, malformed) = chars.next().unwrap();
+                    assert_eq!(
+                        (*at, *malformed),
+                        (*offset, DecodedChar::Malformed(bytes.len()))
+                    );
+                    assert_eq!(&data[*at as usize..*at as usize + bytes.len()], &bytes[..]);
+                }
+            }
+        }
+        assert!(chars.next().is_none());
+    }
+    expected
+}
+
+#[test]
+fn decode_utf8_test() {
+    let mut data = "Größe – 日本語 😀 ok".as_bytes().to_vec();
+    data.extend_from_slice(&[0xFF, b'a', 0xE2, 0x82, b'b', 0xC3, 0xF0, 0x9F, 0x98]);
+    let mut expected = Vec::new();
+    let mut offset = 0;
+    for chunk in data.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            expected.push((offset, DecodedChar::Char(c)));
+            offset += c.len_utf8() as u64;
+        }
+        if !chunk.invalid().is_empty() {
+            expected.push((offset, DecodedChar::Malformed(chunk.invalid().len())));
+            offset += chunk.invalid().len() as u64;
+        }
+    }
+    assert_eq!(decode_all(&data, Encoding::Utf8), expected);
+    assert_eq!(
+        expected.last(),
+        Some(&(data.len() as u64 - 3, DecodedChar::Malformed(3)))
+    );
+
+    let huck: Vec<(u64, DecodedChar)> = DecodeChars::new(
+        &SwapCache::new(new_test_file(), 5, 3).unwrap(),
+        ..,
+        Encoding::Utf8,
+    )
+    .collect::<Result<_>>()
+    .unwrap();
+    let text = std::str::from_utf8(ADV_HUCK_FINN).unwrap();
+    assert_eq!(huck.len(), text.chars().count());
+    assert!(huck
+        .iter()
+        .zip(text.char_indices())
+        .all(|(a, (idx, c))| *a == (idx as u64, DecodedChar::Char(c))));
+
+    // A range that starts within a character reports its tail as malformed.
+    let cache = FullCache::new(std::io::Cursor::new("é!".as_bytes().to_vec())).unwrap();
+    let chars: Vec<(u64, DecodedChar)> = DecodeChars::new(&cache, 1.., Encoding::Utf8)
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(
+        chars,
+        [(1, DecodedChar::Malformed(1)), (2, DecodedChar::Char('!'))]
+    );
+}
+
+#[test]
+fn decode_utf16_test() {
+    let text = "aé日😀z";
+    for encoding in [Encoding::Utf16Le, Encoding::Utf16Be].iter() {
+        let mut units: Vec<u16> = text.encode_utf16().collect();
+        units.insert(2, 0xDC00);
+        units.insert(4, 0xD800);
+        let mut data = Vec::new();
+        for unit in units.iter() {
+            if *encoding == Encoding::Utf16Le {
+                data.extend_from_slice(&unit.to_le_bytes());
+            } else {
+                data.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+        data.push(0x41);
+        let mut expected = Vec::new();
+        let mut offset = 0;
+        for c in std::char::decode_utf16(units.iter().cloned()) {
+            match c {
+                Ok(c) => {
+                    expected.push((offset, DecodedChar::Char(c)));
+                    offset += 2 * c.len_utf16() as u64;
+                }
+                Err(_) => {
+                    expected.push((offset, DecodedChar::Malformed(2)));
+                    offset += 2;
+                }
+            }
+        }
+        expected.push((offset, DecodedChar::Malformed(1)));
+        assert_eq!(decode_all(&data, *encoding), expected);
+    }
+}
+
+#[test]
+fn decode_single_byte_test() {
+    let data: Vec<u8> = (0..=255).collect();
+    let latin1 = decode_all(&data, Encoding::Latin1);
+    assert_eq!(latin1.len(), 256);
+    assert!(latin1
+        .iter()
+        .all(|(offset, c)| *c == DecodedChar::Char(*offset as u8 as char)));
+
+    let windows = decode_all(&data, Encoding::Windows1252);
+    assert_eq!(windows.len(), 256);
+    assert_eq!(windows[0x41], (0x41, DecodedChar::Char('A')));
+    assert_eq!(windows[0x80], (0x80, DecodedChar::Char('€')));
+    assert_eq!(windows[0x81], (0x81, DecodedChar::Malformed(1)));
+    assert_eq!(windows[0x9F], (0x9F, DecodedChar::Char('Ÿ')));
+    assert_eq!(windows[0xE9], (0xE9, DecodedChar::Char('é')));
+    let undefined = windows
+        .iter()
+        .filter(|(_, c)| *c == DecodedChar::Malformed(1))
+        .count();
+    assert_eq!(undefined, 5);
+
+    let ascii = decode_all(&data, Encoding::Ascii);
+    assert_eq!(ascii[0x7F], (0x7F, DecodedChar::Char('\x7F')));
+    assert!(ascii[0x80..]
+        .iter()
+        .all(|(_, c)| *c == DecodedChar::Malformed(1)));
+}
+
+/// A small deterministic pseudo random number generator for edit tests.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
+
+fn assert_content<C: Cache>(cache: &C, expected: &[u8]) {
+    assert_eq!(cache.len(), expected.len() as u64);
+    let mut buf = vec![0; expected.len() + 10];
+    assert_eq!(cache.read(0, &mut buf).unwrap(), expected.len());
+    assert!(&buf[..expected.len()] == expected);
+}
+
+fn edit_test<C: Cache>(cache: C) {
+    let mut cache = EditCache::new(cache);
+    let mut model = ADV_HUCK_FINN.to_vec();
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    assert!(!cache.is_modified());
+    for i in 0..300 {
+        let len = model.len() as u64;
+        let offset = rng.next(len + 1);
+        let bytes: Vec<u8> = (0..rng.next(40)).map(|b| (b + i) as u8).collect();
+        match rng.next(3) {
+            0 => {
+                cache.insert(offset, &bytes).unwrap();
+                let offset = offset as usize;
+                model.splice(offset..offset, bytes);
+            }
+            1 => {
+                cache.overwrite(offset, &bytes).unwrap();
+                let offset = offset as usize;
+                let end = (offset + bytes.len()).min(model.len());
+                model.splice(offset..end, bytes);
+            }
+            _ => {
+                let end = offset + rng.next(500);
+                cache.delete(offset..end);
+                model.drain(offset as usize..(end as usize).min(model.len()));
+            }
+        }
+    }
+    assert!(cache.is_modified());
+    assert_content(&cache, &model);
+    let mut buf = vec![0; 1000];
+    assert_eq!(cache.read(777, &mut buf).unwrap(), 1000);
+    assert_eq!(buf, &model[777..1777]);
+
+    let err = cache.insert(model.len() as u64 + 1, b"x").unwrap_err();
+    assert!(err.is_out_of_range_error());
+
+    let mut reader = CacheReader::new(cache);
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, model);
+
+    let mut cache = reader.into_inner();
+    cache.revert();
+    assert!(!cache.is_modified());
+    assert_content(&cache, ADV_HUCK_FINN);
+}
+
+#[test]
+fn full_cache_edit_test() {
+    edit_test(test_full_cache());
+}
+
+#[test]
+fn swap_cache_edit_test() {
+    edit_test(test_swap_cache());
+}
+
+#[test]
+fn edit_cache_undo_test() {
+    let mut cache = EditCache::new(test_swap_cache());
+    assert!(!cache.undo());
+
+    cache.insert(0, b"Hello ").unwrap();
+    cache.overwrite(6, b"HUCK").unwrap();
+    let after_two = {
+        let mut data = b"Hello HUCK".to_vec();
+        data.extend_from_slice(&ADV_HUCK_FINN[4..]);
+        data
+    };
+    assert_content(&cache, &after_two);
+
+    cache.begin_group();
+    cache.delete(100..);
+    cache.begin_group();
+    cache.insert(0, b">>").unwrap();
+    cache.end_group();
+    cache.overwrite(2, b"J").unwrap();
+    cache.end_group();
+    let mut grouped = b">>J".to_vec();
+    grouped.extend_from_slice(&after_two[1..100]);
+    assert_content(&cache, &grouped);
+
+    assert!(cache.undo());
+    assert_content(&cache, &after_two);
+    assert!(cache.redo());
+    assert_content(&cache, &grouped);
+    assert!(!cache.redo());
+
+    assert!(cache.undo());
+    assert!(cache.undo());
+    let mut after_one = b"Hello ".to_vec();
+    after_one.extend_from_slice(ADV_HUCK_FINN);
+    assert_content(&cache, &after_one);
+    assert!(cache.undo());
+    assert_content(&cache, ADV_HUCK_FINN);
+    assert!(!cache.undo());
+    assert!(cache.redo());
+    assert!(cache.can_redo());
+
+    cache.delete(..10);
+    assert!(!cache.can_redo());
+
+    cache.set_history_limit(1);
+    cache.revert();
+    assert_content(&cache, ADV_HUCK_FINN);
+    assert!(cache.undo());
+    assert!(!cache.undo());
+    assert_eq!(cache.len(), ADV_HUCK_FINN.len() as u64 - 4);
+}
+
+#[test]
+fn edit_cache_history_size_test() {
+    let mut cache = EditCache::new(test_full_cache());
+    cache.set_history_size_limit(3000);
+    for fill in 0..10 {
+        cache.overwrite(100, &[fill; 1000]).unwrap();
+        assert!(cache.history_size() <= 3000);
+    }
+    let mut undone = 0;
+    while cache.undo() {
+        undone += 1;
+    }
+    assert!(undone > 0 && undone < 10);
+    let mut expected = ADV_HUCK_FINN.to_vec();
+    expected[100..1100].copy_from_slice(&[9 - undone; 1000]);
+    assert_content(&cache, &expected);
+
+    // Overwritten bytes that only forgotten steps refer to are freed.
+    let mut cache = EditCache::new(test_full_cache());
+    let base_size = cache.base().cache_size();
+    cache.set_history_limit(1);
+    for fill in 0..40 {
+        cache.overwrite(0, &[fill; 1 << 16]).unwrap();
+        assert!(cache.cache_size() - base_size <= (1 << 20) + (1 << 16));
+    }
+    let mut expected = ADV_HUCK_FINN.to_vec();
+    expected[..1 << 16].copy_from_slice(&[39; 1 << 16]);
+    assert_content(&cache, &expected);
+    assert!(cache.undo());
+    expected[..1 << 16].copy_from_slice(&[38; 1 << 16]);
+    assert_content(&cache, &expected);
+    assert!(!cache.undo());
+}
+
+#[cfg(unix)]
+fn inode(path: &std::path::Path) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).unwrap().ino()
+}
+
+#[test]
+fn edit_cache_save_test() {
+    let named = new_named_test_file();
+    let path = named.into_temp_path();
+    let swap = SwapCache::new(
+        File::open(&path).unwrap(),
+        SWAP_TEST_PAGE_SZ,
+        SWAP_TEST_FRAMES,
+    );
+    let mut cache = EditCache::new(swap.unwrap());
+    assert!(cache.save_in_place().unwrap_err().is_io_error());
+
+    cache.insert(0, b"HEADER").unwrap();
+    cache.delete(1000..2000);
+    let mut model = b"HEADER".to_vec();
+    model.extend_from_slice(&ADV_HUCK_FINN[..994]);
+    model.extend_from_slice(&ADV_HUCK_FINN[1994..]);
+
+    let copy = path.with_extension("copy");
+    cache.save_to(&copy).unwrap();
+    assert_eq!(std::fs::read(&copy).unwrap(), model);
+    assert!(!cache.is_modified());
+    assert!(!cache.can_undo());
+    assert_content(&cache, &model);
+    assert_eq!(cache.path(), Some(copy.as_path()));
+
+    // Same length, only new bytes: rewritten in place.
+    #[cfg(unix)]
+    let before = inode(&copy);
+    cache.overwrite(10, b"0123456789").unwrap();
+    cache.overwrite(5000, b"abc").unwrap();
+    model[10..20].copy_from_slice(b"0123456789");
+    model[5000..5003].copy_from_slice(b"abc");
+    cache.save_in_place().unwrap();
+    #[cfg(unix)]
+    assert_eq!(inode(&copy), before);
+    assert_eq!(std::fs::read(&copy).unwrap(), model);
+    assert_content(&cache, &model);
+
+    // Moved bytes: replaced atomically.
+    cache.delete(0..6);
+    cache.insert(100, b"HEADER").unwrap();
+    let header: Vec<u8> = model.drain(0..6).collect();
+    model.splice(100..100, header);
+    cache.save_in_place().unwrap();
+    #[cfg(unix)]
+    assert_ne!(inode(&copy), before);
+    assert_eq!(std::fs::read(&copy).unwrap(), model);
+    assert_content(&cache, &model);
+    std::fs::remove_file(&copy).unwrap();
+}
+
+#[test]
+fn edit_cache_journal_test() {
+    use std::io::Write;
+    let named = new_named_test_file();
+    let path = named.into_temp_path();
+    let journal = path.with_extension("journal");
+    let open = || FullCache::new(File::open(&path).unwrap()).unwrap();
+
+    let mut model = ADV_HUCK_FINN.to_vec();
+    let mut cache = EditCache::with_journal(open(), &journal).unwrap();
+    cache.overwrite(10, b"0123456789").unwrap();
+    cache.begin_group();
+    cache.insert(0, b"HEADER").unwrap();
+    cache.delete(1000..2000);
+    cache.end_group();
+    cache.insert(50, b"undone").unwrap();
+    assert!(cache.undo());
+    cache.sync_journal().unwrap();
+    model[10..20].copy_from_slice(b"0123456789");
+    model.splice(0..0, b"HEADER".iter().cloned());
+    model.drain(1000..2000);
+    drop(cache);
+
+    // A record torn by a crash is discarded.
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&journal)
+        .unwrap()
+        .write_all(&[9, 0, 0, 0, 0, 0, 0, 0, 1, 2])
+        .unwrap();
+    let mut cache = EditCache::recover(open(), &journal).unwrap();
+    assert_content(&cache, &model);
+    assert!(cache.redo());
+    assert!(cache.undo());
+    assert!(cache.undo());
+    assert!(cache.undo());
+    assert!(!cache.can_undo());
+    assert_content(&cache, ADV_HUCK_FINN);
+    assert!(cache.redo());
+    assert!(cache.redo());
+    cache.insert(0, b">").unwrap();
+    model.insert(0, b'>');
+    drop(cache);
+
+    let mut cache = EditCache::recover(open(), &journal).unwrap();
+    assert_content(&cache, &model);
+    assert!(!cache.can_redo());
+
+    // Saving restarts the journal against the saved file.
+    cache.save_to(&path).unwrap();
+    drop(cache);
+    let cache = EditCache::recover(open(), &journal).unwrap();
+    assert!(!cache.is_modified());
+    assert_content(&cache, &model);
+    drop(cache);
+
+    std::fs::write(&path, b"changed").unwrap();
+    let err = EditCache::recover(open(), &journal).err().unwrap();
+    assert!(err.is_journal_error());
+    std::fs::write(&journal, b"not a journal").unwrap();
+    let err = EditCache::recover(open(), &journal).err().unwrap();
+    assert!(err.is_journal_error());
+    std::fs::remove_file(&journal).unwrap();
+}
+
+#[test]
+fn edit_cache_marks_test() {
+    let mut cache = EditCache::new(test_full_cache());
+    let left = cache.add_mark(100, Gravity::Left).unwrap();
+    let right = cache.add_mark(100, Gravity::Right).unwrap();
+    let end = cache.add_mark(cache.len(), Gravity::Right).unwrap();
+    let inside = cache.add_mark(300, Gravity::Left).unwrap();
+    assert!(cache.add_mark(cache.len() + 1, Gravity::Left).is_err());
+    let len = cache.len();
+
+    cache.insert(100, b"abc").unwrap();
+    assert_eq!(cache.mark_offset(left), Some(100));
+    assert_eq!(cache.mark_offset(right), Some(103));
+    assert_eq!(cache.mark_offset(inside), Some(303));
+    assert_eq!(cache.mark_offset(end), Some(len + 3));
+
+    cache.delete(250..350);
+    assert_eq!(cache.mark_offset(inside), Some(250));
+    assert_eq!(cache.mark_offset(end), Some(len - 97));
+    cache.delete(0..10);
+    assert_eq!(cache.mark_offset(left), Some(90));
+
+    cache.overwrite(90, b"xyz").unwrap();
+    assert_eq!(cache.mark_offset(left), Some(90));
+    cache.overwrite(cache.len() - 1, b"tail").unwrap();
+    assert_eq!(cache.mark_offset(end), Some(cache.len()));
+
+    assert!(cache.undo());
+    assert!(cache.undo());
+    assert!(cache.undo());
+    assert_eq!(cache.mark_offset(left), Some(100));
+    assert_eq!(cache.mark_offset(inside), Some(250));
+    let late = cache.add_mark(cache.len(), Gravity::Left).unwrap();
+    assert!(cache.undo());
+    assert_eq!(cache.mark_offset(inside), Some(303));
+    assert_eq!(cache.mark_offset(late), Some(len - 97));
+    assert!(cache.redo());
+    assert_eq!(cache.mark_offset(inside), Some(250));
+
+    assert!(cache.move_mark(inside, 5).unwrap());
+    assert_eq!(cache.mark_offset(inside), Some(5));
+    assert!(cache.remove_mark(inside));
+    assert!(!cache.remove_mark(inside));
+    assert_eq!(cache.mark_offset(inside), None);
+    assert!(!cache.move_mark(inside, 5).unwrap());
+    assert_eq!(cache.mark_gravity(right), Some(Gravity::Right));
+}
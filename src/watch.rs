@@ -0,0 +1,316 @@
+use super::{Cache, Error, Fingerprint, Fingerprinted, Operation, Result, ResultExt};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::ops::RangeBounds;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+/// A change to the file behind a `WatchedCache`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The file was modified in place, and all cached data was invalidated.
+    Modified,
+
+    /// The file was replaced by another file at the same path, and the cache
+    /// was re-based onto the new file.
+    Replaced,
+
+    /// The file was removed and no file exists at its path. The cache keeps
+    /// serving the contents of the removed file.
+    Removed,
+}
+
+const NAME: &str = "WatchedCache";
+
+const FILE_EVENTS: WatchMask = WatchMask::MODIFY
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::ATTRIB)
+    .union(WatchMask::DELETE_SELF)
+    .union(WatchMask::MOVE_SELF);
+
+const DIR_EVENTS: WatchMask = WatchMask::CREATE
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO);
+
+struct Watch<C: Cache<Source = File>> {
+    cache: C,
+    inotify: Inotify,
+    file_wd: Option<WatchDescriptor>,
+    dir_wd: WatchDescriptor,
+    name: OsString,
+    id: Option<(u64, u64)>,
+    subscribers: Vec<Sender<Change>>,
+    buffer: Vec<u8>,
+}
+
+fn file_id(path: &Path) -> Result<Option<(u64, u64)>> {
+    match std::fs::metadata(path) {
+        Ok(meta) => Ok(Some((meta.dev(), meta.ino()))),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(NAME, Operation::Metadata, None),
+    }
+}
+
+impl<C: Cache<Source = File>> Watch<C> {
+    fn pending(&mut self) -> Result<bool> {
+        let mut pending = false;
+        loop {
+            let events = match self.inotify.read_events(&mut self.buffer) {
+                Ok(events) => events,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(pending),
+                Err(e) => return Err(e).context(NAME, Operation::Watch, None),
+            };
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    pending = true;
+                } else if Some(&event.wd) == self.file_wd.as_ref() {
+                    pending = pending || !event.mask.contains(EventMask::IGNORED);
+                } else if event.wd == self.dir_wd {
+                    pending = pending || event.name == Some(self.name.as_os_str());
+                }
+            }
+        }
+    }
+
+    fn poll(&mut self, path: &Path) -> Result<Option<Change>> {
+        if !self.pending()? {
+            return Ok(None);
+        }
+        let id = file_id(path)?;
+        let change = if id.is_none() {
+            if self.id.take().is_some() {
+                Some(Change::Removed)
+            } else {
+                None
+            }
+        } else if id != self.id {
+            let file = File::open(path).context(NAME, Operation::Open, None)?;
+            let wd = self.inotify.watches().add(path, FILE_EVENTS).context(
+                NAME,
+                Operation::Watch,
+                None,
+            )?;
+            if let Some(old) = self.file_wd.replace(wd.clone()) {
+                if old != wd {
+                    // The old file may already be gone, which removes its watch.
+                    let _ = self.inotify.watches().remove(old);
+                }
+            }
+            self.cache.replace_source(file)?;
+            self.cache.validate()?;
+            self.id = id;
+            Some(Change::Replaced)
+        } else if self.cache.validate()? {
+            Some(Change::Modified)
+        } else {
+            None
+        };
+        if let Some(change) = change {
+            self.subscribers
+                .retain(|subscriber| subscriber.send(change).is_ok());
+        }
+        Ok(change)
+    }
+}
+
+/// A cache over a file that reacts to changes made to the file on disk.
+///
+/// `WatchedCache` wraps any cache whose source is a `std::fs::File`, and
+/// subscribes to inotify events for the file and its parent directory.
+/// Pending events are processed whenever the cache is accessed through
+/// `Cache::traverse_chunks`, or when `WatchedCache::poll` or
+/// `Cache::validate` is called. When the file is modified in place, the
+/// cached data is invalidated. When the file is replaced, for example by
+/// an editor that saves by renaming a new file over the old one, the new
+/// file is opened and the cache is re-based onto it. Each change is
+/// delivered to every receiver returned by `WatchedCache::subscribe`.
+///
+/// The length, cache size and fingerprint of the cache are recorded after
+/// each change, so that they can be read without waiting for a traversal,
+/// including from within the closure passed to `Cache::traverse_chunks`.
+///
+/// `WatchedCache` implements `std::os::unix::io::AsRawFd`, yielding the
+/// inotify file descriptor, so that an event loop can wait for the
+/// descriptor to become readable and then call `WatchedCache::poll`.
+///
+/// This type is only available on Linux with the `watch` feature enabled.
+pub struct WatchedCache<C: Cache<Source = File>> {
+    path: PathBuf,
+    fd: RawFd,
+    watch: Mutex<Watch<C>>,
+    len: AtomicU64,
+    cache_size: AtomicUsize,
+    fingerprint: Mutex<Fingerprint>,
+}
+
+impl<C: Cache<Source = File>> WatchedCache<C> {
+    /// Opens the file at the passed path, creates a cache for it with the
+    /// passed closure, and starts watching the file for changes.
+    pub fn open<P: AsRef<Path>, F: FnOnce(File) -> Result<C>>(
+        path: P,
+        new_cache: F,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let name = match path.file_name() {
+            Some(name) => name.to_os_string(),
+            None => {
+                return Err(Error::from(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "watched path does not name a file",
+                )))
+            }
+        };
+        let dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let inotify = Inotify::init().context(NAME, Operation::Watch, None)?;
+        let dir_wd =
+            inotify
+                .watches()
+                .add(&dir, DIR_EVENTS)
+                .context(NAME, Operation::Watch, None)?;
+        let file_wd =
+            inotify
+                .watches()
+                .add(&path, FILE_EVENTS)
+                .context(NAME, Operation::Watch, None)?;
+        let file = File::open(&path).context(NAME, Operation::Open, None)?;
+        let mut cache = new_cache(file)?;
+        cache.validate()?;
+        let id = file_id(&path)?;
+        let fd = inotify.as_raw_fd();
+        Ok(WatchedCache {
+            path,
+            fd,
+            len: AtomicU64::new(cache.len()),
+            cache_size: AtomicUsize::new(cache.cache_size()),
+            fingerprint: Mutex::new(cache.fingerprint()),
+            watch: Mutex::new(Watch {
+                cache,
+                inotify,
+                file_wd: Some(file_wd),
+                dir_wd,
+                name,
+                id,
+                subscribers: Vec::new(),
+                buffer: vec![0; 4096],
+            }),
+        })
+    }
+
+    /// Returns the path of the watched file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns a receiver on which every subsequent change to the watched
+    /// file is delivered.
+    pub fn subscribe(&self) -> Result<Receiver<Change>> {
+        let (sender, receiver) = channel();
+        self.watch.lock()?.subscribers.push(sender);
+        Ok(receiver)
+    }
+
+    /// Processes pending file system events without blocking, updating the
+    /// cache accordingly. Returns the change that was applied to the cache,
+    /// if any.
+    pub fn poll(&self) -> Result<Option<Change>> {
+        let mut watch = self.watch.lock()?;
+        let change = watch.poll(&self.path);
+        self.record(&watch.cache);
+        change
+    }
+
+    /// Records the length, cache size and fingerprint of the cache, so that
+    /// they can be read without locking the cache.
+    fn record(&self, cache: &C) {
+        self.len.store(cache.len(), Ordering::Relaxed);
+        self.cache_size.store(cache.cache_size(), Ordering::Relaxed);
+        let fingerprint = cache.fingerprint();
+        match self.fingerprint.lock() {
+            Ok(mut recorded) => *recorded = fingerprint,
+            Err(poisoned) => *poisoned.into_inner() = fingerprint,
+        }
+    }
+}
+
+impl<C: Cache<Source = File>> AsRawFd for WatchedCache<C> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl<C: Cache<Source = File>> Cache for WatchedCache<C> {
+    type Source = File;
+
+    fn into_inner(self) -> Result<File> {
+        Mutex::into_inner(self.watch)?.cache.into_inner()
+    }
+
+    fn replace_source(&mut self, source: File) -> Result<File> {
+        let watch = self.watch.get_mut()?;
+        let replaced = watch.cache.replace_source(source);
+        self.len.store(watch.cache.len(), Ordering::Relaxed);
+        self.cache_size
+            .store(watch.cache.cache_size(), Ordering::Relaxed);
+        *self.fingerprint.get_mut()? = watch.cache.fingerprint();
+        replaced
+    }
+
+    fn len(&self) -> u64 {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    fn cache_size(&self) -> usize {
+        self.cache_size.load(Ordering::Relaxed)
+    }
+
+    fn fingerprint(&self) -> Fingerprint {
+        match self.fingerprint.lock() {
+            Ok(fingerprint) => *fingerprint,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+
+    fn validate(&mut self) -> Result<bool>
+    where
+        File: Fingerprinted,
+    {
+        let mut watch = self.watch.lock()?;
+        let change = watch.poll(&self.path);
+        self.record(&watch.cache);
+        Ok(change?.is_some())
+    }
+
+    fn traverse_chunks<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        let mut watch = self.watch.lock()?;
+        let change = watch.poll(&self.path);
+        self.record(&watch.cache);
+        change?;
+        watch.cache.traverse_chunks(range, f)
+    }
+
+    fn traverse_chunks_rev<R: RangeBounds<u64>, F: FnMut(&[u8]) -> Result<()>>(
+        &self,
+        range: R,
+        f: F,
+    ) -> Result<()> {
+        let mut watch = self.watch.lock()?;
+        let change = watch.poll(&self.path);
+        self.record(&watch.cache);
+        change?;
+        watch.cache.traverse_chunks_rev(range, f)
+    }
+}